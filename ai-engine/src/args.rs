@@ -1,13 +1,9 @@
-use tokenizers::Tokenizer;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Debug)]
-pub enum Prompt {
-    Interactive,
-    Chat,
-    One(String),
-}
+use tokenizers::Tokenizer;
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Which {
     L7b,
     L13b,
@@ -41,6 +37,35 @@ impl Which {
     }
 }
 
+impl FromStr for Which {
+    type Err = anyhow::Error;
+
+    /// Parses the names the models go by in dsh's config, as `7b-chat` or
+    /// `mistral-7b-instruct`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "7b" => Ok(Self::L7b),
+            "13b" => Ok(Self::L13b),
+            "70b" => Ok(Self::L70b),
+            "7b-chat" => Ok(Self::L7bChat),
+            "13b-chat" => Ok(Self::L13bChat),
+            "70b-chat" => Ok(Self::L70bChat),
+            "7b-code" => Ok(Self::L7bCode),
+            "13b-code" => Ok(Self::L13bCode),
+            "34b-code" => Ok(Self::L34bCode),
+            "mistral-7b" => Ok(Self::Mistral7b),
+            "mistral-7b-instruct" => Ok(Self::Mistral7bInstruct),
+            "rift-solver" => Ok(Self::RiftSolver),
+            other => Err(anyhow::anyhow!(
+                "unknown model `{}', expected one of 7b, 13b, 70b, 7b-chat, 13b-chat, \
+                 70b-chat, 7b-code, 13b-code, 34b-code, mistral-7b, mistral-7b-instruct \
+                 or rift-solver",
+                other
+            )),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Args {
   /// GGML file to load, typically a .bin file generated by the quantize command from llama.cpp
@@ -83,6 +108,27 @@ pub struct Args {
 
   /// Group-Query Attention, use 8 for the 70B version of LLaMAv2.
   pub gqa: Option<usize>,
+
+  /// The model OpenAI's chat completions are asked for.
+  pub openai_model: String,
+
+  /// Where the OpenAI-compatible API is, up to the `/chat/completions`.
+  pub openai_url: String,
+
+  /// The environment variable holding the API key. Without it, no key is sent.
+  pub openai_api_key_env: String,
+
+  /// Headers sent along with every request.
+  pub openai_headers: Vec<(String, String)>,
+
+  /// How long a whole answer may take.
+  pub openai_timeout: Option<Duration>,
+
+  /// How long connecting may take.
+  pub openai_connect_timeout: Option<Duration>,
+
+  /// A proxy for the requests, instead of the one in `HTTPS_PROXY` and the like.
+  pub openai_proxy: Option<String>,
 }
 
 impl Default for Args {
@@ -101,6 +147,13 @@ impl Default for Args {
           repeat_last_n: 64,
           gqa: None,
           which: Which::L7bChat,
+          openai_model: "gpt-3.5-turbo".to_string(),
+          openai_url: "https://api.openai.com/v1".to_string(),
+          openai_api_key_env: "OPENAI_API_KEY".to_string(),
+          openai_headers: Vec::new(),
+          openai_timeout: None,
+          openai_connect_timeout: None,
+          openai_proxy: None,
       }
   }
 }
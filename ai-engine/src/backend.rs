@@ -0,0 +1,21 @@
+use anyhow::Result;
+use futures_util::stream::LocalBoxStream;
+
+use crate::Args;
+
+/// An answer as the model produces it, a piece of text at a time.
+pub type TextStream<'a> = LocalBoxStream<'a, Result<String>>;
+
+/// A language model that answers prompts.
+pub trait Backend {
+    /// What the backend is called, as `openai`.
+    fn name(&self) -> &str;
+
+    /// Answers `prompt`. Nothing is asked until the stream is polled, and
+    /// dropping the stream stops the answer.
+    fn generate<'a>(&'a mut self, prompt: &'a str) -> TextStream<'a>;
+
+    /// Switches to the settings in `args`, from the next answer on. When
+    /// they don't work, the old ones are kept.
+    fn reconfigure(&mut self, args: &Args) -> Result<()>;
+}
@@ -1,25 +1,20 @@
 mod args;
-mod utils;
+mod backend;
+mod local;
+mod mock;
 mod openai;
+mod utils;
 
-use std::io::Write;
+pub use args::{Args, Which};
+pub use backend::{Backend, TextStream};
+pub use local::LocalBackend;
+pub use mock::MockBackend;
+pub use openai::OpenAiBackend;
 
-use anyhow::{Error, Ok, Result};
-use args::{Args, Prompt, Which};
+use anyhow::{anyhow, Result};
 use futures_util::StreamExt;
-use serde_json::json;
-use serde::Deserialize;
-use utils::{format_size, print_token};
-
-use candle_core::quantized::{ggml_file, gguf_file};
-use candle_transformers::models::quantized_llama::{self as model, ModelWeights};
 
-use candle_core::{Device, Tensor};
-use candle_transformers::generation::LogitsProcessor;
-
-use crate::openai::ChatCompletionChunk;
-
-const SETUPPROMT: &'static str = r#"
+const SETUPPROMT: &str = r#"
 You are expert in programming and solving programming errors. You are to give a suggestion
 to the best of your ability to solve the following error given the following
 context. 
@@ -27,271 +22,118 @@ context.
 Give code examples where useful:
 "#;
 
-pub struct AIEngine {
-    model: ModelWeights,
-    args: Args,
-}
-
-impl AIEngine {
-    pub fn default() -> Result<Self, Error> {
-        let model_args = Args::default();
-        return AIEngine::new(&model_args);
-    }
-
-    pub fn new(model_args: &Args) -> Result<Self, Error> {
-        let model_path = model_args.model()?;
-        let mut file = std::fs::File::open(&model_path).unwrap();
+const COMMAND_PROMPT: &str = r#"
+You turn requests into shell commands. Reply with a single command line for a
+POSIX shell that does what is asked, and nothing else: no explanation, no
+code fences. The request:
+"#;
 
-        let mut model = match model_path.extension().and_then(|v| v.to_str()) {
-            Some("gguf") => {
-                let model = gguf_file::Content::read(&mut file)?;
-                let mut total_size_in_bytes = 0;
-                for (_, tensor) in model.tensor_infos.iter() {
-                    let elem_count = tensor.shape.elem_count();
-                    total_size_in_bytes +=
-                        elem_count * tensor.ggml_dtype.type_size() / tensor.ggml_dtype.blck_size();
-                }
-                println!(
-                    "loaded {:?} tensors ({})",
-                    model.tensor_infos.len(),
-                    &format_size(total_size_in_bytes)
-                );
-                model::ModelWeights::from_gguf(model, &mut file)?
-            }
-            Some("ggml" | "bin") | Some(_) | None => {
-                let model = ggml_file::Content::read(&mut file)?;
-                let mut total_size_in_bytes = 0;
-                for (_, tensor) in model.tensors.iter() {
-                    let elem_count = tensor.shape().elem_count();
-                    total_size_in_bytes +=
-                        elem_count * tensor.dtype().type_size() / tensor.dtype().blck_size();
-                }
-                println!(
-                    "loaded {:?} tensors ({})",
-                    model.tensors.len(),
-                    &format_size(total_size_in_bytes)
-                );
-                println!("params: {:?}", model.hparams);
-                let default_gqa = match model_args.which {
-                    Which::L7b
-                    | Which::L13b
-                    | Which::L7bChat
-                    | Which::L13bChat
-                    | Which::L7bCode
-                    | Which::L13bCode
-                    | Which::L34bCode
-                    | Which::RiftSolver => 1,
-                    Which::Mistral7b | Which::Mistral7bInstruct | Which::L70b | Which::L70bChat => {
-                        8
-                    }
-                };
-                model::ModelWeights::from_ggml(model, model_args.gqa.unwrap_or(default_gqa))?
-            }
-        };
-        println!("model built");
-        return Ok(AIEngine {
-            model,
-            args: model_args.clone(),
-        });
+/// Asks `backend` how to fix the error described in `context`, handing
+/// each piece of the answer to `on_text` as it arrives, and returns the
+/// whole answer.
+pub async fn explain_error(
+    backend: &mut dyn Backend,
+    context: &str,
+    mut on_text: impl FnMut(&str),
+) -> Result<String> {
+    let prompt = format!("{} {}", SETUPPROMT, context);
+    let mut answer = String::new();
+    let mut pieces = backend.generate(&prompt);
+    while let Some(piece) = pieces.next().await {
+        let piece = piece?;
+        on_text(&piece);
+        answer.push_str(&piece);
     }
+    Ok(answer)
+}
 
-    pub async fn inference_openai(&mut self, prompt: &str) -> Result<(), Error> {
-        let final_prompt = format!("{} {}", SETUPPROMT, prompt);
-        let url = "https://api.openai.com/v1/chat/completions";
-        let api_key = std::env::var("OPENAI_API_KEY")?;
-
-        let body = json!({
-            "model": "gpt-3.5-turbo",
-            "messages": [{
-                "role": "user",
-                "content": final_prompt
-            }],
-            "stream": true
-        });
-        let client = reqwest::Client::new();
-        let res = client
-            .post(url)
-            .body(body.to_string())
-            .header("Content-Type", "application/json")
-            .bearer_auth(api_key)
-            .send()
-            .await?;
-        println!("status = {}", res.status());
-
-        println!("ChatGPT Says:");
-        println!();
-        let mut stream = res.bytes_stream();
-        while let Some(item) = stream.next().await {
-            let item = item?;
-            let s = match std::str::from_utf8(&item) {
-                std::result::Result::Ok(v) => v,
-                Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-            };
-
-            for p in s.split("\n\n") {
-                match p.strip_prefix("data: ") {
-                    Some(p) => {
-                        // Check if the stream is done...
-                        if p == "[DONE]" {
-                            break;
-                        }
-
-                        // Parse the json data...
-                        let d = serde_json::from_str::<ChatCompletionChunk>(p)
-                            .expect(format!("Couldn't parse: {}", p).as_str());
-
-                        // Is there data?
-                        let c = d.choices.get(0).expect("No choice returned");
-                        if let Some(content) = &c.delta.content {
-                            print!("{}", content);
-                        }
-
-                        // Flush stdout as it goes...
-                        if let Err(error) = std::io::stdout().flush() {
-                            panic!("{}", error);
-                        }
-                    }
-                    None => {}
-                }
-            }
+/// Asks `backend` for a shell command that does what `request` describes.
+/// The answer is only read up to the end of the first command line.
+pub async fn suggest_command(backend: &mut dyn Backend, request: &str) -> Result<String> {
+    let prompt = format!("{}{}", COMMAND_PROMPT, request);
+    let mut answer = String::new();
+    let mut pieces = backend.generate(&prompt);
+    while let Some(piece) = pieces.next().await {
+        answer.push_str(&piece?);
+        if command_finished(&answer) {
+            break;
         }
-        println!("");
-        println!("[Done.]");
-        Ok(())
     }
+    extract_command(&answer).ok_or_else(|| anyhow!("no command in the answer: {}", answer))
+}
 
-    pub fn inference(&mut self, input: &str) -> Result<()> {
-        self.args.prompt = Some(input.to_string());
-        let prompt = match self.args.prompt.as_deref() {
-            Some(s) => Prompt::One(SETUPPROMT.to_owned() + s),
-            None => Prompt::One("Say you are a smart model in the computer shell".to_string()),
-        };
-
-        let mut pre_prompt_tokens = vec![];
+/// Whether `answer` already holds a whole command line, so generating
+/// more is pointless.
+fn command_finished(answer: &str) -> bool {
+    match answer.rfind('\n') {
+        Some(end) => extract_command(&answer[..end]).is_some(),
+        None => false,
+    }
+}
 
-        let prompt_str = match &prompt {
-            Prompt::One(prompt) => prompt.clone(),
-            Prompt::Interactive => todo!(),
-            Prompt::Chat => todo!(),
-        };
-        let tokenizer = self.args.tokenizer().unwrap();
-        let tokens = tokenizer
-            .encode(prompt_str, true)
-            .map_err(anyhow::Error::msg)
-            .unwrap();
+/// Picks the command out of a model's answer: the first line that isn't
+/// blank or a code fence, without a `$ ` prompt or backticks around it.
+fn extract_command(answer: &str) -> Option<String> {
+    let line = answer
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("```"))?;
+    let line = line.strip_prefix("$ ").unwrap_or(line);
+    let line = line.trim_matches('`').trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
 
-        let prompt_tokens = [&pre_prompt_tokens, tokens.get_ids()].concat();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::task::noop_waker;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll};
+
+    /// Polls `future` until it's done. The backends here never wait on
+    /// anything for long, so there's no need for a runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
         loop {
-            let prompt_str = match &prompt {
-                Prompt::One(prompt) => prompt.clone(),
-                Prompt::Interactive | Prompt::Chat => {
-                    print!("> ");
-                    std::io::stdout().flush()?;
-                    let mut prompt = String::new();
-                    std::io::stdin().read_line(&mut prompt)?;
-                    if prompt.ends_with('\n') {
-                        prompt.pop();
-                        if prompt.ends_with('\r') {
-                            prompt.pop();
-                        }
-                    }
-                    if self.args.which.is_mistral() {
-                        format!("[INST] {prompt} [/INST]")
-                    } else {
-                        prompt
-                    }
-                }
-            };
-            print!("{}", &prompt_str);
-            let tokens = tokenizer
-                .encode(prompt_str, true)
-                .map_err(anyhow::Error::msg)?;
-            if self.args.verbose_prompt {
-                for (token, id) in tokens.get_tokens().iter().zip(tokens.get_ids().iter()) {
-                    let token = token.replace('▁', " ").replace("<0x0A>", "\n");
-                    println!("{id:7} -> '{token}'");
-                }
-            }
-
-            let prompt_tokens = [&pre_prompt_tokens, tokens.get_ids()].concat();
-            let to_sample = self.args.sample_len.saturating_sub(1);
-            let prompt_tokens = if prompt_tokens.len() + to_sample > model::MAX_SEQ_LEN - 10 {
-                let to_remove = prompt_tokens.len() + to_sample + 10 - model::MAX_SEQ_LEN;
-                prompt_tokens[prompt_tokens.len().saturating_sub(to_remove)..].to_vec()
-            } else {
-                prompt_tokens
-            };
-            let mut all_tokens = vec![];
-            let temperature = Some(self.args.temperature);
-            let mut logits_processor =
-                LogitsProcessor::new(self.args.seed, temperature, self.args.top_p);
-
-            let start_prompt_processing = std::time::Instant::now();
-            let mut next_token = {
-                let input = Tensor::new(prompt_tokens.as_slice(), &Device::Cpu)?.unsqueeze(0)?;
-                let logits = self.model.forward(&input, 0)?;
-                let logits = logits.squeeze(0)?;
-                logits_processor.sample(&logits)?
-            };
-            let prompt_dt = start_prompt_processing.elapsed();
-            all_tokens.push(next_token);
-            print_token(next_token, &tokenizer);
-
-            let eos_token = *tokenizer.get_vocab(true).get("</s>").unwrap();
-
-            let start_post_prompt = std::time::Instant::now();
-            for index in 0..to_sample {
-                let input = Tensor::new(&[next_token], &Device::Cpu)?.unsqueeze(0)?;
-                let logits = self.model.forward(&input, prompt_tokens.len() + index)?;
-                let logits = logits.squeeze(0)?;
-                let logits = if self.args.repeat_penalty == 1. {
-                    logits
-                } else {
-                    let start_at = all_tokens.len().saturating_sub(self.args.repeat_last_n);
-                    candle_transformers::utils::apply_repeat_penalty(
-                        &logits,
-                        self.args.repeat_penalty,
-                        &all_tokens[start_at..],
-                    )?
-                };
-                next_token = logits_processor.sample(&logits)?;
-                all_tokens.push(next_token);
-                print_token(next_token, &tokenizer);
-                if next_token == eos_token {
-                    break;
-                };
-            }
-            let dt = start_post_prompt.elapsed();
-            println!(
-                "\n\n{:4} prompt tokens processed: {:.2} token/s",
-                prompt_tokens.len(),
-                prompt_tokens.len() as f64 / prompt_dt.as_secs_f64(),
-            );
-            println!(
-                "{:4} tokens generated: {:.2} token/s",
-                to_sample,
-                to_sample as f64 / dt.as_secs_f64(),
-            );
-
-            match prompt {
-                Prompt::One(_) => break,
-                Prompt::Interactive => {}
-                Prompt::Chat => {
-                    pre_prompt_tokens = [prompt_tokens.as_slice(), all_tokens.as_slice()].concat()
-                }
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
             }
         }
-        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn extracts_the_command_from_an_answer() {
+        let answer = "```bash\n$ find . -name '*.rs' -size +1M\n```\n";
+        assert_eq!(
+            extract_command(answer).as_deref(),
+            Some("find . -name '*.rs' -size +1M")
+        );
+        assert_eq!(extract_command("`ls -la`").as_deref(), Some("ls -la"));
+        assert_eq!(extract_command("```\n"), None);
+        assert!(!command_finished("```sh\n"));
+        assert!(command_finished("```sh\ndu -sh *\n"));
+    }
+
+    #[test]
+    fn suggests_commands_from_a_streamed_answer() {
+        let mut backend = MockBackend::new("```sh\nls -la\n```\nThis lists everything.");
+        let command = block_on(suggest_command(&mut backend, "list all files"));
+        assert_eq!(command.unwrap(), "ls -la");
+
+        let mut pieces = Vec::new();
+        let answer = explain_error(&mut backend, "", |piece| pieces.push(piece.to_string()));
+        let answer = block_on(answer).unwrap();
+        assert_eq!(answer, pieces.concat());
+        assert_eq!(pieces[..2], ["```sh\n", "ls "]);
+    }
 
     #[test]
     fn simple_inference() {
-        let mut ai_engine = AIEngine::default().unwrap();
-        let _ = ai_engine.inference("write a add function in rust");
+        let mut backend = LocalBackend::new(Args::default());
+        let answer = explain_error(&mut backend, "write a add function in rust", |_| {});
+        let _ = block_on(answer);
     }
 }
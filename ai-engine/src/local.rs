@@ -0,0 +1,289 @@
+use std::task::Poll;
+
+use anyhow::{anyhow, Result};
+use futures_util::future;
+use futures_util::stream::{self, StreamExt};
+use tokenizers::Tokenizer;
+
+use candle_core::quantized::{ggml_file, gguf_file};
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::quantized_llama::{self as model, ModelWeights};
+
+use crate::backend::{Backend, TextStream};
+use crate::utils::{format_size, token_text};
+use crate::{Args, Which};
+
+/// A quantized llama model run on the CPU with candle. The weights are
+/// downloaded and loaded the first time it is asked something.
+pub struct LocalBackend {
+    args: Args,
+    model: Option<Model>,
+}
+
+/// The weights with the tokenizer that goes with them.
+struct Model {
+    weights: ModelWeights,
+    tokenizer: Tokenizer,
+}
+
+impl LocalBackend {
+    pub fn new(args: Args) -> Self {
+        LocalBackend { args, model: None }
+    }
+}
+
+impl Backend for LocalBackend {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn generate<'a>(&'a mut self, prompt: &'a str) -> TextStream<'a> {
+        let start = Step::Start(self, prompt);
+        stream::unfold(start, |step| async move {
+            // Sampling never waits on anything, so without this, whoever
+            // reads the stream wouldn't get to check for Ctrl+C until the
+            // answer is done.
+            yield_now().await;
+            let mut generation = match step {
+                Step::Start(backend, prompt) => {
+                    if backend.model.is_none() {
+                        match Model::load(backend.args.clone()).await {
+                            Ok(model) => backend.model = Some(model),
+                            Err(err) => return Some((Err(err), Step::Done)),
+                        }
+                    }
+                    match Generation::start(backend, prompt) {
+                        Ok(generation) => Box::new(generation),
+                        Err(err) => return Some((Err(err), Step::Done)),
+                    }
+                }
+                Step::Running(generation) => generation,
+                Step::Done => return None,
+            };
+            match generation.next()? {
+                Ok(piece) => Some((Ok(piece), Step::Running(generation))),
+                Err(err) => Some((Err(err), Step::Done)),
+            }
+        })
+        .boxed_local()
+    }
+
+    /// The weights are only loaded again when a different model is asked
+    /// for.
+    fn reconfigure(&mut self, args: &Args) -> Result<()> {
+        let same_model = args.model == self.args.model
+            && args.which == self.args.which
+            && args.gqa == self.args.gqa
+            && args.tokenizer == self.args.tokenizer;
+        if !same_model {
+            self.model = None;
+        }
+        self.args = args.clone();
+        Ok(())
+    }
+}
+
+/// Lets the executor run whatever else is waiting before carrying on.
+async fn yield_now() {
+    let mut yielded = false;
+    future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+impl Model {
+    /// Downloads what isn't there yet and loads it, on a thread of its own,
+    /// so that whoever reads the answer can still give up on it meanwhile.
+    async fn load(args: Args) -> Result<Model> {
+        tokio::task::spawn_blocking(move || {
+            Ok(Model {
+                weights: load(&args)?,
+                tokenizer: args.tokenizer()?,
+            })
+        })
+        .await?
+    }
+}
+
+fn load(args: &Args) -> Result<ModelWeights> {
+    let model_path = args.model()?;
+    let mut file = std::fs::File::open(&model_path)?;
+
+    let model = match model_path.extension().and_then(|v| v.to_str()) {
+        Some("gguf") => {
+            let model = gguf_file::Content::read(&mut file)?;
+            let mut total_size_in_bytes = 0;
+            for (_, tensor) in model.tensor_infos.iter() {
+                let elem_count = tensor.shape.elem_count();
+                total_size_in_bytes +=
+                    elem_count * tensor.ggml_dtype.type_size() / tensor.ggml_dtype.blck_size();
+            }
+            println!(
+                "loaded {:?} tensors ({})",
+                model.tensor_infos.len(),
+                &format_size(total_size_in_bytes)
+            );
+            model::ModelWeights::from_gguf(model, &mut file)?
+        }
+        Some("ggml" | "bin") | Some(_) | None => {
+            let model = ggml_file::Content::read(&mut file)?;
+            let mut total_size_in_bytes = 0;
+            for (_, tensor) in model.tensors.iter() {
+                let elem_count = tensor.shape().elem_count();
+                total_size_in_bytes +=
+                    elem_count * tensor.dtype().type_size() / tensor.dtype().blck_size();
+            }
+            println!(
+                "loaded {:?} tensors ({})",
+                model.tensors.len(),
+                &format_size(total_size_in_bytes)
+            );
+            println!("params: {:?}", model.hparams);
+            let default_gqa = match args.which {
+                Which::L7b
+                | Which::L13b
+                | Which::L7bChat
+                | Which::L13bChat
+                | Which::L7bCode
+                | Which::L13bCode
+                | Which::L34bCode
+                | Which::RiftSolver => 1,
+                Which::Mistral7b | Which::Mistral7bInstruct | Which::L70b | Which::L70bChat => 8,
+            };
+            model::ModelWeights::from_ggml(model, args.gqa.unwrap_or(default_gqa))?
+        }
+    };
+    println!("model built");
+    Ok(model)
+}
+
+/// Where `generate`'s stream is up to.
+enum Step<'a> {
+    Start(&'a mut LocalBackend, &'a str),
+    Running(Box<Generation<'a>>),
+    Done,
+}
+
+/// One answer being sampled from the model, a token at a time.
+struct Generation<'a> {
+    model: &'a mut ModelWeights,
+    args: &'a Args,
+    tokenizer: &'a Tokenizer,
+    logits_processor: LogitsProcessor,
+    eos_token: u32,
+    prompt_len: usize,
+    to_sample: usize,
+    sampled: usize,
+    all_tokens: Vec<u32>,
+    next_token: u32,
+    /// Whether `next_token` has yet to be handed out.
+    fresh: bool,
+}
+
+impl<'a> Generation<'a> {
+    /// Runs the loaded model over `prompt`, which gives the first token of
+    /// the answer.
+    fn start(backend: &'a mut LocalBackend, prompt: &str) -> Result<Self> {
+        let args = &backend.args;
+        let Model {
+            weights: model,
+            tokenizer,
+        } = backend.model.as_mut().expect("the model is loaded");
+
+        let prompt = if args.which.is_mistral() {
+            format!("[INST] {prompt} [/INST]")
+        } else {
+            prompt.to_string()
+        };
+
+        let tokens = tokenizer.encode(prompt, true).map_err(anyhow::Error::msg)?;
+        if args.verbose_prompt {
+            for (token, id) in tokens.get_tokens().iter().zip(tokens.get_ids().iter()) {
+                let token = token.replace('▁', " ").replace("<0x0A>", "\n");
+                println!("{id:7} -> '{token}'");
+            }
+        }
+
+        let prompt_tokens = tokens.get_ids().to_vec();
+        let to_sample = args.sample_len.saturating_sub(1);
+        let prompt_tokens = if prompt_tokens.len() + to_sample > model::MAX_SEQ_LEN - 10 {
+            let to_remove = prompt_tokens.len() + to_sample + 10 - model::MAX_SEQ_LEN;
+            prompt_tokens[prompt_tokens.len().saturating_sub(to_remove)..].to_vec()
+        } else {
+            prompt_tokens
+        };
+        let mut logits_processor =
+            LogitsProcessor::new(args.seed, Some(args.temperature), args.top_p);
+
+        let input = Tensor::new(prompt_tokens.as_slice(), &Device::Cpu)?.unsqueeze(0)?;
+        let logits = model.forward(&input, 0)?;
+        let next_token = logits_processor.sample(&logits.squeeze(0)?)?;
+
+        let eos_token = *tokenizer
+            .get_vocab(true)
+            .get("</s>")
+            .ok_or_else(|| anyhow!("the tokenizer has no </s> token"))?;
+        Ok(Generation {
+            model,
+            args,
+            tokenizer,
+            logits_processor,
+            eos_token,
+            prompt_len: prompt_tokens.len(),
+            to_sample,
+            sampled: 0,
+            all_tokens: vec![next_token],
+            next_token,
+            fresh: true,
+        })
+    }
+
+    /// The next piece of the answer, or `None` once the model is done or
+    /// has sampled as many tokens as it may.
+    fn next(&mut self) -> Option<Result<String>> {
+        loop {
+            if !std::mem::take(&mut self.fresh) {
+                if self.sampled >= self.to_sample {
+                    return None;
+                }
+                if let Err(err) = self.sample() {
+                    return Some(Err(err));
+                }
+            }
+            if self.next_token == self.eos_token {
+                return None;
+            }
+            if let Some(piece) = token_text(self.next_token, self.tokenizer) {
+                return Some(Ok(piece));
+            }
+        }
+    }
+
+    fn sample(&mut self) -> Result<()> {
+        let args = self.args;
+        let input = Tensor::new(&[self.next_token], &Device::Cpu)?.unsqueeze(0)?;
+        let logits = self.model.forward(&input, self.prompt_len + self.sampled)?;
+        let logits = logits.squeeze(0)?;
+        let logits = if args.repeat_penalty == 1. {
+            logits
+        } else {
+            let start_at = self.all_tokens.len().saturating_sub(args.repeat_last_n);
+            candle_transformers::utils::apply_repeat_penalty(
+                &logits,
+                args.repeat_penalty,
+                &self.all_tokens[start_at..],
+            )?
+        };
+        self.next_token = self.logits_processor.sample(&logits)?;
+        self.all_tokens.push(self.next_token);
+        self.sampled += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use futures_util::stream::{self, StreamExt};
+
+use crate::backend::{Backend, TextStream};
+use crate::Args;
+
+/// Gives the same reply to every prompt, a word at a time, for trying the
+/// shell out without a model and for tests.
+pub struct MockBackend {
+    reply: String,
+}
+
+impl MockBackend {
+    pub fn new(reply: impl Into<String>) -> Self {
+        MockBackend {
+            reply: reply.into(),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn generate<'a>(&'a mut self, _prompt: &'a str) -> TextStream<'a> {
+        let words = self.reply.split_inclusive(char::is_whitespace);
+        stream::iter(words.map(|word| Ok(word.to_string()))).boxed_local()
+    }
+
+    fn reconfigure(&mut self, _args: &Args) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,7 +1,15 @@
-use futures_util::StreamExt;
+use std::collections::VecDeque;
+use std::env;
+
+use anyhow::{anyhow, Result};
+use futures_util::stream::{self, LocalBoxStream, StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
 use serde::Deserialize;
 use serde_json::json;
-use std::io::Write;
+
+use crate::backend::{Backend, TextStream};
+use crate::Args;
 
 #[derive(Debug, Deserialize)]
 pub struct ChatChunkDelta {
@@ -11,15 +19,342 @@ pub struct ChatChunkDelta {
 #[derive(Debug, Deserialize)]
 pub struct ChatChunkChoice {
     pub delta: ChatChunkDelta,
-    pub index: usize,
-    pub finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionChunk {
-    pub id: String,
-    pub object: String,
-    pub created: usize,
-    pub model: String,
     pub choices: Vec<ChatChunkChoice>,
 }
+
+/// Chat completions, streamed, from OpenAI or any server with the same API,
+/// as llama.cpp's, vLLM or Ollama. The key is read from its environment
+/// variable with every request.
+pub struct OpenAiBackend {
+    /// The chat completions endpoint.
+    url: String,
+    model: String,
+    api_key_env: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiBackend {
+    pub fn new(args: &Args) -> Result<Self> {
+        Ok(OpenAiBackend {
+            url: endpoint(&args.openai_url),
+            model: args.openai_model.clone(),
+            api_key_env: args.openai_api_key_env.clone(),
+            client: client(args)?,
+        })
+    }
+
+    async fn request(&self, prompt: &str) -> Result<reqwest::Response> {
+        let api_key = env::var(&self.api_key_env)
+            .ok()
+            .filter(|key| !key.is_empty());
+        let body = json!({
+            "model": self.model,
+            "messages": [{
+                "role": "user",
+                "content": prompt
+            }],
+            "stream": true
+        });
+        let mut request = self
+            .client
+            .post(&self.url)
+            .body(body.to_string())
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(api_key) = &api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let hint = match status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN if api_key.is_none() => {
+                    format!(" (${} is not set)", self.api_key_env)
+                }
+                _ => String::new(),
+            };
+            return Err(anyhow!(
+                "request failed with {}{}: {}",
+                status,
+                hint,
+                response.text().await?
+            ));
+        }
+        Ok(response)
+    }
+}
+
+/// The chat completions endpoint under the API at `base`.
+fn endpoint(base: &str) -> String {
+    format!("{}/chat/completions", base.trim_end_matches('/'))
+}
+
+fn client(args: &Args) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &args.openai_headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("invalid header name `{}'", name))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| anyhow!("invalid value for the {} header", name))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(timeout) = args.openai_timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(timeout) = args.openai_connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(proxy) = &args.openai_proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|err| anyhow!("invalid proxy `{}': {}", proxy, err))?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build()?)
+}
+
+impl Backend for OpenAiBackend {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn generate<'a>(&'a mut self, prompt: &'a str) -> TextStream<'a> {
+        stream::once(self.request(prompt))
+            .map_ok(|response| Events::new(response).into_stream())
+            .try_flatten()
+            .boxed_local()
+    }
+
+    fn reconfigure(&mut self, args: &Args) -> Result<()> {
+        *self = OpenAiBackend::new(args)?;
+        Ok(())
+    }
+}
+
+/// The text in a response's server-sent events. Events end with a blank
+/// line, or with the body for the last one, and may be split anywhere
+/// across chunks. Lines end with `\n` or `\r\n`.
+struct Events {
+    body: LocalBoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    pieces: VecDeque<Result<String>>,
+    done: bool,
+}
+
+impl Events {
+    fn new(response: reqwest::Response) -> Self {
+        let body = response.bytes_stream().map_ok(|chunk| chunk.to_vec());
+        Events {
+            body: body.boxed_local(),
+            buffer: Vec::new(),
+            pieces: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn into_stream(self) -> TextStream<'static> {
+        stream::unfold(self, |mut events| async move {
+            let piece = events.next().await?;
+            Some((piece, events))
+        })
+        .boxed_local()
+    }
+
+    async fn next(&mut self) -> Option<Result<String>> {
+        loop {
+            if let Some(piece) = self.pieces.pop_front() {
+                return Some(piece);
+            }
+            if self.done {
+                return None;
+            }
+            match self.body.next().await {
+                Some(Ok(chunk)) => {
+                    self.buffer.extend_from_slice(&chunk);
+                    self.split();
+                }
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Some(Err(err.into()));
+                }
+                None => {
+                    self.done = true;
+                    if !self.buffer.is_empty() {
+                        self.buffer.extend_from_slice(b"\n\n");
+                        self.split();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the events that have fully arrived out of the buffer.
+    fn split(&mut self) {
+        // Drops the `\r` of every `\r\n`. One at the end stays until the next
+        // chunk says whether a `\n` follows.
+        let mut bytes = self.buffer.iter().copied().peekable();
+        let mut normalized = Vec::with_capacity(self.buffer.len());
+        while let Some(byte) = bytes.next() {
+            if byte != b'\r' || bytes.peek() != Some(&b'\n') {
+                normalized.push(byte);
+            }
+        }
+        self.buffer = normalized;
+
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            for data in event.lines().filter_map(|line| line.strip_prefix("data: ")) {
+                // Check if the stream is done...
+                if data == "[DONE]" {
+                    self.done = true;
+                    return;
+                }
+
+                match serde_json::from_str::<ChatCompletionChunk>(data) {
+                    Ok(chunk) => {
+                        let choice = chunk.choices.into_iter().next();
+                        if let Some(content) = choice.and_then(|c| c.delta.content) {
+                            self.pieces.push_back(Ok(content));
+                        }
+                    }
+                    Err(err) => {
+                        let err = anyhow!("couldn't parse {}: {}", data, err);
+                        self.pieces.push_back(Err(err));
+                        self.done = true;
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Starts a server that answers one request with `response`. Its
+    /// address comes back with a handle for the request it got.
+    fn serve_once(response: String) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (address, server)
+    }
+
+    fn event(content: &str) -> String {
+        let chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "llama3",
+            "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": null}]
+        });
+        format!("data: {}\n\n", chunk)
+    }
+
+    #[tokio::test]
+    async fn streams_answers_from_a_configured_server() {
+        let (address, server) = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n{}{}data: [DONE]\n\n",
+            event("Hello"),
+            event(" there"),
+        ));
+        env::set_var("DSH_TEST_OPENAI_KEY", "test-key");
+        let args = Args {
+            openai_url: format!("{}/v1/", address),
+            openai_model: "llama3".to_string(),
+            openai_api_key_env: "DSH_TEST_OPENAI_KEY".to_string(),
+            openai_headers: vec![("X-Team".to_string(), "shell".to_string())],
+            ..Args::default()
+        };
+        let mut backend = OpenAiBackend::new(&args).unwrap();
+        let pieces: Vec<String> = backend.generate("hi").try_collect().await.unwrap();
+        assert_eq!(pieces, ["Hello", " there"]);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions HTTP/1.1\r\n"));
+        assert!(request.contains("authorization: Bearer test-key\r\n"));
+        assert!(request.contains("x-team: shell\r\n"));
+        assert!(request.contains(r#""model":"llama3""#));
+
+        // Lines that end with `\r\n`, and a last event the body ends, with
+        // nothing in it but the text.
+        let (address, server) = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n{}{}",
+            event("Hello").replace('\n', "\r\n"),
+            r#"data: {"choices":[{"delta":{"content":" again"}}]}"#,
+        ));
+        let args = Args {
+            openai_url: format!("{}/v1/", address),
+            ..args
+        };
+        let mut backend = OpenAiBackend::new(&args).unwrap();
+        let pieces: Vec<String> = backend.generate("hi").try_collect().await.unwrap();
+        assert_eq!(pieces, ["Hello", " again"]);
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn goes_through_the_proxy_and_reports_refusals() {
+        let (address, server) = serve_once(
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 7\r\nConnection: close\r\n\r\nno key!"
+                .to_string(),
+        );
+        let args = Args {
+            openai_url: "http://models.internal/v1".to_string(),
+            openai_api_key_env: "DSH_TEST_UNSET_KEY".to_string(),
+            openai_proxy: Some(address),
+            ..Args::default()
+        };
+        let mut backend = OpenAiBackend::new(&args).unwrap();
+        let err = backend
+            .generate("hi")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "request failed with 401 Unauthorized ($DSH_TEST_UNSET_KEY is not set): no key!"
+        );
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST http://models.internal/v1/chat/completions HTTP/1.1\r\n"));
+        assert!(!request.contains("authorization:"));
+
+        let args = Args {
+            openai_headers: vec![("bad header".to_string(), "x".to_string())],
+            ..Args::default()
+        };
+        assert!(OpenAiBackend::new(&args).is_err());
+    }
+}
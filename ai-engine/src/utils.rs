@@ -27,27 +27,21 @@ pub fn device(cpu: bool) -> Result<Device> {
 }
 
 
-pub fn print_token(next_token: u32, tokenizer: &Tokenizer) {
+/// The text of one sampled token.
+pub fn token_text(next_token: u32, tokenizer: &Tokenizer) -> Option<String> {
   // Extracting the last token as a string is complicated, here we just apply some simple
   // heuristics as it seems to work well enough for this example. See the following for more
   // details:
   // https://github.com/huggingface/tokenizers/issues/1141#issuecomment-1562644141
-  if let Some(text) = tokenizer.id_to_token(next_token) {
-      let text = text.replace('▁', " ");
-      let ascii = text
-          .strip_prefix("<0x")
-          .and_then(|t| t.strip_suffix('>'))
-          .and_then(|t| u8::from_str_radix(t, 16).ok());
-      match ascii {
-          None => print!("{text}"),
-          Some(ascii) => {
-              if let Some(chr) = char::from_u32(ascii as u32) {
-                  if chr.is_ascii() {
-                      print!("{chr}")
-                  }
-              }
-          }
-      }
-      let _ = std::io::Write::flush(&mut std::io::stdout());
+  let text = tokenizer.id_to_token(next_token)?.replace('▁', " ");
+  let ascii = text
+      .strip_prefix("<0x")
+      .and_then(|t| t.strip_suffix('>'))
+      .and_then(|t| u8::from_str_radix(t, 16).ok());
+  match ascii {
+      None => Some(text),
+      Some(ascii) => char::from_u32(ascii as u32)
+          .filter(|chr| chr.is_ascii())
+          .map(String::from),
   }
-}
\ No newline at end of file
+}
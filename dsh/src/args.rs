@@ -0,0 +1,179 @@
+//! The command line dsh itself is started with.
+
+use std::io;
+
+use anyhow::{anyhow, Result};
+use crossterm::tty::IsTty;
+
+use crate::internals::options::Options;
+
+pub const USAGE: &str =
+    "dsh [-eilsux] [-o option] [--login] [--norc] [-c command [name [arg ...]] | file [arg ...]]";
+
+/// Where the commands to run come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Standard input, which is the interactive prompt when it's a terminal.
+    Stdin,
+    /// The string after `-c`.
+    Command(String),
+    /// A script file, given by name as the first argument.
+    Script(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub source: Source,
+    /// Asked for with `-i`.
+    pub interactive: bool,
+    /// Asked for with `-l` or `--login`, or started with a name beginning
+    /// with `-`, the way `login` starts shells.
+    pub login: bool,
+    /// Asked for with `--norc`: skip `~/.dsh_profile` and `~/.dshrc`.
+    pub norc: bool,
+    pub options: Options,
+    /// `$0`: the script's name, the name after a `-c` command, or dsh's own.
+    pub arg0: String,
+    /// `$1`, `$2`, ...
+    pub positional: Vec<String>,
+}
+
+impl Args {
+    /// Parses `dsh`'s arguments, `args[0]` being the name it was run as.
+    pub fn parse(args: Vec<String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        let arg0 = args.next().unwrap_or_else(|| "dsh".to_string());
+        let mut parsed = Args {
+            source: Source::Stdin,
+            interactive: false,
+            login: arg0.starts_with('-'),
+            norc: false,
+            options: Options::default(),
+            arg0,
+            positional: Vec::new(),
+        };
+
+        let mut command = false;
+        let mut stdin = false;
+        let mut rest: Vec<String> = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--" => break,
+                "--login" => {
+                    parsed.login = true;
+                    continue;
+                }
+                "--norc" => {
+                    parsed.norc = true;
+                    continue;
+                }
+                long if long.starts_with("--") => {
+                    return Err(anyhow!("{}: invalid option", long));
+                }
+                _ => {}
+            }
+            let (on, flags) = match arg.split_at_checked(1) {
+                Some(("-", flags)) if !flags.is_empty() => (true, flags),
+                Some(("+", flags)) if !flags.is_empty() => (false, flags),
+                _ => {
+                    rest.push(arg);
+                    break;
+                }
+            };
+            for flag in flags.chars() {
+                match flag {
+                    'c' if on => command = true,
+                    'i' if on => parsed.interactive = true,
+                    'l' if on => parsed.login = true,
+                    's' if on => stdin = true,
+                    'o' => {
+                        let name = args
+                            .next()
+                            .ok_or_else(|| anyhow!("-o: option requires an argument"))?;
+                        parsed.options.set(&name, on)?;
+                    }
+                    flag => parsed.options.set_flag(flag, on).map_err(|_| {
+                        let sign = if on { '-' } else { '+' };
+                        anyhow!("{}{}: invalid option", sign, flag)
+                    })?,
+                }
+            }
+        }
+        rest.extend(args);
+
+        let mut rest = rest.into_iter();
+        if command {
+            let command = rest
+                .next()
+                .ok_or_else(|| anyhow!("-c: option requires an argument"))?;
+            parsed.source = Source::Command(command);
+            if let Some(name) = rest.next() {
+                parsed.arg0 = name;
+            }
+        } else if !stdin {
+            if let Some(script) = rest.next() {
+                parsed.arg0 = script.clone();
+                parsed.source = Source::Script(script);
+            }
+        }
+        parsed.positional = rest.collect();
+        Ok(parsed)
+    }
+
+    /// Whether to prompt and keep a history: when asked to with `-i`, or
+    /// when commands come from standard input and a person is there to
+    /// type them.
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+            || (self.source == Source::Stdin && io::stdin().is_tty() && io::stderr().is_tty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_commands_scripts_and_flags() {
+        let args = parse(&[
+            "dsh",
+            "-ex",
+            "-o",
+            "pipefail",
+            "-c",
+            "echo $0 $1",
+            "me",
+            "a",
+        ])
+        .unwrap();
+        assert_eq!(args.source, Source::Command("echo $0 $1".to_string()));
+        assert_eq!(
+            (args.arg0.as_str(), args.positional),
+            ("me", vec!["a".to_string()])
+        );
+        assert!(args.options.errexit && args.options.xtrace && args.options.pipefail);
+
+        let args = parse(&["-dsh", "+x", "script.sh", "-i", "b"]).unwrap();
+        assert_eq!(args.source, Source::Script("script.sh".to_string()));
+        assert_eq!(args.arg0, "script.sh");
+        assert_eq!(args.positional, vec!["-i", "b"]);
+        assert!(args.login && !args.interactive);
+
+        let args = parse(&["dsh", "--norc", "-s", "--", "x"]).unwrap();
+        assert!(args.norc);
+        assert_eq!(
+            (args.source, args.positional),
+            (Source::Stdin, vec!["x".to_string()])
+        );
+
+        assert!(parse(&["dsh", "-c"]).is_err());
+        assert!(parse(&["dsh", "-q"]).is_err());
+        let err = parse(&["dsh", "--bogus"]).unwrap_err();
+        assert_eq!(err.to_string(), "--bogus: invalid option");
+        assert!(parse(&["dsh", "-o", "nope"]).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+use crate::internals::variables;
+
+pub struct Alias;
+
+impl Builtin for Alias {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "alias [-p] [name[=value] ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Define name as an alias for value, or show the alias for name. \
+         Without names, or with -p, list every alias in a form that can be \
+         read back in."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let names = match args.get(1).map(String::as_str) {
+            Some("-p") => &args[2..],
+            _ => &args[1..],
+        };
+        if names.is_empty() {
+            for (name, value) in &state.aliases {
+                println!("alias {}={}", name, variables::quote(value));
+            }
+            return ExitStatus::SUCCESS;
+        }
+
+        let mut status = ExitStatus::SUCCESS;
+        for arg in names {
+            match arg.split_once('=') {
+                Some((name, _)) if !is_alias_name(name) => {
+                    report!("alias: `{}': invalid alias name", name);
+                    status = ExitStatus::FAILURE;
+                }
+                Some((name, value)) => {
+                    state.aliases.insert(name.to_string(), value.to_string());
+                }
+                None => match state.aliases.get(arg) {
+                    Some(value) => println!("alias {}={}", arg, variables::quote(value)),
+                    None => {
+                        report!("alias: {}: not found", arg);
+                        status = ExitStatus::FAILURE;
+                    }
+                },
+            }
+        }
+        status
+    }
+}
+
+pub struct Unalias;
+
+impl Builtin for Unalias {
+    fn name(&self) -> &'static str {
+        "unalias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unalias [-a] name [name ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove each name from the aliases, or remove every alias with -a."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        match args.get(1).map(String::as_str) {
+            Some("-a") => {
+                state.aliases.clear();
+                return ExitStatus::SUCCESS;
+            }
+            Some(_) => {}
+            None => {
+                report!("unalias: usage: {}", self.usage());
+                return ExitStatus::Exited(2);
+            }
+        }
+
+        let mut status = ExitStatus::SUCCESS;
+        for name in &args[1..] {
+            if state.aliases.remove(name).is_none() {
+                report!("unalias: {}: not found", name);
+                status = ExitStatus::FAILURE;
+            }
+        }
+        status
+    }
+}
+
+/// Whether `name` can be an alias: a word with nothing the parser would
+/// treat specially.
+fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "/$`=|&;()<>'\"\\".contains(c))
+}
@@ -0,0 +1,42 @@
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct True;
+pub struct False;
+
+impl Builtin for True {
+    fn name(&self) -> &'static str {
+        "true"
+    }
+
+    fn usage(&self) -> &'static str {
+        "true"
+    }
+
+    fn description(&self) -> &'static str {
+        "Do nothing, successfully."
+    }
+
+    fn run(&self, _args: &[String], _state: &mut ShellState) -> ExitStatus {
+        ExitStatus::SUCCESS
+    }
+}
+
+impl Builtin for False {
+    fn name(&self) -> &'static str {
+        "false"
+    }
+
+    fn usage(&self) -> &'static str {
+        "false"
+    }
+
+    fn description(&self) -> &'static str {
+        "Do nothing, unsuccessfully."
+    }
+
+    fn run(&self, _args: &[String], _state: &mut ShellState) -> ExitStatus {
+        ExitStatus::FAILURE
+    }
+}
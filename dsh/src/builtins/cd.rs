@@ -1,23 +1,174 @@
 use std::env;
-use anyhow::Error;
-
-pub fn run(args: &str) ->Result<(), Error> {
-  let args_split: Vec<&str> = args.split_whitespace().collect();
-  let args_len = args_split.len();
-  
-  if args_len > 2 {
-    println!("dsh: cd: too many arguments");
-  }
- 
-  if (args_split.len() < 2) || (args_split[1].is_empty()) || (args_split[1] == "~") {
-    let home = std::env::var("HOME")?;
-    if let Err(err) = env::set_current_dir(home) {
-      println!("lsh: {}", err);
-    }
-  } else {
-    if let Err(err) = env::set_current_dir(args_split[1]) {
-        println!("lsh: {}", err);
-    }
-  }
-  Ok(())
-}
\ No newline at end of file
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Cd;
+
+impl Builtin for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cd [-L|-P] [dir]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change the working directory: to $HOME without a dir, back to $OLDPWD \
+         for `-`, and through $CDPATH for relative names that don't start with \
+         `.` or `..`. -L, the default, follows `..` in the path as written; -P \
+         resolves symlinks first."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        match cd(args, state) {
+            Ok(()) => ExitStatus::SUCCESS,
+            Err(err) => {
+                report!("cd: {}", err);
+                ExitStatus::FAILURE
+            }
+        }
+    }
+}
+
+fn cd(args: &[String], state: &mut ShellState) -> Result<(), Error> {
+    let mut physical = false;
+    let mut operands = &args[1..];
+    while let Some(arg) = operands.first() {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            "--" => {
+                operands = &operands[1..];
+                break;
+            }
+            arg if arg.starts_with('-') && arg != "-" => {
+                return Err(anyhow!("usage: {}", Cd.usage()))
+            }
+            _ => break,
+        }
+        operands = &operands[1..];
+    }
+
+    let (dir, print) = match operands {
+        [] => (non_empty(state, "HOME")?, false),
+        [dir] if dir == "-" => (non_empty(state, "OLDPWD")?, true),
+        [dir] => (dir.clone(), false),
+        _ => return Err(anyhow!("too many arguments")),
+    };
+    if dir.is_empty() {
+        return Ok(());
+    }
+
+    let (target, found_in_cdpath) = match search_cdpath(&dir, state) {
+        Some(found) => found,
+        None => (PathBuf::from(&dir), false),
+    };
+    let new = change_dir(&target, physical, state).map_err(|err| anyhow!("{}: {}", dir, err))?;
+    if print || found_in_cdpath {
+        println!("{}", new.display());
+    }
+    Ok(())
+}
+
+/// Changes to `target`, relative to `$PWD`, and updates `$PWD` and
+/// `$OLDPWD`. Returns the new working directory.
+pub fn change_dir(target: &Path, physical: bool, state: &mut ShellState) -> Result<PathBuf, Error> {
+    let old = logical_cwd(state);
+    let path = if physical {
+        target.to_path_buf()
+    } else {
+        normalize(&old.join(target))
+    };
+
+    env::set_current_dir(&path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => anyhow!("No such file or directory"),
+        std::io::ErrorKind::PermissionDenied => anyhow!("Permission denied"),
+        _ if !path.is_dir() => anyhow!("Not a directory"),
+        _ => Error::from(err),
+    })?;
+
+    let new = if physical { env::current_dir()? } else { path };
+    state
+        .variables
+        .export("OLDPWD", Some(&old.to_string_lossy()))?;
+    state
+        .variables
+        .export("PWD", Some(&new.to_string_lossy()))?;
+    Ok(new)
+}
+
+/// The working directory as the user reached it, symlinks and all:
+/// `$PWD` if it still names the current directory, otherwise the physical
+/// path.
+pub fn logical_cwd(state: &ShellState) -> PathBuf {
+    let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    match state.variables.get("PWD").map(Path::new) {
+        Some(pwd) if pwd.is_absolute() && same_file(pwd, &physical) => pwd.to_path_buf(),
+        _ => physical,
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Looks `dir` up in the directories of `$CDPATH`, unless it is absolute or
+/// starts with `.` or `..`. An empty entry stands for the current
+/// directory. Also returns whether the directory was found through a
+/// non-empty entry, which `cd` reports by printing where it went.
+fn search_cdpath(dir: &str, state: &ShellState) -> Option<(PathBuf, bool)> {
+    let first = Path::new(dir).components().next()?;
+    if !matches!(first, Component::Normal(_)) {
+        return None;
+    }
+    let cdpath = state.variables.get("CDPATH")?;
+    cdpath.split(':').find_map(|entry| {
+        let candidate = Path::new(if entry.is_empty() { "." } else { entry }).join(dir);
+        candidate.is_dir().then_some((candidate, !entry.is_empty()))
+    })
+}
+
+/// Drops `.` components and resolves `..` by removing the component before
+/// it, as `cd -L` does.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn non_empty(state: &ShellState, name: &str) -> Result<String, Error> {
+    match state.variables.get(name) {
+        Some(value) if !value.is_empty() => Ok(value.to_string()),
+        _ => Err(anyhow!("{} not set", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_logical_paths() {
+        assert_eq!(normalize(Path::new("/a/b/../c/./d")), Path::new("/a/c/d"));
+        assert_eq!(normalize(Path::new("/a/../..")), Path::new("/"));
+        assert_eq!(normalize(Path::new("/link/..")), Path::new("/"));
+    }
+}
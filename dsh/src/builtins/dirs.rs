@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::{cd, Builtin};
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Dirs;
+pub struct Pushd;
+pub struct Popd;
+
+impl Builtin for Dirs {
+    fn name(&self) -> &'static str {
+        "dirs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "dirs [-c] [-l] [-p] [-v]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the directory stack, the current directory first. -l spells out \
+         home as a full path rather than ~, -p puts each entry on its own line \
+         and -v numbers them. -c empties the stack."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        dirs(args, state)
+    }
+}
+
+impl Builtin for Pushd {
+    fn name(&self) -> &'static str {
+        "pushd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pushd [-n] [+n | -n | dir]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change to dir and put the directory it left on the stack. Without dir, \
+         swap the top two entries; +n and -n rotate the stack so that entry n, \
+         counted from the top or the bottom, is on top. -n as the first \
+         argument only changes the stack, not the directory."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        finish("pushd", args, state, push)
+    }
+}
+
+impl Builtin for Popd {
+    fn name(&self) -> &'static str {
+        "popd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "popd [-n] [+n | -n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove the top of the directory stack and change to the next entry. \
+         +n and -n remove entry n, counted from the top or the bottom, instead; \
+         -n as the first argument leaves the directory alone."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        finish("popd", args, state, pop)
+    }
+}
+
+fn dirs(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let mut long = false;
+    let mut per_line = false;
+    let mut numbered = false;
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "-c" => {
+                state.dir_stack.clear();
+                return ExitStatus::SUCCESS;
+            }
+            "-l" => long = true,
+            "-p" => per_line = true,
+            "-v" => numbered = true,
+            _ => {
+                report!("dirs: usage: {}", Dirs.usage());
+                return ExitStatus::Exited(2);
+            }
+        }
+    }
+
+    let home = state.variables.get("HOME").filter(|home| !home.is_empty());
+    let entries: Vec<String> = stack(state)
+        .iter()
+        .map(|dir| match home {
+            Some(home) if !long => abbreviate(dir, home),
+            _ => dir.display().to_string(),
+        })
+        .collect();
+    if numbered {
+        for (index, entry) in entries.iter().enumerate() {
+            println!("{:>2}  {}", index, entry);
+        }
+    } else if per_line {
+        for entry in &entries {
+            println!("{}", entry);
+        }
+    } else {
+        println!("{}", entries.join(" "));
+    }
+    ExitStatus::SUCCESS
+}
+
+/// Runs `pushd` or `popd` and shows the stack afterwards, as `dirs` does.
+fn finish(
+    name: &str,
+    args: &[String],
+    state: &mut ShellState,
+    op: fn(&[String], bool, &mut ShellState) -> Result<(), Error>,
+) -> ExitStatus {
+    let (stay, args) = match args.get(1).map(String::as_str) {
+        Some("-n") => (true, &args[2..]),
+        _ => (false, &args[1..]),
+    };
+    match op(args, stay, state) {
+        Ok(()) => dirs(&[String::from("dirs")], state),
+        Err(err) => {
+            report!("{}: {}", name, err);
+            ExitStatus::FAILURE
+        }
+    }
+}
+
+fn push(args: &[String], stay: bool, state: &mut ShellState) -> Result<(), Error> {
+    let mut full = stack(state);
+    match args {
+        [] => {
+            if full.len() < 2 {
+                return Err(anyhow!("no other directory"));
+            }
+            full.swap(0, 1);
+            go_to(full, stay, state)
+        }
+        [arg] => match position(arg, full.len())? {
+            Some(index) => {
+                full.rotate_left(index);
+                go_to(full, stay, state)
+            }
+            None if stay => {
+                state.dir_stack.insert(0, PathBuf::from(arg));
+                Ok(())
+            }
+            None => {
+                let old = full.remove(0);
+                cd::change_dir(Path::new(arg), false, state)
+                    .map_err(|err| anyhow!("{}: {}", arg, err))?;
+                state.dir_stack.insert(0, old);
+                Ok(())
+            }
+        },
+        _ => Err(anyhow!("too many arguments")),
+    }
+}
+
+fn pop(args: &[String], stay: bool, state: &mut ShellState) -> Result<(), Error> {
+    let mut full = stack(state);
+    if full.len() < 2 {
+        return Err(anyhow!("directory stack empty"));
+    }
+    let index = match args {
+        [] => 0,
+        [arg] => position(arg, full.len())?.ok_or_else(|| anyhow!("{}: invalid argument", arg))?,
+        _ => return Err(anyhow!("too many arguments")),
+    };
+
+    if index > 0 || stay {
+        // Leaving the current directory where it is: with `-n`, `popd`
+        // drops the entry below it.
+        full.remove(index.max(1));
+        state.dir_stack = full.split_off(1);
+        return Ok(());
+    }
+    full.remove(0);
+    go_to(full, false, state)
+}
+
+/// Makes `full[0]` the current directory and the rest the stack.
+fn go_to(mut full: Vec<PathBuf>, stay: bool, state: &mut ShellState) -> Result<(), Error> {
+    let rest = full.split_off(1);
+    if !stay {
+        cd::change_dir(&full[0], false, state)
+            .map_err(|err| anyhow!("{}: {}", full[0].display(), err))?;
+    }
+    state.dir_stack = rest;
+    Ok(())
+}
+
+/// The whole stack, starting with the current directory.
+fn stack(state: &ShellState) -> Vec<PathBuf> {
+    let mut full = vec![cd::logical_cwd(state)];
+    full.extend(state.dir_stack.iter().cloned());
+    full
+}
+
+/// Resolves `+n` and `-n` to an index into a stack of `len` entries, or
+/// `None` if `arg` is a directory name instead.
+fn position(arg: &str, len: usize) -> Result<Option<usize>, Error> {
+    let (from_top, digits) = match arg.chars().next() {
+        Some('+') => (true, &arg[1..]),
+        Some('-') => (false, &arg[1..]),
+        _ => return Ok(None),
+    };
+    let Ok(n) = digits.parse::<usize>() else {
+        return Ok(None);
+    };
+    if n >= len {
+        return Err(anyhow!("{}: directory stack index out of range", arg));
+    }
+    Ok(Some(if from_top { n } else { len - 1 - n }))
+}
+
+/// Shows `dir` with `home` at its start as `~`.
+fn abbreviate(dir: &Path, home: &str) -> String {
+    match dir.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => dir.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_stack_positions() {
+        assert_eq!(position("+0", 3).unwrap(), Some(0));
+        assert_eq!(position("-0", 3).unwrap(), Some(2));
+        assert_eq!(position("+2", 3).unwrap(), Some(2));
+        assert!(position("+3", 3).is_err());
+        assert_eq!(position("src", 3).unwrap(), None);
+        assert_eq!(position("-", 3).unwrap(), None);
+        assert_eq!(abbreviate(Path::new("/home/me/src"), "/home/me"), "~/src");
+        assert_eq!(abbreviate(Path::new("/home/meh"), "/home/me"), "/home/meh");
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Echo;
+
+impl Builtin for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn usage(&self) -> &'static str {
+        "echo [-neE] [arg ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Write the arguments, separated by spaces, followed by a newline. -n \
+         leaves out the newline. -e interprets backslash escapes such as \\n, \
+         \\t, \\0nnn and \\xHH, and \\c stops the output there; -E, the \
+         default, doesn't."
+    }
+
+    fn run(&self, args: &[String], _state: &mut ShellState) -> ExitStatus {
+        let mut newline = true;
+        let mut escapes = false;
+        let mut words = &args[1..];
+        // Only arguments made up entirely of known flags are options, so
+        // `echo -x` prints `-x`.
+        while let Some(flags) = words.first().and_then(|word| word.strip_prefix('-')) {
+            if flags.is_empty() || !flags.chars().all(|c| "neE".contains(c)) {
+                break;
+            }
+            for flag in flags.chars() {
+                match flag {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
+                }
+            }
+            words = &words[1..];
+        }
+
+        let mut out = Vec::new();
+        for (index, word) in words.iter().enumerate() {
+            if index > 0 {
+                out.push(b' ');
+            }
+            if escapes {
+                if !unescape(word, &mut out) {
+                    newline = false;
+                    break;
+                }
+            } else {
+                out.extend_from_slice(word.as_bytes());
+            }
+        }
+        if newline {
+            out.push(b'\n');
+        }
+
+        let mut stdout = io::stdout().lock();
+        match stdout.write_all(&out).and_then(|()| stdout.flush()) {
+            Ok(()) => ExitStatus::SUCCESS,
+            Err(err) => {
+                report!("echo: write error: {}", err);
+                ExitStatus::FAILURE
+            }
+        }
+    }
+}
+
+/// Appends `word` to `out` with its backslash escapes interpreted. Returns
+/// false at a `\c`, which ends the output.
+fn unescape(word: &str, out: &mut Vec<u8>) -> bool {
+    let bytes = word.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 == bytes.len() {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        i += 2;
+        let escaped = match bytes[i - 1] {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'c' => return false,
+            b'e' | b'E' => 0x1b,
+            b'f' => 0x0c,
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'v' => 0x0b,
+            b'\\' => b'\\',
+            b'0' => number(bytes, &mut i, 8, 3),
+            b'x' if bytes.get(i).is_some_and(u8::is_ascii_hexdigit) => number(bytes, &mut i, 16, 2),
+            other => {
+                out.push(b'\\');
+                other
+            }
+        };
+        out.push(escaped);
+    }
+    true
+}
+
+/// Reads up to `max` digits in `radix` from `bytes[*i..]`.
+fn number(bytes: &[u8], i: &mut usize, radix: u32, max: usize) -> u8 {
+    let mut value: u32 = 0;
+    for _ in 0..max {
+        match bytes.get(*i).and_then(|&b| (b as char).to_digit(radix)) {
+            Some(digit) => value = value * radix + digit,
+            None => break,
+        }
+        *i += 1;
+    }
+    value as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interprets_escapes() {
+        let mut out = Vec::new();
+        assert!(unescape(r"a\tb\\n\x41\0101\q", &mut out));
+        assert_eq!(out, b"a\tb\\nAA\\q");
+
+        let mut out = Vec::new();
+        assert!(!unescape(r"stop\chere", &mut out));
+        assert_eq!(out, b"stop");
+    }
+}
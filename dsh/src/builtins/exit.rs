@@ -0,0 +1,68 @@
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Exit;
+pub struct Logout;
+
+impl Builtin for Exit {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "exit [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Exit the shell with status n, or with the status of the last command. \
+         The rest of the command line is skipped and the line is still saved \
+         to the history."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        exit(args, state)
+    }
+}
+
+impl Builtin for Logout {
+    fn name(&self) -> &'static str {
+        "logout"
+    }
+
+    fn usage(&self) -> &'static str {
+        "logout [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Exit a login shell, the same way exit does."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        if !state.login {
+            report!("logout: not login shell: use `exit'");
+            return ExitStatus::FAILURE;
+        }
+        exit(args, state)
+    }
+}
+
+fn exit(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let code = match args.get(1..) {
+        Some([]) | None => state.last_status.code(),
+        Some([code]) => match code.parse::<i64>() {
+            // Only the low byte makes it to the parent, as with `_exit`.
+            Ok(code) => (code & 0xff) as i32,
+            Err(_) => {
+                report!("{}: {}: numeric argument required", args[0], code);
+                2
+            }
+        },
+        Some(_) => {
+            report!("{}: too many arguments", args[0]);
+            return ExitStatus::FAILURE;
+        }
+    };
+    state.exiting = Some(code);
+    ExitStatus::Exited(code)
+}
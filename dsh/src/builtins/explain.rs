@@ -0,0 +1,44 @@
+use super::{block_on, Builtin};
+use crate::internals::diagnosis::{self, AiPolicy};
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Explain;
+
+impl Builtin for Explain {
+    fn name(&self) -> &'static str {
+        "explain"
+    }
+
+    fn usage(&self) -> &'static str {
+        "explain [question]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Ask the AI engine about the last command that failed. Extra words are \
+         passed along as a follow-up question. `?` is short for explain."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let engine = match &mut state.engine {
+            Some(engine) if state.ai_policy != AiPolicy::Never => engine,
+            _ => {
+                report!("{}: AI assistance is turned off", args[0]);
+                return ExitStatus::FAILURE;
+            }
+        };
+
+        let Some(failure) = &state.last_failure else {
+            report!("{}: no failed command to explain", args[0]);
+            return ExitStatus::FAILURE;
+        };
+
+        let mut prompt = failure.prompt();
+        if args.len() > 1 {
+            prompt.push_str(&format!("\nThe user also asks: {}\n", args[1..].join(" ")));
+        }
+
+        block_on(diagnosis::diagnose(engine.as_mut(), &prompt));
+        ExitStatus::SUCCESS
+    }
+}
@@ -0,0 +1,86 @@
+use super::Builtin;
+use crate::internals::shell::{Flow, ShellState};
+use crate::internals::status::ExitStatus;
+
+pub struct Local;
+
+impl Builtin for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn usage(&self) -> &'static str {
+        "local name[=value] ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Make each name a variable of the running function, set to value if \
+         one is given. What the variable was before comes back when the \
+         function returns. Only works inside a function."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        if args.len() < 2 {
+            report!("local: usage: {}", self.usage());
+            return ExitStatus::Exited(2);
+        }
+
+        let mut status = ExitStatus::SUCCESS;
+        for arg in &args[1..] {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            let made = state.variables.make_local(name).and_then(|()| match value {
+                Some(value) => state.variables.set(name, value),
+                None => Ok(()),
+            });
+            if let Err(err) = made {
+                report!("local: {}", err);
+                status = ExitStatus::FAILURE;
+            }
+        }
+        status
+    }
+}
+
+pub struct Return;
+
+impl Builtin for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn usage(&self) -> &'static str {
+        "return [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Return from a function or a sourced file with status n, or with the \
+         status of the last command."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        if state.call_depth == 0 {
+            report!("return: can only `return' from a function or sourced script");
+            return ExitStatus::FAILURE;
+        }
+        let status = match args.get(1..) {
+            Some([]) | None => state.last_status,
+            Some([code]) => match code.parse::<i64>() {
+                Ok(code) => ExitStatus::Exited((code & 0xff) as i32),
+                Err(_) => {
+                    report!("return: {}: numeric argument required", code);
+                    ExitStatus::Exited(2)
+                }
+            },
+            Some(_) => {
+                report!("return: too many arguments");
+                return ExitStatus::FAILURE;
+            }
+        };
+        state.flow = Some(Flow::Return);
+        state.last_status = status;
+        status
+    }
+}
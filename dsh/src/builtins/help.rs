@@ -0,0 +1,97 @@
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+/// Where `help` wraps descriptions.
+const WIDTH: usize = 76;
+
+pub struct Help;
+
+impl Builtin for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help [builtin ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Describe the given builtins, or list them all with their usage."
+    }
+
+    fn run(&self, args: &[String], _state: &mut ShellState) -> ExitStatus {
+        if args.len() == 1 {
+            print!("{}", list());
+            return ExitStatus::SUCCESS;
+        }
+
+        let mut status = ExitStatus::SUCCESS;
+        for name in &args[1..] {
+            match super::lookup(name) {
+                Some(builtin) => print!("{}", describe(builtin)),
+                None => {
+                    report!("help: no help topics match `{}'", name);
+                    status = ExitStatus::FAILURE;
+                }
+            }
+        }
+        status
+    }
+}
+
+fn list() -> String {
+    let mut text = String::from(
+        "These commands are built into dsh. Type `help name' to find out more \
+         about one.\n\n",
+    );
+    for builtin in super::all() {
+        text.push_str(&format!(" {}\n", builtin.usage()));
+    }
+    text
+}
+
+fn describe(builtin: &dyn Builtin) -> String {
+    let mut text = format!("{}: {}\n", builtin.name(), builtin.usage());
+    for line in wrap(builtin.description(), WIDTH - 4) {
+        text.push_str(&format!("    {}\n", line));
+    }
+    text
+}
+
+/// Breaks `text` into lines of at most `width` characters, between words.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::{all, lookup};
+
+    #[test]
+    fn documents_every_builtin() {
+        let listing = list();
+        for builtin in all() {
+            assert!(listing.contains(builtin.usage()));
+            assert!(describe(builtin).starts_with(&format!("{}: ", builtin.name())));
+        }
+        assert_eq!(lookup("?").unwrap().name(), "explain");
+        assert!(lookup("ls").is_none());
+        assert_eq!(wrap("a bb ccc dd", 6), vec!["a bb", "ccc dd"]);
+    }
+}
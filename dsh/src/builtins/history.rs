@@ -0,0 +1,92 @@
+use super::Builtin;
+use crate::internals::shell::{HistoryEntry, ShellState};
+use crate::internals::status::ExitStatus;
+
+pub struct History;
+
+impl Builtin for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "history [-c] [-v] [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the last n commands, or all of them. -v adds when and where each \
+         ran, how long it took and its exit status. -c clears the history."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        history(args, state)
+    }
+}
+
+fn history(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let mut verbose = false;
+    let mut count = None;
+
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "-c" => {
+                return match state.history.clear() {
+                    Ok(()) => ExitStatus::SUCCESS,
+                    Err(err) => {
+                        report!("history: {}", err);
+                        ExitStatus::FAILURE
+                    }
+                };
+            }
+            "-v" => verbose = true,
+            arg => match arg.parse::<usize>() {
+                Ok(n) if count.is_none() => count = Some(n),
+                _ => {
+                    report!("history: usage: {}", History.usage());
+                    return ExitStatus::Exited(2);
+                }
+            },
+        }
+    }
+
+    let history = &state.history;
+    let total = history.entries().len();
+    let first = total - count.unwrap_or(total).min(total);
+    for (index, entry) in history.entries().iter().enumerate().skip(first) {
+        let number = history.number(index);
+        if verbose {
+            println!("{:>5}  {}  {}", number, details(entry), entry.command);
+        } else {
+            println!("{:>5}  {}", number, entry.command);
+        }
+    }
+    ExitStatus::SUCCESS
+}
+
+fn details(entry: &HistoryEntry) -> String {
+    format!(
+        "{}  {:>7.2}s  {:>3}  {}",
+        local_time(entry.timestamp),
+        entry.duration.as_secs_f64(),
+        entry.status,
+        entry.cwd
+    )
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM:SS` in local time.
+fn local_time(timestamp: u64) -> String {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return timestamp.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Error, Result};
+
+use super::{block_on, Builtin};
+use crate::internals::commands::Outcome;
+use crate::internals::exec;
+use crate::internals::jobs::{self, JobState, Pid};
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Jobs;
+pub struct Fg;
+pub struct Bg;
+pub struct Wait;
+pub struct Kill;
+
+impl Builtin for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "jobs [-p]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List background and stopped jobs, or only their pids with -p."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        jobs(args, state)
+    }
+}
+
+impl Builtin for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "fg [%job]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Bring a job, the current one by default, to the foreground and wait \
+         for it like any other command, so a failure can still be diagnosed."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let outcome = block_on(fg(args, state));
+        block_on(exec::record_outcome(outcome, state))
+    }
+}
+
+impl Builtin for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "bg [%job ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Continue stopped jobs, the current one by default, in the background."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        bg(args, state)
+    }
+}
+
+impl Builtin for Wait {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    fn usage(&self) -> &'static str {
+        "wait [%job | pid ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Wait for the given jobs, or for every running job, and return the \
+         status of the last one."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        wait(args, state)
+    }
+}
+
+impl Builtin for Kill {
+    fn name(&self) -> &'static str {
+        "kill"
+    }
+
+    fn usage(&self) -> &'static str {
+        "kill [-s sigspec | -sigspec] pid | %job ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Send a signal, SIGTERM by default, to processes or whole jobs. kill -l \
+         lists the signal names."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        kill(args, state)
+    }
+}
+
+fn jobs(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let pids_only = match args.get(1).map(String::as_str) {
+        None => false,
+        Some("-p") => true,
+        Some(_) => {
+            report!("jobs: usage: {}", Jobs.usage());
+            return ExitStatus::Exited(2);
+        }
+    };
+
+    state.jobs.reap();
+    for index in 0..state.jobs.len() {
+        if pids_only {
+            if let Some(pid) = state.jobs.get(index).last_pid() {
+                println!("{}", pid);
+            }
+        } else {
+            println!("{}", state.jobs.describe(index));
+        }
+    }
+    // Finished jobs are reported once, here or at the next prompt.
+    state.jobs.forget_finished();
+    ExitStatus::SUCCESS
+}
+
+async fn fg(args: &[String], state: &mut ShellState) -> Result<Outcome, Error> {
+    if args.len() > 2 {
+        return Err(anyhow!("fg: too many arguments"));
+    }
+    state.jobs.reap();
+    let index = state
+        .jobs
+        .find(args.get(1).map(String::as_str))
+        .map_err(|err| anyhow!("fg: {}", err))?;
+
+    let job = state.jobs.remove(index);
+    println!("{}", job.command());
+    state.jobs.foreground(job, true).await
+}
+
+fn bg(args: &[String], state: &mut ShellState) -> ExitStatus {
+    state.jobs.reap();
+    let specs: Vec<Option<&str>> = match args.len() {
+        1 => vec![None],
+        _ => args[1..].iter().map(|spec| Some(spec.as_str())).collect(),
+    };
+
+    let mut status = ExitStatus::SUCCESS;
+    for spec in specs {
+        let resumed = state.jobs.find(spec).and_then(|index| {
+            state.jobs.resume_in_background(index)?;
+            Ok(state.jobs.len() - 1)
+        });
+        match resumed {
+            Ok(index) => {
+                let job = state.jobs.get(index);
+                println!("[{}] {} &", job.id(), job.command());
+            }
+            Err(err) => {
+                report!("bg: {}", err);
+                status = ExitStatus::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+fn wait(args: &[String], state: &mut ShellState) -> ExitStatus {
+    state.jobs.reap();
+
+    if args.len() == 1 {
+        // A job that stops instead of finishing stays in the table, but is
+        // no longer running, so this ends.
+        while let Some(index) =
+            (0..state.jobs.len()).find(|&index| state.jobs.get(index).state() == JobState::Running)
+        {
+            state.jobs.wait(index);
+        }
+        return ExitStatus::SUCCESS;
+    }
+
+    let mut status = ExitStatus::SUCCESS;
+    for target in &args[1..] {
+        let index = if target.starts_with('%') {
+            state.jobs.find(Some(target))
+        } else {
+            match target.parse::<Pid>() {
+                Ok(pid) => state
+                    .jobs
+                    .find_pid(pid)
+                    .ok_or_else(|| anyhow!("pid {} is not a child of this shell", pid)),
+                Err(_) => Err(anyhow!("`{}': not a pid or valid job spec", target)),
+            }
+        };
+        status = match index {
+            Ok(index) => state.jobs.wait(index),
+            Err(err) => {
+                report!("wait: {}", err);
+                ExitStatus::NOT_FOUND
+            }
+        };
+    }
+    status
+}
+
+fn kill(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let mut rest = &args[1..];
+    let mut signal = libc::SIGTERM;
+
+    match rest.first().map(String::as_str) {
+        Some("-l") => {
+            let names: Vec<&str> = jobs::SIGNALS.iter().map(|(name, _)| *name).collect();
+            println!("{}", names.join(" "));
+            return ExitStatus::SUCCESS;
+        }
+        Some("-s") | Some("-n") => {
+            let Some(spec) = rest.get(1) else {
+                report!("kill: {}: option requires an argument", rest[0]);
+                return ExitStatus::Exited(2);
+            };
+            match jobs::signal_number(spec) {
+                Some(number) => signal = number,
+                None => return invalid_signal(spec),
+            }
+            rest = &rest[2..];
+        }
+        Some("--") => rest = &rest[1..],
+        Some(option) if option.len() > 1 && option.starts_with('-') => {
+            match jobs::signal_number(&option[1..]) {
+                Some(number) => signal = number,
+                None => return invalid_signal(&option[1..]),
+            }
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+
+    if rest.is_empty() {
+        report!("kill: usage: {}", Kill.usage());
+        return ExitStatus::Exited(2);
+    }
+
+    state.jobs.reap();
+    let mut status = ExitStatus::SUCCESS;
+    for target in rest {
+        if let Err(err) = kill_one(target, signal, state) {
+            report!("kill: {}", err);
+            status = ExitStatus::FAILURE;
+        }
+    }
+    status
+}
+
+fn kill_one(target: &str, signal: libc::c_int, state: &ShellState) -> Result<(), Error> {
+    if target.starts_with('%') {
+        let index = state.jobs.find(Some(target))?;
+        return state
+            .jobs
+            .kill(index, signal)
+            .map_err(|err| anyhow!("{}: {}", target, err));
+    }
+
+    let pid = target
+        .parse::<Pid>()
+        .map_err(|_| anyhow!("{}: arguments must be process or job IDs", target))?;
+    if unsafe { libc::kill(pid, signal) } < 0 {
+        return Err(anyhow!("{}: {}", target, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+fn invalid_signal(spec: &str) -> ExitStatus {
+    report!("kill: {}: invalid signal specification", spec);
+    ExitStatus::FAILURE
+}
@@ -0,0 +1,77 @@
+use super::Builtin;
+use crate::internals::shell::{Flow, ShellState};
+use crate::internals::status::ExitStatus;
+
+pub struct Break;
+
+impl Builtin for Break {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    fn usage(&self) -> &'static str {
+        "break [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Leave the innermost for, while or until loop, or the n innermost \
+         ones."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        leave(args, state, Flow::Break)
+    }
+}
+
+pub struct Continue;
+
+impl Builtin for Continue {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    fn usage(&self) -> &'static str {
+        "continue [n]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Skip the rest of the innermost for, while or until loop's body and \
+         start its next round, or the next round of the nth loop out."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        leave(args, state, Flow::Continue)
+    }
+}
+
+/// Sets `state.flow` to leave `n` loops, `n` being `args[1]` or 1. Asking
+/// for more loops than there are leaves all of them.
+fn leave(args: &[String], state: &mut ShellState, flow: fn(usize) -> Flow) -> ExitStatus {
+    let count = match args.get(1..) {
+        Some([]) | None => 1,
+        Some([count]) => match count.parse::<usize>() {
+            Ok(0) => {
+                report!("{}: {}: loop count out of range", args[0], count);
+                return ExitStatus::FAILURE;
+            }
+            Ok(count) => count,
+            Err(_) => {
+                report!("{}: {}: numeric argument required", args[0], count);
+                return ExitStatus::Exited(2);
+            }
+        },
+        Some(_) => {
+            report!("{}: too many arguments", args[0]);
+            return ExitStatus::FAILURE;
+        }
+    };
+    if state.loop_depth == 0 {
+        report!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            args[0]
+        );
+        return ExitStatus::SUCCESS;
+    }
+    state.flow = Some(flow(count.min(state.loop_depth)));
+    ExitStatus::SUCCESS
+}
@@ -1 +1,103 @@
-pub mod cd;
\ No newline at end of file
+use std::future::Future;
+
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub mod alias;
+pub mod boolean;
+pub mod cd;
+pub mod dirs;
+pub mod echo;
+pub mod exit;
+pub mod explain;
+pub mod function;
+pub mod help;
+pub mod history;
+pub mod jobs;
+pub mod loops;
+pub mod pwd;
+pub mod reload;
+pub mod set;
+pub mod source;
+pub mod test;
+pub mod variables;
+pub mod which;
+
+/// A command the shell runs itself. Builtins run inside the shell process,
+/// in pipelines too, with the shell's standard streams pointed wherever the
+/// command's pipes and redirections say for as long as they run.
+pub trait Builtin: Sync {
+    fn name(&self) -> &'static str;
+
+    /// The synopsis, as in `cd [-L|-P] [dir]`.
+    fn usage(&self) -> &'static str;
+
+    /// What `help` says about it.
+    fn description(&self) -> &'static str;
+
+    /// Runs the builtin; `args[0]` is the name it was called by.
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus;
+}
+
+/// Every builtin, in the order `help` lists them.
+static BUILTINS: &[&dyn Builtin] = &[
+    &alias::Alias,
+    &jobs::Bg,
+    &loops::Break,
+    &cd::Cd,
+    &loops::Continue,
+    &dirs::Dirs,
+    &echo::Echo,
+    &exit::Exit,
+    &explain::Explain,
+    &variables::Export,
+    &boolean::False,
+    &jobs::Fg,
+    &help::Help,
+    &history::History,
+    &jobs::Jobs,
+    &jobs::Kill,
+    &function::Local,
+    &exit::Logout,
+    &dirs::Popd,
+    &dirs::Pushd,
+    &pwd::Pwd,
+    &reload::Reload,
+    &function::Return,
+    &set::Set,
+    &source::Source,
+    &test::Test,
+    &test::Bracket,
+    &boolean::True,
+    &which::Type,
+    &alias::Unalias,
+    &variables::Unset,
+    &jobs::Wait,
+    &which::Which,
+];
+
+/// The builtin called `name`, if there is one.
+pub fn lookup(name: &str) -> Option<&'static dyn Builtin> {
+    // `?` is short for `explain`, `.` for `source`, and `:` for `true`, as
+    // in `while :; do ...; done`.
+    let name = match name {
+        "?" => "explain",
+        "." => "source",
+        ":" => "true",
+        name => name,
+    };
+    BUILTINS
+        .iter()
+        .copied()
+        .find(|builtin| builtin.name() == name)
+}
+
+pub fn all() -> impl Iterator<Item = &'static dyn Builtin> {
+    BUILTINS.iter().copied()
+}
+
+/// Waits for `future` from inside a builtin, which runs synchronously in
+/// the middle of the shell's async main loop.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
+}
@@ -0,0 +1,50 @@
+use std::env;
+
+use super::{cd, Builtin};
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Pwd;
+
+impl Builtin for Pwd {
+    fn name(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pwd [-L|-P]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Print the working directory. -L, the default, shows it the way cd \
+         reached it, symlinks and all; -P resolves the symlinks."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let mut physical = false;
+        for arg in &args[1..] {
+            match arg.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                _ => {
+                    report!("pwd: usage: {}", self.usage());
+                    return ExitStatus::Exited(2);
+                }
+            }
+        }
+
+        let dir = if physical {
+            match env::current_dir() {
+                Ok(dir) => dir,
+                Err(err) => {
+                    report!("pwd: {}", err);
+                    return ExitStatus::FAILURE;
+                }
+            }
+        } else {
+            cd::logical_cwd(state)
+        };
+        println!("{}", dir.display());
+        ExitStatus::SUCCESS
+    }
+}
@@ -0,0 +1,42 @@
+use super::Builtin;
+use crate::config::Config;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Reload;
+
+impl Builtin for Reload {
+    fn name(&self) -> &'static str {
+        "reload"
+    }
+
+    fn usage(&self) -> &'static str {
+        "reload"
+    }
+
+    fn description(&self) -> &'static str {
+        "Read ~/.config/dsh/config.toml again and switch to its settings. \
+         If it has a mistake, nothing changes. A different local model is \
+         loaded when it is next asked something."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        if args.len() > 1 {
+            report!("reload: usage: {}", self.usage());
+            return ExitStatus::Exited(2);
+        }
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(err) => {
+                report!("reload: {}", err);
+                return ExitStatus::FAILURE;
+            }
+        };
+
+        if let Err(err) = state.configure(config) {
+            report!("reload: {}", err);
+            return ExitStatus::FAILURE;
+        }
+        ExitStatus::SUCCESS
+    }
+}
@@ -0,0 +1,92 @@
+use super::Builtin;
+use crate::internals::options::NAMES;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+use crate::internals::variables;
+
+pub struct Set;
+
+impl Builtin for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "set [-eux] [+eux] [-o option] [+o option] [--] [arg ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Turn shell options on with -, or off with +: -e (errexit) exits when \
+         a command fails unchecked, -u (nounset) makes expanding an unset \
+         variable an error, -x (xtrace) prints each command before it runs, \
+         and -o pipefail makes a pipeline fail when any of its commands does. \
+         -o or +o alone lists the options. Any args, or none after --, become \
+         the positional parameters. Without arguments, list every variable."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        if args.len() == 1 {
+            for name in state.variables.names() {
+                let value = state.variables.get(name).unwrap_or_default();
+                println!("{}={}", name, variables::quote_if_needed(value));
+            }
+            return ExitStatus::SUCCESS;
+        }
+
+        let mut rest = args[1..].iter().peekable();
+        let mut positional = None;
+        while let Some(arg) = rest.next() {
+            let (on, flags) = match arg.split_at_checked(1) {
+                // `set -` only ends the options, while `set --` also
+                // clears the positional parameters when nothing follows.
+                _ if arg == "--" || (arg == "-" && rest.peek().is_some()) => {
+                    positional = Some(rest.by_ref().cloned().collect());
+                    break;
+                }
+                Some(("-", flags)) => (true, flags),
+                Some(("+", flags)) => (false, flags),
+                _ => {
+                    let args = std::iter::once(arg).chain(rest.by_ref());
+                    positional = Some(args.cloned().collect());
+                    break;
+                }
+            };
+            for flag in flags.chars() {
+                let set = match flag {
+                    'o' => match rest.next() {
+                        Some(name) => state.options.set(name, on),
+                        None => {
+                            list(state, on);
+                            continue;
+                        }
+                    },
+                    flag => state.options.set_flag(flag, on),
+                };
+                if let Err(err) = set {
+                    report!("set: {}", err);
+                    report!("set: usage: {}", self.usage());
+                    return ExitStatus::Exited(2);
+                }
+            }
+        }
+
+        if let Some(positional) = positional {
+            state.variables.set_positional(positional);
+        }
+        ExitStatus::SUCCESS
+    }
+}
+
+/// `set -o` lists the options for people, `set +o` as commands that set
+/// them back the way they are.
+fn list(state: &ShellState, readable: bool) {
+    for (name, _) in NAMES {
+        let on = state.options.get(name).unwrap_or_default();
+        match (readable, on) {
+            (true, true) => println!("{:<15} on", name),
+            (true, false) => println!("{:<15} off", name),
+            (false, true) => println!("set -o {}", name),
+            (false, false) => println!("set +o {}", name),
+        }
+    }
+}
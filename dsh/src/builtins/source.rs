@@ -0,0 +1,76 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use super::{block_on, Builtin};
+use crate::internals::exec;
+use crate::internals::redirect;
+use crate::internals::shell::{Flow, ShellState};
+use crate::internals::status::ExitStatus;
+
+pub struct Source;
+
+impl Builtin for Source {
+    fn name(&self) -> &'static str {
+        "source"
+    }
+
+    fn usage(&self) -> &'static str {
+        "source file [arg ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Read and run the commands in file in the current shell, so that \
+         variables, aliases and the working directory it sets stay set. A \
+         file without a slash in its name is looked for on $PATH, then in the \
+         current directory. Any args become the positional parameters while \
+         it runs, and `return` stops it early. `.` is the same as source."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let Some(file) = args.get(1) else {
+            report!("{}: usage: {}", args[0], self.usage());
+            return ExitStatus::Exited(2);
+        };
+        let source = match fs::read_to_string(find(file, state)) {
+            Ok(source) => source,
+            Err(err) => {
+                report!("{}: {}", args[0], redirect::file_error(file, err));
+                return ExitStatus::FAILURE;
+            }
+        };
+
+        let saved = (args.len() > 2).then(|| state.variables.set_positional(args[2..].to_vec()));
+        let status = block_on(run_sourced(&source, file, state));
+        if let Some(saved) = saved {
+            state.variables.set_positional(saved);
+        }
+        status
+    }
+}
+
+/// Runs `source`, read from the file `name`, in the current shell, where
+/// `return` stops it.
+pub async fn run_sourced(source: &str, name: &str, state: &mut ShellState) -> ExitStatus {
+    state.call_depth += 1;
+    let status = exec::run_script(source, name, state).await;
+    state.call_depth -= 1;
+    // A `break` or `continue` carries on out to a loop around `.`.
+    if state.flow == Some(Flow::Return) {
+        state.flow = None;
+    }
+    status
+}
+
+fn find(file: &str, state: &ShellState) -> PathBuf {
+    if !file.contains('/') {
+        let path = state.variables.get("PATH").unwrap_or_default();
+        let found = env::split_paths(path)
+            .map(|dir| dir.join(file))
+            .find(|candidate| candidate.is_file());
+        if let Some(found) = found {
+            return found;
+        }
+    }
+    PathBuf::from(file)
+}
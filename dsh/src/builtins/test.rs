@@ -0,0 +1,306 @@
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Test;
+pub struct Bracket;
+
+impl Builtin for Test {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn usage(&self) -> &'static str {
+        "test [expr]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Succeed if expr is true. Files: -e exists, -f regular file, -d \
+         directory, -L or -h symlink, -p fifo, -S socket, -b and -c devices, \
+         -r -w -x accessible, -s not empty, -u -g -k setuid, setgid and \
+         sticky, -t fd a terminal, and a -nt b, a -ot b, a -ef b. Strings: -n \
+         and -z for non-empty and empty, = or ==, != and < > to compare, and \
+         a string alone for non-empty. Integers: -eq -ne -lt -le -gt -ge. \
+         Combine with ! expr, expr -a expr, expr -o expr and ( expr )."
+    }
+
+    fn run(&self, args: &[String], _state: &mut ShellState) -> ExitStatus {
+        test("test", &args[1..])
+    }
+}
+
+impl Builtin for Bracket {
+    fn name(&self) -> &'static str {
+        "["
+    }
+
+    fn usage(&self) -> &'static str {
+        "[ [expr] ]"
+    }
+
+    fn description(&self) -> &'static str {
+        "The same as test, with a closing ] as the last argument."
+    }
+
+    fn run(&self, args: &[String], _state: &mut ShellState) -> ExitStatus {
+        match args[1..].split_last() {
+            Some((last, expr)) if last == "]" => test("[", expr),
+            _ => {
+                report!("[: missing `]'");
+                ExitStatus::Exited(2)
+            }
+        }
+    }
+}
+
+fn test(name: &str, args: &[String]) -> ExitStatus {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match evaluate(&args) {
+        Ok(true) => ExitStatus::SUCCESS,
+        Ok(false) => ExitStatus::FAILURE,
+        Err(err) => {
+            report!("{}: {}", name, err);
+            ExitStatus::Exited(2)
+        }
+    }
+}
+
+/// Evaluates an expression, going by the number of arguments as POSIX
+/// says for up to four, so that e.g. `test -n` and `test ! = x` mean what
+/// they do in other shells.
+fn evaluate(args: &[&str]) -> Result<bool, Error> {
+    match *args {
+        [] => Ok(false),
+        [word] => Ok(!word.is_empty()),
+        ["!", word] => Ok(word.is_empty()),
+        [op, operand] if is_unary(op) => unary(op, operand),
+        [op, _] => Err(anyhow!("{}: unary operator expected", op)),
+        [left, op, right] if is_binary(op) || op == "-a" || op == "-o" => binary(left, op, right),
+        ["!", ..] => Ok(!evaluate(&args[1..])?),
+        ["(", word, ")"] => Ok(!word.is_empty()),
+        [_, op, _] => Err(anyhow!("{}: binary operator expected", op)),
+        ["(", _, _, ")"] => evaluate(&args[1..3]),
+        _ => {
+            let mut parser = Parser { args, pos: 0 };
+            let result = parser.or()?;
+            match parser.args.get(parser.pos) {
+                Some(extra) => Err(anyhow!("{}: too many arguments", extra)),
+                None => Ok(result),
+            }
+        }
+    }
+}
+
+/// Longer expressions: `-o` binds looser than `-a`, which binds looser
+/// than `!`.
+struct Parser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, Error> {
+        let arg = self.peek(0).ok_or_else(|| anyhow!("argument expected"))?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn or(&mut self) -> Result<bool, Error> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, Error> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, Error> {
+        if self.peek(0) == Some("!") {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, Error> {
+        if self.peek(1).is_some_and(is_binary) && self.peek(2).is_some() {
+            let (left, op, right) = (self.next()?, self.next()?, self.next()?);
+            return binary(left, op, right);
+        }
+        let arg = self.next()?;
+        if arg == "(" {
+            let result = self.or()?;
+            return match self.next() {
+                Ok(")") => Ok(result),
+                _ => Err(anyhow!("`)' expected")),
+            };
+        }
+        match self.peek(0) {
+            Some(operand) if is_unary(arg) => {
+                self.pos += 1;
+                unary(arg, operand)
+            }
+            _ => Ok(!arg.is_empty()),
+        }
+    }
+}
+
+fn is_unary(op: &str) -> bool {
+    matches!(
+        op,
+        "-b" | "-c"
+            | "-d"
+            | "-e"
+            | "-f"
+            | "-g"
+            | "-h"
+            | "-k"
+            | "-L"
+            | "-n"
+            | "-p"
+            | "-r"
+            | "-s"
+            | "-S"
+            | "-t"
+            | "-u"
+            | "-w"
+            | "-x"
+            | "-z"
+    )
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "-eq"
+            | "-ne"
+            | "-lt"
+            | "-le"
+            | "-gt"
+            | "-ge"
+            | "-nt"
+            | "-ot"
+            | "-ef"
+    )
+}
+
+fn unary(op: &str, operand: &str) -> Result<bool, Error> {
+    let meta = || fs::metadata(operand).ok();
+    let is = |check: fn(&Metadata) -> bool| meta().is_some_and(|meta| check(&meta));
+    Ok(match op {
+        "-n" => !operand.is_empty(),
+        "-z" => operand.is_empty(),
+        "-e" => meta().is_some(),
+        "-f" => is(|meta| meta.is_file()),
+        "-d" => is(|meta| meta.is_dir()),
+        "-b" => is(|meta| meta.file_type().is_block_device()),
+        "-c" => is(|meta| meta.file_type().is_char_device()),
+        "-p" => is(|meta| meta.file_type().is_fifo()),
+        "-S" => is(|meta| meta.file_type().is_socket()),
+        "-s" => is(|meta| meta.len() > 0),
+        "-u" => is(|meta| meta.permissions().mode() & 0o4000 != 0),
+        "-g" => is(|meta| meta.permissions().mode() & 0o2000 != 0),
+        "-k" => is(|meta| meta.permissions().mode() & 0o1000 != 0),
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|meta| meta.is_symlink()),
+        "-r" => access(operand, libc::R_OK),
+        "-w" => access(operand, libc::W_OK),
+        "-x" => access(operand, libc::X_OK),
+        "-t" => unsafe { libc::isatty(integer(operand)? as libc::c_int) == 1 },
+        _ => return Err(anyhow!("{}: unary operator expected", op)),
+    })
+}
+
+fn binary(left: &str, op: &str, right: &str) -> Result<bool, Error> {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    Ok(match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-a" => !left.is_empty() && !right.is_empty(),
+        "-o" => !left.is_empty() || !right.is_empty(),
+        "-eq" => integer(left)? == integer(right)?,
+        "-ne" => integer(left)? != integer(right)?,
+        "-lt" => integer(left)? < integer(right)?,
+        "-le" => integer(left)? <= integer(right)?,
+        "-gt" => integer(left)? > integer(right)?,
+        "-ge" => integer(left)? >= integer(right)?,
+        // A file that exists is newer than one that doesn't.
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, right) => left.is_some() && right.is_none(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (left, right) => left.is_none() && right.is_some(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => return Err(anyhow!("{}: binary operator expected", op)),
+    })
+}
+
+fn integer(arg: &str) -> Result<i64, Error> {
+    arg.trim()
+        .parse()
+        .map_err(|_| anyhow!("{}: integer expression expected", arg))
+}
+
+/// Whether the shell may access `path` as `mode` says, going by the real
+/// user and group as `access(2)` does.
+fn access(path: &str, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<bool, Error> {
+        let args: Vec<&str> = expr.split(' ').filter(|arg| !arg.is_empty()).collect();
+        evaluate(&args)
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        assert!(!eval("").unwrap());
+        assert!(eval("-n").unwrap());
+        assert!(eval("! -z x").unwrap());
+        assert!(eval("abc = abc").unwrap());
+        assert!(!eval("! = x").unwrap());
+        assert!(eval("2 -lt 10").unwrap());
+        assert!(!eval("b < a").unwrap());
+        assert!(eval("1 -eq 2 -o ( x != y -a ! -z y )").unwrap());
+        assert!(eval("-d / -a -e /").unwrap());
+        assert!(eval("x -eq 1").is_err());
+        assert!(eval("( a").is_err());
+    }
+}
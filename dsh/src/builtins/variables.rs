@@ -0,0 +1,97 @@
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+use crate::internals::variables;
+
+pub struct Export;
+pub struct Unset;
+
+impl Builtin for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "export [-p] [name[=value] ...]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Mark variables for the environment of the commands the shell runs, \
+         setting them first when given a value. Without names, or with -p, \
+         list the exported variables."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        export(args, state)
+    }
+}
+
+impl Builtin for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unset [-f|-v] name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove variables from the shell and from the environment of the \
+         commands it runs. With -f, remove functions instead. Without either \
+         flag, a name that isn't a variable is removed as a function."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        unset(args, state)
+    }
+}
+
+fn export(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let names = match args.get(1).map(String::as_str) {
+        Some("-p") => &args[2..],
+        _ => &args[1..],
+    };
+    if names.is_empty() {
+        for (name, value) in state.variables.exported() {
+            println!("export {}={}", name, variables::quote(value));
+        }
+        return ExitStatus::SUCCESS;
+    }
+
+    let mut status = ExitStatus::SUCCESS;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if let Err(err) = state.variables.export(name, value) {
+            report!("export: {}", err);
+            status = ExitStatus::FAILURE;
+        }
+    }
+    status
+}
+
+fn unset(args: &[String], state: &mut ShellState) -> ExitStatus {
+    let (flag, names) = match args.get(1).map(String::as_str) {
+        Some(flag @ ("-f" | "-v")) => (Some(flag), &args[2..]),
+        _ => (None, &args[1..]),
+    };
+
+    let mut status = ExitStatus::SUCCESS;
+    for name in names {
+        let is_variable = state.variables.get(name).is_some();
+        match flag {
+            Some("-f") => {
+                state.functions.remove(name);
+            }
+            None if !is_variable && state.functions.remove(name).is_some() => {}
+            _ if variables::is_name(name) => state.variables.unset(name),
+            _ => {
+                report!("unset: `{}': not a valid identifier", name);
+                status = ExitStatus::FAILURE;
+            }
+        }
+    }
+    status
+}
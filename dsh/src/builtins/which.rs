@@ -0,0 +1,214 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use super::Builtin;
+use crate::internals::shell::ShellState;
+use crate::internals::status::ExitStatus;
+
+pub struct Type;
+pub struct Which;
+
+/// What a command name refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Meaning {
+    Alias(String),
+    /// The function's body, as it would be written.
+    Function(String),
+    Builtin,
+    File(PathBuf),
+}
+
+impl Meaning {
+    /// The word `type -t` prints.
+    fn kind(&self) -> &'static str {
+        match self {
+            Meaning::Alias(_) => "alias",
+            Meaning::Function(_) => "function",
+            Meaning::Builtin => "builtin",
+            Meaning::File(_) => "file",
+        }
+    }
+}
+
+impl Builtin for Type {
+    fn name(&self) -> &'static str {
+        "type"
+    }
+
+    fn usage(&self) -> &'static str {
+        "type [-a] [-t | -p | -P] name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Tell how each name would be interpreted as a command: as an alias, a \
+         function, a builtin or a file on $PATH, in the order the shell looks. -a shows \
+         every meaning rather than the first, -t only says which kind it is, \
+         and -p prints the file a name would run, if that is what it does. -P \
+         searches $PATH even for aliases, functions and builtins."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let mut all = false;
+        let mut kind_only = false;
+        let mut path_only = false;
+        let mut force_path = false;
+        let mut names = &args[1..];
+        while let Some(flags) = names.first().and_then(|name| name.strip_prefix('-')) {
+            if flags.is_empty() {
+                break;
+            }
+            for flag in flags.chars() {
+                match flag {
+                    'a' => all = true,
+                    't' => kind_only = true,
+                    'p' => path_only = true,
+                    'P' => force_path = true,
+                    _ => {
+                        report!("type: usage: {}", self.usage());
+                        return ExitStatus::Exited(2);
+                    }
+                }
+            }
+            names = &names[1..];
+        }
+
+        let mut status = ExitStatus::SUCCESS;
+        for name in names {
+            let mut meanings = if force_path {
+                search_path(name, state, all)
+                    .into_iter()
+                    .map(Meaning::File)
+                    .collect()
+            } else {
+                resolve(name, state, all)
+            };
+            if !all {
+                meanings.truncate(1);
+            }
+            if meanings.is_empty() {
+                if !kind_only && !path_only && !force_path {
+                    report!("type: {}: not found", name);
+                }
+                status = ExitStatus::FAILURE;
+            }
+
+            for meaning in meanings {
+                if kind_only {
+                    println!("{}", meaning.kind());
+                    continue;
+                }
+                match meaning {
+                    Meaning::File(path) if path_only || force_path => {
+                        println!("{}", path.display())
+                    }
+                    // `-p` and `-P` only ever print files.
+                    _ if path_only || force_path => {}
+                    Meaning::Alias(value) => println!("{} is aliased to `{}'", name, value),
+                    Meaning::Function(body) => {
+                        println!("{} is a function", name);
+                        println!("{} () {}", name, body);
+                    }
+                    Meaning::Builtin => println!("{} is a shell builtin", name),
+                    Meaning::File(path) => println!("{} is {}", name, path.display()),
+                }
+            }
+        }
+        status
+    }
+}
+
+impl Builtin for Which {
+    fn name(&self) -> &'static str {
+        "which"
+    }
+
+    fn usage(&self) -> &'static str {
+        "which [-a] name ..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Show what each name runs as a command: the file on $PATH, or that it \
+         is an alias, a function or a builtin. -a shows every match rather than the first."
+    }
+
+    fn run(&self, args: &[String], state: &mut ShellState) -> ExitStatus {
+        let (all, names) = match args.get(1).map(String::as_str) {
+            Some("-a") => (true, &args[2..]),
+            Some(flag) if flag.starts_with('-') && flag.len() > 1 => {
+                report!("which: usage: {}", self.usage());
+                return ExitStatus::Exited(2);
+            }
+            _ => (false, &args[1..]),
+        };
+
+        let mut status = ExitStatus::SUCCESS;
+        for name in names {
+            let mut meanings = resolve(name, state, all);
+            if !all {
+                meanings.truncate(1);
+            }
+            if meanings.is_empty() {
+                report!("which: {}: not found", name);
+                status = ExitStatus::FAILURE;
+            }
+            for meaning in meanings {
+                match meaning {
+                    Meaning::Alias(value) => println!("{}: aliased to {}", name, value),
+                    Meaning::Function(_) => println!("{}: shell function", name),
+                    Meaning::Builtin => println!("{}: shell builtin", name),
+                    Meaning::File(path) => println!("{}", path.display()),
+                }
+            }
+        }
+        status
+    }
+}
+
+/// Everything `name` could mean, in the order the shell tries them.
+fn resolve(name: &str, state: &ShellState, all: bool) -> Vec<Meaning> {
+    let mut meanings = Vec::new();
+    if let Some(value) = state.aliases.get(name) {
+        meanings.push(Meaning::Alias(value.clone()));
+    }
+    if let Some(body) = state.functions.get(name) {
+        meanings.push(Meaning::Function(body.to_string()));
+    }
+    if super::lookup(name).is_some() {
+        meanings.push(Meaning::Builtin);
+    }
+    if meanings.is_empty() || all {
+        meanings.extend(search_path(name, state, all).into_iter().map(Meaning::File));
+    }
+    meanings
+}
+
+/// The executables `name` runs from `$PATH`: the first, or with `all`
+/// every one. A name with a slash in it is only looked at where it is.
+pub fn search_path(name: &str, state: &ShellState, all: bool) -> Vec<PathBuf> {
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return if is_executable(&path) {
+            vec![path]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let path = state.variables.get("PATH").unwrap_or_default();
+    let mut found = env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .filter(|candidate| is_executable(candidate));
+    if all {
+        found.collect()
+    } else {
+        found.next().into_iter().collect()
+    }
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,409 @@
+//! dsh's settings, read from `$XDG_CONFIG_HOME/dsh/config.toml` or
+//! `~/.config/dsh/config.toml`. Every setting is optional; environment
+//! variables such as `DSH_AI_POLICY` win over the file.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use ai_engine::Which;
+use anyhow::{anyhow, Result};
+use crossterm::event::KeyEvent;
+use crossterm::style::Color;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::editor::{self, Action, EditMode};
+use crate::internals::diagnosis::{AiBackend, AiPolicy};
+
+/// How many commands the history keeps, in memory and on disk, unless the
+/// config says otherwise.
+pub const HISTORY_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub ai: AiConfig,
+    pub prompt: PromptConfig,
+    pub history: HistoryConfig,
+    pub keybindings: KeybindingConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiConfig {
+    /// `local`, `openai` or `mock`.
+    #[serde(deserialize_with = "parsed")]
+    pub backend: Option<AiBackend>,
+    /// When a failed command is explained: `auto`, `ask` or `never`.
+    #[serde(deserialize_with = "parsed")]
+    pub policy: Option<AiPolicy>,
+    /// The local model, by name, as `7b-chat`.
+    #[serde(deserialize_with = "parsed")]
+    pub model: Option<Which>,
+    /// Weights to load instead of downloading the named model's.
+    pub model_path: Option<String>,
+    pub tokenizer: Option<String>,
+    /// The model asked when the backend is OpenAI, as `gpt-4o-mini`.
+    pub openai_model: Option<String>,
+    /// Any server with OpenAI's API, up to the `/chat/completions`, as
+    /// `http://localhost:8080/v1`.
+    pub openai_url: Option<String>,
+    /// The environment variable holding the API key, `OPENAI_API_KEY`
+    /// unless it says otherwise.
+    pub openai_api_key_env: Option<String>,
+    /// Headers sent with every request.
+    pub openai_headers: BTreeMap<String, String>,
+    /// How long a whole answer may take, in seconds.
+    #[serde(deserialize_with = "seconds")]
+    pub openai_timeout: Option<Duration>,
+    /// How long connecting may take, in seconds.
+    #[serde(deserialize_with = "seconds")]
+    pub openai_connect_timeout: Option<Duration>,
+    /// Instead of the one in `$HTTPS_PROXY`, as `http://proxy:3128`.
+    pub openai_proxy: Option<String>,
+    /// What the `mock` backend answers, whatever it is asked.
+    pub mock_reply: Option<String>,
+    pub sampling: SamplingConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    /// 0 always picks the likeliest token.
+    #[serde(deserialize_with = "non_negative")]
+    pub temperature: Option<f64>,
+    /// Nucleus sampling: only the likeliest tokens making up this much of
+    /// the probability are considered.
+    #[serde(deserialize_with = "probability")]
+    pub top_p: Option<f64>,
+    pub seed: Option<u64>,
+    /// The longest answer, in tokens.
+    #[serde(deserialize_with = "at_least_one")]
+    pub max_tokens: Option<usize>,
+    /// 1 means repeating tokens isn't penalized.
+    #[serde(deserialize_with = "non_negative")]
+    pub repeat_penalty: Option<f64>,
+    /// How many of the last tokens the repeat penalty looks at.
+    pub repeat_last_n: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptConfig {
+    /// The color of the prompt's own text: a color name, as `blue` or
+    /// `dark_green`, `#rrggbb`, an ANSI color number, or `none`.
+    #[serde(deserialize_with = "color")]
+    pub color: Color,
+    /// The prompt when `$PS1` isn't set, written as described in
+    /// [`crate::internals::prompt`].
+    pub format: Option<String>,
+    /// The prompt at the right end of the line when `$RPS1` isn't set.
+    pub right_format: Option<String>,
+    pub colors: SegmentColors,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        PromptConfig {
+            color: Color::Blue,
+            format: None,
+            right_format: None,
+            colors: SegmentColors::default(),
+        }
+    }
+}
+
+/// The colors of the prompt's segments. Those without one are in the
+/// prompt's color.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SegmentColors {
+    #[serde(deserialize_with = "some_color")]
+    pub user: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub host: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub cwd: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub git: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub status: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub duration: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub jobs: Option<Color>,
+    #[serde(deserialize_with = "some_color")]
+    pub ai: Option<Color>,
+}
+
+impl Default for SegmentColors {
+    fn default() -> Self {
+        SegmentColors {
+            user: None,
+            host: None,
+            cwd: None,
+            git: Some(Color::Magenta),
+            status: Some(Color::Red),
+            duration: Some(Color::Yellow),
+            jobs: Some(Color::Cyan),
+            ai: Some(Color::DarkGrey),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// How many commands to keep.
+    pub size: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig { size: HISTORY_SIZE }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeybindingConfig {
+    /// `emacs` or `vi`.
+    #[serde(deserialize_with = "parsed")]
+    pub mode: Option<EditMode>,
+    /// Keys, as `ctrl-t`, and the readline actions they run, as
+    /// `transpose-chars`. Checked by [`Config::parse`] so that mistakes
+    /// are reported on their own line.
+    bind: BTreeMap<Spanned<String>, Spanned<String>>,
+    #[serde(skip)]
+    pub bindings: Vec<(KeyEvent, Action)>,
+}
+
+impl Config {
+    /// Where the config lives, if there is a home to find it in.
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("dsh").join("config.toml"))
+    }
+
+    /// Reads the config file. Without one, every setting has its default.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text)
+                .map_err(|(line, message)| anyhow!("{}:{}: {}", path.display(), line, message)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Parses and checks a config, or says what is wrong with it and on
+    /// which line.
+    pub fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let line = |offset: usize| text[..offset.min(text.len())].matches('\n').count() + 1;
+
+        let mut config: Config = toml::from_str(text).map_err(|err| {
+            let offset = err.span().map_or(0, |span| span.start);
+            (line(offset), err.message().to_string())
+        })?;
+
+        let keybindings = &mut config.keybindings;
+        for (key, action) in &keybindings.bind {
+            let parsed_key = editor::parse_key(key.get_ref())
+                .map_err(|err| (line(key.span().start), err.to_string()))?;
+            let parsed_action = editor::parse_action(action.get_ref())
+                .map_err(|err| (line(action.span().start), err.to_string()))?;
+            keybindings.bindings.push((parsed_key, parsed_action));
+        }
+        Ok(config)
+    }
+}
+
+impl AiConfig {
+    /// The settings the AI engine is loaded with.
+    pub fn engine_args(&self) -> ai_engine::Args {
+        let mut args = ai_engine::Args::default();
+        if let Some(which) = self.model {
+            args.which = which;
+        }
+        args.model = self.model_path.clone();
+        args.tokenizer = self.tokenizer.clone();
+        if let Some(model) = &self.openai_model {
+            args.openai_model = model.clone();
+        }
+        if let Some(url) = &self.openai_url {
+            args.openai_url = url.clone();
+        }
+        if let Some(name) = &self.openai_api_key_env {
+            args.openai_api_key_env = name.clone();
+        }
+        args.openai_headers = self.openai_headers.clone().into_iter().collect();
+        args.openai_timeout = self.openai_timeout;
+        args.openai_connect_timeout = self.openai_connect_timeout;
+        args.openai_proxy = self.openai_proxy.clone();
+
+        let sampling = &self.sampling;
+        args.temperature = sampling.temperature.unwrap_or(args.temperature);
+        args.top_p = sampling.top_p.or(args.top_p);
+        args.seed = sampling.seed.unwrap_or(args.seed);
+        args.sample_len = sampling.max_tokens.unwrap_or(args.sample_len);
+        if let Some(penalty) = sampling.repeat_penalty {
+            args.repeat_penalty = penalty as f32;
+        }
+        args.repeat_last_n = sampling.repeat_last_n.unwrap_or(args.repeat_last_n);
+        args
+    }
+}
+
+/// Reads a setting through its `FromStr`, so that a bad value is reported
+/// where it was written.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if value < 0.0 {
+        return Err(de::Error::custom(format!("{} is negative", value)));
+    }
+    Ok(Some(value))
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    match Duration::try_from_secs_f64(value) {
+        Ok(duration) if !duration.is_zero() => Ok(Some(duration)),
+        _ => Err(de::Error::custom(format!(
+            "{} is not a number of seconds",
+            value
+        ))),
+    }
+}
+
+fn probability<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(value > 0.0 && value <= 1.0) {
+        return Err(de::Error::custom(format!(
+            "{} is not a probability between 0 and 1",
+            value
+        )));
+    }
+    Ok(Some(value))
+}
+
+fn at_least_one<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match usize::deserialize(deserializer)? {
+        0 => Err(de::Error::custom("has to be at least 1")),
+        value => Ok(Some(value)),
+    }
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_color(&text).map_err(de::Error::custom)
+}
+
+fn some_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    color(deserializer).map(Some)
+}
+
+/// Parses a color name, as `blue` or `dark-green`, `#rrggbb`, an ANSI color
+/// number from 0 to 255, or `none` for the terminal's own color.
+pub fn parse_color(text: &str) -> Result<Color> {
+    let name = text.trim().to_ascii_lowercase().replace('-', "_");
+    if name == "none" || name == "default" {
+        return Ok(Color::Reset);
+    }
+    if let Ok(number) = name.parse::<u8>() {
+        return Ok(Color::AnsiValue(number));
+    }
+    if let Some(hex) = name.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16);
+        if let (Ok(r), Ok(g), Ok(b)) = (channel(0), channel(2), channel(4)) {
+            return Ok(Color::Rgb { r, g, b });
+        }
+    }
+    Color::try_from(name.as_str()).map_err(|()| anyhow!("unknown color `{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_reports_bad_ones_by_line() {
+        let config = Config::parse(
+            "[ai]\n\
+             backend = \"openai\"\n\
+             model = \"mistral-7b\"\n\
+             openai_url = \"http://localhost:11434/v1\"\n\
+             openai_api_key_env = \"OLLAMA_KEY\"\n\
+             openai_headers = { \"X-Team\" = \"shell\" }\n\
+             openai_timeout = 1.5\n\
+             [ai.sampling]\n\
+             temperature = 0\n\
+             max_tokens = 200\n\
+             [prompt]\n\
+             color = \"#ff8000\"\n\
+             colors = { git = \"none\" }\n\
+             [history]\n\
+             size = 50\n\
+             [keybindings]\n\
+             mode = \"vi\"\n\
+             bind = { \"ctrl-o\" = \"accept-line\" }\n",
+        )
+        .unwrap();
+        assert_eq!(config.ai.backend, Some(AiBackend::OpenAi));
+        assert_eq!(config.ai.policy, None);
+        let args = config.ai.engine_args();
+        assert_eq!(
+            (args.which, args.temperature, args.sample_len),
+            (Which::Mistral7b, 0.0, 200)
+        );
+        assert_eq!(args.openai_url, "http://localhost:11434/v1");
+        assert_eq!(args.openai_api_key_env, "OLLAMA_KEY");
+        assert_eq!(
+            args.openai_headers,
+            [("X-Team".to_string(), "shell".to_string())]
+        );
+        assert_eq!(args.openai_timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(args.openai_connect_timeout, None);
+        assert_eq!(
+            config.prompt.color,
+            Color::Rgb {
+                r: 255,
+                g: 128,
+                b: 0
+            }
+        );
+        assert_eq!(config.prompt.colors.git, Some(Color::Reset));
+        assert_eq!(config.prompt.colors.status, Some(Color::Red));
+        assert_eq!(config.history.size, 50);
+        assert_eq!(config.keybindings.mode, Some(EditMode::Vi));
+        assert_eq!(config.keybindings.bindings.len(), 1);
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+
+        let line = |text| Config::parse(text).unwrap_err().0;
+        assert_eq!(line("[ai]\n\npolicy = \"sometimes\"\n"), 3);
+        assert_eq!(line("[ai.sampling]\ntop_p = 1.5\n"), 2);
+        assert_eq!(line("[ai]\nopenai_timeout = -1\n"), 2);
+        assert_eq!(line("[history]\nsize = 10\nlimit = 5\n"), 3);
+        assert_eq!(line("[keybindings.bind]\n\"ctrl-o\" = \"fly\"\n"), 2);
+        assert_eq!(line("[prompt\n"), 1);
+    }
+}
@@ -0,0 +1,393 @@
+use std::ops::Range;
+
+/// How many kills the kill ring remembers.
+const KILL_RING_SIZE: usize = 32;
+
+/// Where a cursor movement, or the text a deletion covers, ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    /// Emacs `backward-word`: to the start of the previous run of
+    /// alphanumerics.
+    BackwardWord,
+    /// Emacs `forward-word`: past the end of the next run of alphanumerics.
+    ForwardWord,
+    /// Vi `b`.
+    WordLeft,
+    /// Vi `w`.
+    WordRight,
+    /// Vi `e`; inclusive, so deleting takes the last character with it.
+    WordEnd,
+    /// Vi `B`, where words are only separated by whitespace.
+    BigWordLeft,
+    /// Vi `W`.
+    BigWordRight,
+    /// Vi `E`.
+    BigWordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// The whole current line, as for `dd`.
+    WholeLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punct,
+}
+
+fn class(c: char, big: bool) -> Class {
+    if c.is_whitespace() {
+        Class::Space
+    } else if big || c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punct
+    }
+}
+
+/// The text being edited and the cursor, a byte offset into it that is
+/// always on a character boundary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Buffer {
+    text: String,
+    cursor: usize,
+}
+
+impl Buffer {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the text, leaving the cursor at the end.
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = text.len();
+    }
+
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.text.len());
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) -> String {
+        self.cursor = range.start;
+        self.text.drain(range).collect()
+    }
+
+    fn char_at(&self, pos: usize) -> Option<char> {
+        self.text[pos..].chars().next()
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        self.text[..pos].chars().next_back()
+    }
+
+    fn next(&self, pos: usize) -> usize {
+        self.char_at(pos).map_or(pos, |c| pos + c.len_utf8())
+    }
+
+    fn prev(&self, pos: usize) -> usize {
+        self.char_before(pos).map_or(pos, |c| pos - c.len_utf8())
+    }
+
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos].rfind('\n').map_or(0, |index| index + 1)
+    }
+
+    fn line_end(&self, pos: usize) -> usize {
+        self.text[pos..]
+            .find('\n')
+            .map_or(self.text.len(), |index| pos + index)
+    }
+
+    /// Whether the cursor is past the last character of its line, which vi
+    /// command mode doesn't allow.
+    pub fn at_line_end(&self) -> bool {
+        self.cursor > self.line_start(self.cursor) && self.cursor == self.line_end(self.cursor)
+    }
+
+    /// Moves to the same column on the line above or below. Returns false
+    /// when there is no such line.
+    pub fn move_line(&mut self, up: bool) -> bool {
+        let start = self.line_start(self.cursor);
+        let column = self.text[start..self.cursor].chars().count();
+        let target_start = if up {
+            if start == 0 {
+                return false;
+            }
+            self.line_start(start - 1)
+        } else {
+            let end = self.line_end(self.cursor);
+            if end == self.text.len() {
+                return false;
+            }
+            end + 1
+        };
+        let target_end = self.line_end(target_start);
+        self.cursor = self.text[target_start..target_end]
+            .char_indices()
+            .nth(column)
+            .map_or(target_end, |(offset, _)| target_start + offset);
+        true
+    }
+
+    /// Where `motion` takes the cursor.
+    pub fn target(&self, motion: Motion) -> usize {
+        let mut pos = self.cursor;
+        match motion {
+            Motion::Left => self.prev(pos),
+            Motion::Right => self.next(pos),
+            Motion::BackwardWord => {
+                while self.char_before(pos).is_some_and(|c| !c.is_alphanumeric()) {
+                    pos = self.prev(pos);
+                }
+                while self.char_before(pos).is_some_and(char::is_alphanumeric) {
+                    pos = self.prev(pos);
+                }
+                pos
+            }
+            Motion::ForwardWord => {
+                while self.char_at(pos).is_some_and(|c| !c.is_alphanumeric()) {
+                    pos = self.next(pos);
+                }
+                while self.char_at(pos).is_some_and(char::is_alphanumeric) {
+                    pos = self.next(pos);
+                }
+                pos
+            }
+            Motion::WordLeft | Motion::BigWordLeft => {
+                let big = motion == Motion::BigWordLeft;
+                while self.char_before(pos).is_some_and(char::is_whitespace) {
+                    pos = self.prev(pos);
+                }
+                if let Some(c) = self.char_before(pos) {
+                    let run = class(c, big);
+                    while self.char_before(pos).is_some_and(|c| class(c, big) == run) {
+                        pos = self.prev(pos);
+                    }
+                }
+                pos
+            }
+            Motion::WordRight | Motion::BigWordRight => {
+                let big = motion == Motion::BigWordRight;
+                if let Some(c) = self.char_at(pos) {
+                    let run = class(c, big);
+                    if run != Class::Space {
+                        while self.char_at(pos).is_some_and(|c| class(c, big) == run) {
+                            pos = self.next(pos);
+                        }
+                    }
+                }
+                while self.char_at(pos).is_some_and(char::is_whitespace) {
+                    pos = self.next(pos);
+                }
+                pos
+            }
+            Motion::WordEnd | Motion::BigWordEnd => {
+                let big = motion == Motion::BigWordEnd;
+                pos = self.next(pos);
+                while self.char_at(pos).is_some_and(char::is_whitespace) {
+                    pos = self.next(pos);
+                }
+                if let Some(c) = self.char_at(pos) {
+                    let run = class(c, big);
+                    while self.char_at(pos).is_some_and(|c| class(c, big) == run) {
+                        pos = self.next(pos);
+                    }
+                }
+                pos
+            }
+            Motion::LineStart => self.line_start(pos),
+            Motion::FirstNonBlank => {
+                pos = self.line_start(pos);
+                while self.char_at(pos).is_some_and(|c| c == ' ' || c == '\t') {
+                    pos = self.next(pos);
+                }
+                pos
+            }
+            Motion::LineEnd | Motion::WholeLine => self.line_end(pos),
+        }
+    }
+
+    /// The text between the cursor and where `motion` leads.
+    pub fn range(&self, motion: Motion) -> Range<usize> {
+        if motion == Motion::WholeLine {
+            return self.line_start(self.cursor)..self.line_end(self.cursor);
+        }
+        let target = self.target(motion);
+        self.cursor.min(target)..self.cursor.max(target)
+    }
+
+    pub fn move_to(&mut self, motion: Motion) {
+        let target = self.target(motion);
+        self.cursor = match motion {
+            // Inclusive motions land on the last character, not after it.
+            Motion::WordEnd | Motion::BigWordEnd => self.prev(target).max(self.cursor),
+            _ => target,
+        };
+    }
+
+    pub fn move_left_in_line(&mut self) {
+        if self.cursor > self.line_start(self.cursor) {
+            self.cursor = self.prev(self.cursor);
+        }
+    }
+
+    /// Vi `r`: replaces the character under the cursor.
+    pub fn replace_char(&mut self, with: char) {
+        if let Some(c) = self.char_at(self.cursor).filter(|&c| c != '\n') {
+            let end = self.cursor + c.len_utf8();
+            self.text
+                .replace_range(self.cursor..end, with.encode_utf8(&mut [0; 4]));
+        }
+    }
+
+    /// Vi `~`: flips the case of the character under the cursor and moves
+    /// past it.
+    pub fn toggle_case(&mut self) {
+        if let Some(c) = self.char_at(self.cursor).filter(|&c| c != '\n') {
+            let toggled: String = if c.is_uppercase() {
+                c.to_lowercase().collect()
+            } else {
+                c.to_uppercase().collect()
+            };
+            let end = self.cursor + c.len_utf8();
+            self.text.replace_range(self.cursor..end, &toggled);
+            self.cursor += toggled.len();
+        }
+    }
+
+    /// Emacs `C-t`: swaps the characters around the cursor, or the last two
+    /// at the end of the line.
+    pub fn transpose_chars(&mut self) {
+        let mut pos = self.cursor;
+        if matches!(self.char_at(pos), None | Some('\n')) {
+            pos = self.prev(pos);
+        }
+        let before = self.prev(pos);
+        if before == pos {
+            return;
+        }
+        let (Some(first), Some(second)) = (self.char_at(before), self.char_at(pos)) else {
+            return;
+        };
+        if first == '\n' || second == '\n' {
+            return;
+        }
+        let end = pos + second.len_utf8();
+        self.text
+            .replace_range(before..end, &format!("{}{}", second, first));
+        self.cursor = end;
+    }
+}
+
+/// Killed text, most recent last, for yanking back.
+#[derive(Debug, Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    /// How far `rotate` has gone back from the most recent kill.
+    offset: usize,
+}
+
+impl KillRing {
+    /// Saves killed text. Consecutive kills grow the latest entry instead,
+    /// at the front when killing backwards.
+    pub fn kill(&mut self, text: String, append: bool, backward: bool) {
+        if text.is_empty() {
+            return;
+        }
+        match self.entries.last_mut() {
+            Some(last) if append && backward => last.insert_str(0, &text),
+            Some(last) if append => last.push_str(&text),
+            _ => {
+                self.entries.push(text);
+                if self.entries.len() > KILL_RING_SIZE {
+                    self.entries.remove(0);
+                }
+            }
+        }
+        self.offset = 0;
+    }
+
+    pub fn yank(&mut self) -> Option<&str> {
+        self.offset = 0;
+        self.entries.last().map(String::as_str)
+    }
+
+    /// The kill before the one yanked last, for `M-y`.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.offset = (self.offset + 1) % self.entries.len();
+        let index = self.entries.len() - 1 - self.offset;
+        Some(&self.entries[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str, cursor: usize) -> Buffer {
+        Buffer {
+            text: text.to_string(),
+            cursor,
+        }
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let buffer = at("git commit -m 'fix it'", 4);
+        assert_eq!(buffer.target(Motion::WordRight), 11);
+        assert_eq!(buffer.target(Motion::WordEnd), 10);
+        assert_eq!(buffer.target(Motion::ForwardWord), 10);
+        assert_eq!(buffer.target(Motion::WordLeft), 0);
+        assert_eq!(at("cd ../src", 9).target(Motion::WordLeft), 6);
+        assert_eq!(at("cd ../src", 9).target(Motion::BigWordLeft), 3);
+        assert_eq!(at("cd ../src", 3).target(Motion::BigWordRight), 9);
+    }
+
+    #[test]
+    fn edits_multiple_lines() {
+        let mut buffer = at("for f in *\ndo echo $f\ndone", 14);
+        assert!(buffer.move_line(true));
+        assert_eq!(buffer.cursor(), 3);
+        assert!(!buffer.move_line(true));
+        assert_eq!(buffer.range(Motion::WholeLine), 0..10);
+
+        buffer.set_cursor(14);
+        buffer.move_to(Motion::LineStart);
+        assert_eq!(buffer.cursor(), 11);
+        let killed = buffer.remove(buffer.range(Motion::LineEnd));
+        assert_eq!(killed, "do echo $f");
+        assert_eq!(buffer.text(), "for f in *\n\ndone");
+    }
+
+    #[test]
+    fn kill_ring_appends_and_rotates() {
+        let mut ring = KillRing::default();
+        ring.kill("world".into(), false, false);
+        ring.kill("hello ".into(), true, true);
+        ring.kill("other".into(), false, false);
+        assert_eq!(ring.yank(), Some("other"));
+        assert_eq!(ring.rotate(), Some("hello world"));
+        assert_eq!(ring.rotate(), Some("other"));
+    }
+}
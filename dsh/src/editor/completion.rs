@@ -0,0 +1,95 @@
+/// Supplies the candidates for Tab.
+pub trait Completer {
+    /// Completes the word that ends at byte `pos` of `line`.
+    fn complete(&self, line: &str, pos: usize) -> Completion;
+}
+
+/// The candidates for the text from `start` up to the cursor.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// What replaces the completed text, already quoted for the shell.
+    pub replacement: String,
+    /// What the candidate looks like in a listing.
+    pub display: String,
+    /// Whether the word is done, so a space can follow it. Directories
+    /// aren't, since a name inside them usually comes next.
+    pub finished: bool,
+}
+
+impl Candidate {
+    pub fn new(replacement: impl Into<String>, display: impl Into<String>, finished: bool) -> Self {
+        Candidate {
+            replacement: replacement.into(),
+            display: display.into(),
+            finished,
+        }
+    }
+}
+
+/// The longest prefix all the candidates share.
+pub fn common_prefix(candidates: &[Candidate]) -> &str {
+    let Some((first, rest)) = candidates.split_first() else {
+        return "";
+    };
+    let mut prefix = first.replacement.as_str();
+    for candidate in rest {
+        let shared = prefix
+            .char_indices()
+            .zip(candidate.replacement.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(
+                prefix.len().min(candidate.replacement.len()),
+                |((i, _), _)| i,
+            );
+        prefix = &prefix[..shared];
+    }
+    prefix
+}
+
+/// Lays the candidates out in columns, top to bottom and then left to
+/// right, as many as fit in `width`.
+pub fn columns(items: &[&str], width: usize) -> Vec<String> {
+    let widest = items
+        .iter()
+        .map(|item| item.chars().count())
+        .max()
+        .unwrap_or(0);
+    let column_width = widest + 2;
+    let columns = (width / column_width).max(1);
+    let rows = items.len().div_ceil(columns);
+
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for item in items.iter().skip(row).step_by(rows) {
+                line.push_str(&format!("{:<1$}", item, column_width));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_prefix_and_columns() {
+        let candidates = [
+            Candidate::new("cargo", "cargo", true),
+            Candidate::new("cat", "cat", true),
+            Candidate::new("cal", "cal", true),
+        ];
+        assert_eq!(common_prefix(&candidates), "ca");
+        assert_eq!(common_prefix(&candidates[..1]), "cargo");
+
+        let items = ["a", "bb", "ccc", "d", "e"];
+        assert_eq!(columns(&items, 10), vec!["a    d", "bb   e", "ccc"]);
+    }
+}
@@ -0,0 +1,476 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::buffer::Motion;
+
+/// Which set of keybindings the line editor uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+impl EditMode {
+    /// Reads the mode from `DSH_EDIT_MODE`, falling back to the `configured`
+    /// one, or emacs, when it is unset or invalid.
+    pub fn from_env(configured: Option<EditMode>) -> Self {
+        let fallback = configured.unwrap_or_default();
+        match std::env::var("DSH_EDIT_MODE") {
+            Ok(value) => value.parse().unwrap_or_else(|err| {
+                eprintln!("dsh: {}", err);
+                fallback
+            }),
+            Err(_) => fallback,
+        }
+    }
+}
+
+impl FromStr for EditMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "emacs" => Ok(EditMode::Emacs),
+            "vi" | "vim" => Ok(EditMode::Vi),
+            other => Err(anyhow!(
+                "invalid edit mode `{}', expected emacs or vi",
+                other
+            )),
+        }
+    }
+}
+
+/// What a key press asks the editor to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Insert(char),
+    /// Runs the input if it is complete, otherwise starts a new line.
+    Accept,
+    Newline,
+    Move(Motion),
+    /// Deletes without saving to the kill ring.
+    Delete(Motion),
+    Kill(Motion),
+    /// Vi `y`: saves to the kill ring without deleting.
+    Copy(Motion),
+    Yank,
+    YankPop,
+    /// Vi `p`: puts the last kill after the cursor.
+    PutAfter,
+    ReplaceChar(char),
+    ToggleCase,
+    TransposeChars,
+    Undo,
+    HistoryPrev,
+    HistoryNext,
+    SearchHistory,
+    Complete,
+    ClearScreen,
+    Interrupt,
+    /// End of input on an empty line, delete forward otherwise.
+    EndOfFile,
+    /// Vi: switch to insert mode.
+    InsertMode,
+    /// Vi: switch to command mode.
+    CommandMode,
+}
+
+/// Turns key presses into [`Action`]s, keeping track of vi's modes,
+/// pending operators and counts.
+#[derive(Debug)]
+pub struct Keymap {
+    mode: EditMode,
+    /// Keys bound in the config, which win over the emacs bindings and vi
+    /// insert mode's.
+    bindings: Vec<(KeyEvent, Action)>,
+    vi_command: bool,
+    /// An operator waiting for its motion, as the `d` of `dw`, or an `r`
+    /// waiting for its character.
+    pending: Option<char>,
+    count: Option<usize>,
+}
+
+impl Keymap {
+    pub fn new(mode: EditMode) -> Self {
+        Keymap {
+            mode,
+            bindings: Vec::new(),
+            vi_command: false,
+            pending: None,
+            count: None,
+        }
+    }
+
+    /// Makes `key` do `action`, in place of what it did before.
+    pub fn bind(&mut self, key: KeyEvent, action: Action) {
+        self.bindings.retain(|(bound, _)| !same_key(bound, &key));
+        self.bindings.push((key, action));
+    }
+
+    /// Every line starts out in insert mode.
+    pub fn reset(&mut self) {
+        self.vi_command = false;
+        self.pending = None;
+        self.count = None;
+    }
+
+    pub fn vi_command(&self) -> bool {
+        self.vi_command
+    }
+
+    pub fn translate(&mut self, key: KeyEvent) -> Vec<Action> {
+        if !self.vi_command {
+            let bound = self
+                .bindings
+                .iter()
+                .find(|(bound, _)| same_key(bound, &key));
+            if let Some((_, action)) = bound {
+                return vec![*action];
+            }
+        }
+        match self.mode {
+            EditMode::Emacs => emacs(key).into_iter().collect(),
+            EditMode::Vi if self.vi_command => self.vi_command_key(key),
+            EditMode::Vi => match key.code {
+                KeyCode::Esc => {
+                    self.vi_command = true;
+                    vec![Action::CommandMode]
+                }
+                _ => emacs(key).into_iter().collect(),
+            },
+        }
+    }
+
+    fn vi_command_key(&mut self, key: KeyEvent) -> Vec<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let c = match key.code {
+            KeyCode::Char(c) if !ctrl => c,
+            KeyCode::Char('c') => return self.cancel(vec![Action::Interrupt]),
+            KeyCode::Char('d') => return self.cancel(vec![Action::EndOfFile]),
+            KeyCode::Char('r') => return self.cancel(vec![Action::SearchHistory]),
+            KeyCode::Char('l') => return self.cancel(vec![Action::ClearScreen]),
+            KeyCode::Enter => return self.cancel(vec![Action::Accept]),
+            KeyCode::Left | KeyCode::Backspace => 'h',
+            KeyCode::Right => 'l',
+            KeyCode::Up => 'k',
+            KeyCode::Down => 'j',
+            KeyCode::Home => '0',
+            KeyCode::End => '$',
+            KeyCode::Esc => return self.cancel(Vec::new()),
+            _ => return Vec::new(),
+        };
+
+        if self.pending == Some('r') {
+            return self.cancel(vec![Action::ReplaceChar(c)]);
+        }
+
+        if c.is_ascii_digit() && (c != '0' || self.count.is_some()) {
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10) + digit);
+            return Vec::new();
+        }
+        let count = self.count.take().unwrap_or(1).clamp(1, 1000);
+
+        if let Some(operator) = self.pending.take() {
+            let motion = match (operator, c) {
+                ('d', 'd') | ('c', 'c') | ('y', 'y') => Some(Motion::WholeLine),
+                // `cw` changes to the end of the word, like `ce`.
+                ('c', 'w') => Some(Motion::WordEnd),
+                ('c', 'W') => Some(Motion::BigWordEnd),
+                _ => vi_motion(c),
+            };
+            let Some(motion) = motion else {
+                return Vec::new();
+            };
+            let action = match operator {
+                'y' => Action::Copy(motion),
+                _ => Action::Kill(motion),
+            };
+            let mut actions = vec![action; count];
+            if operator == 'c' {
+                actions.push(self.insert_mode());
+            }
+            return actions;
+        }
+
+        if let Some(motion) = vi_motion(c) {
+            return vec![Action::Move(motion); count];
+        }
+
+        match c {
+            'd' | 'c' | 'y' | 'r' => {
+                self.pending = Some(c);
+                self.count = (count > 1).then_some(count);
+                Vec::new()
+            }
+            'x' => vec![Action::Kill(Motion::Right); count],
+            'X' => vec![Action::Kill(Motion::Left); count],
+            'D' => vec![Action::Kill(Motion::LineEnd)],
+            'C' => vec![Action::Kill(Motion::LineEnd), self.insert_mode()],
+            's' => vec![Action::Kill(Motion::Right), self.insert_mode()],
+            'S' => vec![Action::Kill(Motion::WholeLine), self.insert_mode()],
+            'i' => vec![self.insert_mode()],
+            'a' => vec![Action::Move(Motion::Right), self.insert_mode()],
+            'I' => vec![Action::Move(Motion::FirstNonBlank), self.insert_mode()],
+            'A' => vec![Action::Move(Motion::LineEnd), self.insert_mode()],
+            'p' => vec![Action::PutAfter; count],
+            'P' => vec![Action::Yank; count],
+            '~' => vec![Action::ToggleCase; count],
+            'u' => vec![Action::Undo; count],
+            'k' | '-' => vec![Action::HistoryPrev; count],
+            'j' | '+' => vec![Action::HistoryNext; count],
+            '/' | '?' => vec![Action::SearchHistory],
+            _ => Vec::new(),
+        }
+    }
+
+    fn insert_mode(&mut self) -> Action {
+        self.vi_command = false;
+        Action::InsertMode
+    }
+
+    fn cancel(&mut self, actions: Vec<Action>) -> Vec<Action> {
+        self.pending = None;
+        self.count = None;
+        actions
+    }
+}
+
+fn vi_motion(c: char) -> Option<Motion> {
+    Some(match c {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'w' => Motion::WordRight,
+        'W' => Motion::BigWordRight,
+        'b' => Motion::WordLeft,
+        'B' => Motion::BigWordLeft,
+        'e' => Motion::WordEnd,
+        'E' => Motion::BigWordEnd,
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        _ => return None,
+    })
+}
+
+/// Whether two key presses are the same key, ignoring the Shift that
+/// typing a capital letter takes.
+fn same_key(a: &KeyEvent, b: &KeyEvent) -> bool {
+    let modifiers = |key: &KeyEvent| match key.code {
+        KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+        _ => key.modifiers,
+    };
+    a.code == b.code && modifiers(a) == modifiers(b)
+}
+
+/// Parses a key as the config writes it: a character or the name of a key,
+/// as `tab` or `left`, after any of `ctrl-`, `alt-` and `shift-`.
+pub fn parse_key(spec: &str) -> Result<KeyEvent, Error> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let (modifier, prefix) = if lower.starts_with("ctrl-") {
+            (KeyModifiers::CONTROL, "ctrl-")
+        } else if lower.starts_with("alt-") {
+            (KeyModifiers::ALT, "alt-")
+        } else if lower.starts_with("meta-") {
+            (KeyModifiers::ALT, "meta-")
+        } else if lower.starts_with("shift-") {
+            (KeyModifiers::SHIFT, "shift-")
+        } else {
+            break;
+        };
+        modifiers |= modifier;
+        rest = &rest[prefix.len()..];
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        // Terminals report Ctrl with a letter in lowercase.
+        (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "enter" | "return" => KeyCode::Enter,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "esc" | "escape" => KeyCode::Esc,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            _ => return Err(anyhow!("unknown key `{}'", spec)),
+        },
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+/// Looks up an action by the name readline gives it, as `kill-line`.
+pub fn parse_action(name: &str) -> Result<Action, Error> {
+    Ok(match name.trim() {
+        "accept-line" => Action::Accept,
+        "insert-newline" => Action::Newline,
+        "beginning-of-line" => Action::Move(Motion::LineStart),
+        "end-of-line" => Action::Move(Motion::LineEnd),
+        "backward-char" => Action::Move(Motion::Left),
+        "forward-char" => Action::Move(Motion::Right),
+        "backward-word" => Action::Move(Motion::BackwardWord),
+        "forward-word" => Action::Move(Motion::ForwardWord),
+        "backward-delete-char" => Action::Delete(Motion::Left),
+        "delete-char" => Action::Delete(Motion::Right),
+        "kill-line" => Action::Kill(Motion::LineEnd),
+        "backward-kill-line" | "unix-line-discard" => Action::Kill(Motion::LineStart),
+        "kill-word" => Action::Kill(Motion::ForwardWord),
+        "backward-kill-word" => Action::Kill(Motion::BackwardWord),
+        "unix-word-rubout" => Action::Kill(Motion::BigWordLeft),
+        "yank" => Action::Yank,
+        "yank-pop" => Action::YankPop,
+        "transpose-chars" => Action::TransposeChars,
+        "undo" => Action::Undo,
+        "previous-history" => Action::HistoryPrev,
+        "next-history" => Action::HistoryNext,
+        "reverse-search-history" => Action::SearchHistory,
+        "complete" => Action::Complete,
+        "clear-screen" => Action::ClearScreen,
+        "end-of-file" => Action::EndOfFile,
+        other => return Err(anyhow!("unknown editor action `{}'", other)),
+    })
+}
+
+/// The emacs bindings, which vi insert mode shares for everything but Esc.
+fn emacs(key: KeyEvent) -> Option<Action> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+    Some(match key.code {
+        KeyCode::Char(c) if ctrl => match c {
+            'a' => Action::Move(Motion::LineStart),
+            'e' => Action::Move(Motion::LineEnd),
+            'b' => Action::Move(Motion::Left),
+            'f' => Action::Move(Motion::Right),
+            'p' => Action::HistoryPrev,
+            'n' => Action::HistoryNext,
+            'h' => Action::Delete(Motion::Left),
+            'k' => Action::Kill(Motion::LineEnd),
+            'u' => Action::Kill(Motion::LineStart),
+            'w' => Action::Kill(Motion::BigWordLeft),
+            'y' => Action::Yank,
+            't' => Action::TransposeChars,
+            'r' => Action::SearchHistory,
+            'l' => Action::ClearScreen,
+            'c' => Action::Interrupt,
+            'd' => Action::EndOfFile,
+            'j' => Action::Accept,
+            // Terminals disagree on what Ctrl+_ looks like.
+            '_' | '/' | '7' => Action::Undo,
+            _ => return None,
+        },
+        KeyCode::Char(c) if alt => match c {
+            'b' => Action::Move(Motion::BackwardWord),
+            'f' => Action::Move(Motion::ForwardWord),
+            'd' => Action::Kill(Motion::ForwardWord),
+            'y' => Action::YankPop,
+            _ => return None,
+        },
+        KeyCode::Char(c) => Action::Insert(c),
+        KeyCode::Tab => Action::Complete,
+        KeyCode::Enter if alt => Action::Newline,
+        KeyCode::Enter => Action::Accept,
+        KeyCode::Backspace if alt => Action::Kill(Motion::BackwardWord),
+        KeyCode::Backspace => Action::Delete(Motion::Left),
+        KeyCode::Delete => Action::Delete(Motion::Right),
+        KeyCode::Left if ctrl || alt => Action::Move(Motion::BackwardWord),
+        KeyCode::Right if ctrl || alt => Action::Move(Motion::ForwardWord),
+        KeyCode::Left => Action::Move(Motion::Left),
+        KeyCode::Right => Action::Move(Motion::Right),
+        KeyCode::Home => Action::Move(Motion::LineStart),
+        KeyCode::End => Action::Move(Motion::LineEnd),
+        KeyCode::Up => Action::HistoryPrev,
+        KeyCode::Down => Action::HistoryNext,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keymap: &mut Keymap, input: &str) -> Vec<Action> {
+        input
+            .chars()
+            .flat_map(|c| {
+                let code = if c == '\x1b' {
+                    KeyCode::Esc
+                } else {
+                    KeyCode::Char(c)
+                };
+                keymap.translate(KeyEvent::new(code, KeyModifiers::NONE))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vi_operators_counts_and_modes() {
+        let mut keymap = Keymap::new(EditMode::Vi);
+        assert_eq!(keys(&mut keymap, "a"), vec![Action::Insert('a')]);
+        assert_eq!(keys(&mut keymap, "\x1b"), vec![Action::CommandMode]);
+        assert_eq!(
+            keys(&mut keymap, "2dw"),
+            vec![Action::Kill(Motion::WordRight); 2]
+        );
+        assert_eq!(
+            keys(&mut keymap, "cw"),
+            vec![Action::Kill(Motion::WordEnd), Action::InsertMode]
+        );
+        assert!(!keymap.vi_command());
+        assert_eq!(
+            keys(&mut keymap, "\x1b0rx"),
+            vec![
+                Action::CommandMode,
+                Action::Move(Motion::LineStart),
+                Action::ReplaceChar('x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn emacs_control_keys() {
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        let mut keymap = Keymap::new(EditMode::Emacs);
+        assert_eq!(
+            keymap.translate(ctrl('k')),
+            vec![Action::Kill(Motion::LineEnd)]
+        );
+        assert_eq!(keymap.translate(alt('y')), vec![Action::YankPop]);
+        assert_eq!(keymap.translate(ctrl('r')), vec![Action::SearchHistory]);
+        assert_eq!("vi".parse::<EditMode>().unwrap(), EditMode::Vi);
+    }
+
+    #[test]
+    fn config_bindings_win() {
+        let mut keymap = Keymap::new(EditMode::Vi);
+        let key = parse_key("Ctrl-T").unwrap();
+        assert_eq!(
+            key,
+            KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL)
+        );
+        keymap.bind(key, parse_action("kill-line").unwrap());
+        keymap.bind(parse_key("alt-left").unwrap(), Action::Undo);
+
+        assert_eq!(keymap.translate(key), vec![Action::Kill(Motion::LineEnd)]);
+        let alt_left = KeyEvent::new(KeyCode::Left, KeyModifiers::ALT);
+        assert_eq!(keymap.translate(alt_left), vec![Action::Undo]);
+        assert!(parse_key("hyper-x").is_err());
+        assert!(parse_action("self-destruct").is_err());
+    }
+}
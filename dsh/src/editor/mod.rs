@@ -0,0 +1,738 @@
+//! The interactive line editor: raw-mode input on top of crossterm, with
+//! emacs and vi keybindings, a kill ring, multi-line input and incremental
+//! history search.
+
+mod buffer;
+mod completion;
+mod keymap;
+
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{queue, tty::IsTty};
+
+use crate::internals::shell::History;
+
+use buffer::{Buffer, KillRing, Motion};
+use keymap::Keymap;
+
+pub use completion::{Candidate, Completer, Completion};
+pub use keymap::{parse_action, parse_key, Action, EditMode};
+
+/// Shown in front of every line after the first, unless the shell says
+/// otherwise.
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// Past this many completions, ask before listing them all.
+const COMPLETION_QUERY_ITEMS: usize = 100;
+
+/// What reading a line came back with.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// The user pressed Ctrl+C.
+    Interrupted,
+    /// Ctrl+D on an empty line, or the end of a non-interactive stdin.
+    Eof,
+}
+
+/// Reads command lines. The kill ring and keymap state live here so they
+/// carry over from one line to the next.
+pub struct Editor {
+    keymap: Keymap,
+    kill_ring: KillRing,
+    /// Text the next line starts out with.
+    preload: Option<String>,
+    /// Shown in front of every line after the first.
+    continuation: String,
+    /// Shown at the right end of the first line, while there is room.
+    right: String,
+}
+
+impl Editor {
+    pub fn new(mode: EditMode) -> Self {
+        Editor {
+            keymap: Keymap::new(mode),
+            kill_ring: KillRing::default(),
+            preload: None,
+            continuation: CONTINUATION_PROMPT.to_string(),
+            right: String::new(),
+        }
+    }
+
+    /// Sets what is shown in front of every line after the first, as
+    /// `$PS2` says.
+    pub fn set_continuation_prompt(&mut self, prompt: &str) {
+        self.continuation = prompt.to_string();
+    }
+
+    /// Sets what is shown at the right end of the line being typed, as
+    /// `$RPS1` says. Typing over it hides it.
+    pub fn set_right_prompt(&mut self, prompt: &str) {
+        self.right = prompt.to_string();
+    }
+
+    /// Switches to `mode`, with `bindings` on top of its own.
+    pub fn set_keymap(&mut self, mode: EditMode, bindings: &[(KeyEvent, Action)]) {
+        self.keymap = Keymap::new(mode);
+        for (key, action) in bindings {
+            self.keymap.bind(*key, *action);
+        }
+    }
+
+    /// Starts the next line with `text` already typed, for the user to run
+    /// or edit.
+    pub fn preload(&mut self, text: &str) {
+        self.preload = Some(text.to_string());
+    }
+
+    /// Shows `prompt` and reads one command, which may span several lines:
+    /// Enter only runs the input once `is_complete` accepts it, and Tab asks
+    /// `completer`. When stdin isn't a terminal, lines are read as they come,
+    /// until they make up a complete command.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &History,
+        completer: &dyn Completer,
+        is_complete: impl Fn(&str) -> bool,
+    ) -> io::Result<Input> {
+        let preload = self.preload.take();
+        if !io::stdin().is_tty() {
+            return read_plain(prompt, &self.continuation, &is_complete);
+        }
+
+        self.keymap.reset();
+        let mut buffer = Buffer::default();
+        if let Some(text) = preload {
+            buffer.set(&text);
+        }
+        let _raw = RawMode::enable()?;
+        let mut session = Session {
+            editor: self,
+            history,
+            completer,
+            buffer,
+            history_index: None,
+            draft: String::new(),
+            search: None,
+            undo: Vec::new(),
+            last: Last::Other,
+            screen: Screen::default(),
+        };
+        session.run(prompt, &is_complete)
+    }
+}
+
+/// Reads lines without any editing, for input from a pipe or file.
+fn read_plain(
+    prompt: &str,
+    continuation: &str,
+    is_complete: &dyn Fn(&str) -> bool,
+) -> io::Result<Input> {
+    let mut input = String::new();
+    let mut prompt = prompt;
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            // What was read so far still runs, to report what it lacks.
+            return Ok(if input.is_empty() {
+                Input::Eof
+            } else {
+                Input::Line(input)
+            });
+        }
+        if !input.is_empty() {
+            input.push('\n');
+        }
+        input.push_str(line.strip_suffix('\n').unwrap_or(&line));
+        if is_complete(&input) {
+            return Ok(Input::Line(input));
+        }
+        prompt = continuation;
+    }
+}
+
+/// Keeps the terminal in raw mode for as long as it is alive.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// What the previous action was, for the actions that depend on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Last {
+    Other,
+    Insert,
+    Kill,
+    Complete,
+    /// The range the last yank inserted, which `M-y` replaces.
+    Yank(Range<usize>),
+}
+
+/// An incremental history search in progress.
+struct Search {
+    query: String,
+    /// The history entry that matched, if any.
+    found: Option<usize>,
+    /// The line as it was before searching, restored on cancel.
+    saved: Buffer,
+}
+
+/// Reading one line.
+struct Session<'a> {
+    editor: &'a mut Editor,
+    history: &'a History,
+    completer: &'a dyn Completer,
+    buffer: Buffer,
+    /// The history entry being shown, when browsing with Up and Down.
+    history_index: Option<usize>,
+    /// The line being typed before browsing the history.
+    draft: String,
+    search: Option<Search>,
+    undo: Vec<Buffer>,
+    last: Last,
+    screen: Screen,
+}
+
+impl Session<'_> {
+    fn run(&mut self, prompt: &str, is_complete: &dyn Fn(&str) -> bool) -> io::Result<Input> {
+        let mut out = io::stdout();
+        self.redraw(&mut out, prompt)?;
+
+        loop {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                Event::Resize(..) => {
+                    self.redraw(&mut out, prompt)?;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if self.search.is_some() {
+                match self.search_key(key) {
+                    SearchKey::Handled | SearchKey::Cancel => {
+                        self.redraw(&mut out, prompt)?;
+                        continue;
+                    }
+                    SearchKey::Accept => {
+                        let line = self.buffer.text().to_string();
+                        return self.finish(&mut out, prompt, Input::Line(line));
+                    }
+                    SearchKey::Pass => {}
+                }
+            }
+
+            for action in self.editor.keymap.translate(key) {
+                match action {
+                    Action::Accept if is_complete(self.buffer.text()) => {
+                        let line = self.buffer.text().to_string();
+                        return self.finish(&mut out, prompt, Input::Line(line));
+                    }
+                    Action::Accept | Action::Newline => self.apply(Action::Insert('\n')),
+                    Action::Interrupt => {
+                        return self.finish(&mut out, prompt, Input::Interrupted);
+                    }
+                    Action::EndOfFile if self.buffer.is_empty() => {
+                        return self.finish(&mut out, prompt, Input::Eof);
+                    }
+                    Action::EndOfFile => self.apply(Action::Delete(Motion::Right)),
+                    Action::Complete => self.complete(&mut out, prompt)?,
+                    Action::ClearScreen => {
+                        queue!(out, Clear(ClearType::All), crossterm::cursor::MoveTo(0, 0))?;
+                        self.screen = Screen::default();
+                    }
+                    action => self.apply(action),
+                }
+            }
+
+            if self.editor.keymap.vi_command() && self.buffer.at_line_end() {
+                self.buffer.move_left_in_line();
+            }
+            self.redraw(&mut out, prompt)?;
+        }
+    }
+
+    fn apply(&mut self, action: Action) {
+        let modifies = !matches!(
+            action,
+            Action::Move(_)
+                | Action::Copy(_)
+                | Action::Undo
+                | Action::HistoryPrev
+                | Action::HistoryNext
+                | Action::SearchHistory
+                | Action::InsertMode
+                | Action::CommandMode
+        );
+        // A run of typed characters is undone in one go.
+        let typing = matches!(action, Action::Insert(_)) && self.last == Last::Insert;
+        if modifies && !typing {
+            self.undo.push(self.buffer.clone());
+        }
+
+        let last = match action {
+            Action::Insert(c) => {
+                self.buffer.insert(c.encode_utf8(&mut [0; 4]));
+                Last::Insert
+            }
+            Action::Move(motion) => {
+                self.buffer.move_to(motion);
+                Last::Other
+            }
+            Action::Delete(motion) => {
+                self.buffer.remove(self.buffer.range(motion));
+                Last::Other
+            }
+            Action::Kill(motion) => {
+                let range = self.buffer.range(motion);
+                let backward = range.start < self.buffer.cursor();
+                let killed = self.buffer.remove(range);
+                let append = self.last == Last::Kill;
+                self.editor.kill_ring.kill(killed, append, backward);
+                Last::Kill
+            }
+            Action::Copy(motion) => {
+                let range = self.buffer.range(motion);
+                let copied = self.buffer.text()[range].to_string();
+                self.editor.kill_ring.kill(copied, false, false);
+                Last::Other
+            }
+            Action::Yank | Action::PutAfter => {
+                if action == Action::PutAfter && !self.buffer.is_empty() {
+                    self.buffer.move_to(Motion::Right);
+                }
+                match self.editor.kill_ring.yank() {
+                    Some(text) => {
+                        let start = self.buffer.cursor();
+                        self.buffer.insert(text);
+                        Last::Yank(start..self.buffer.cursor())
+                    }
+                    None => Last::Other,
+                }
+            }
+            Action::YankPop => match (&self.last, self.editor.kill_ring.rotate()) {
+                (Last::Yank(range), Some(text)) => {
+                    let text = text.to_string();
+                    let range = range.clone();
+                    self.buffer.remove(range.clone());
+                    self.buffer.insert(&text);
+                    Last::Yank(range.start..range.start + text.len())
+                }
+                _ => Last::Other,
+            },
+            Action::ReplaceChar(c) => {
+                self.buffer.replace_char(c);
+                Last::Other
+            }
+            Action::ToggleCase => {
+                self.buffer.toggle_case();
+                Last::Other
+            }
+            Action::TransposeChars => {
+                self.buffer.transpose_chars();
+                Last::Other
+            }
+            Action::Undo => {
+                if let Some(previous) = self.undo.pop() {
+                    self.buffer = previous;
+                }
+                Last::Other
+            }
+            Action::HistoryPrev => {
+                if !self.buffer.move_line(true) {
+                    self.history_prev();
+                }
+                Last::Other
+            }
+            Action::HistoryNext => {
+                if !self.buffer.move_line(false) {
+                    self.history_next();
+                }
+                Last::Other
+            }
+            Action::SearchHistory => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                    saved: self.buffer.clone(),
+                });
+                Last::Other
+            }
+            Action::CommandMode => {
+                self.buffer.move_left_in_line();
+                Last::Other
+            }
+            _ => Last::Other,
+        };
+        self.last = last;
+    }
+
+    /// Tab: completes the word up to the cursor as far as the candidates
+    /// agree, and lists them when pressed again without getting further.
+    fn complete(&mut self, out: &mut impl Write, prompt: &str) -> io::Result<()> {
+        let cursor = self.buffer.cursor();
+        let completion = self.completer.complete(self.buffer.text(), cursor);
+        let listing = self.last == Last::Complete;
+        self.last = Last::Complete;
+
+        let candidates = &completion.candidates;
+        let start = completion.start.min(cursor);
+        let replacement = match candidates.as_slice() {
+            [] => None,
+            [only] if only.finished => Some(format!("{} ", only.replacement)),
+            [only] => Some(only.replacement.clone()),
+            _ => {
+                let prefix = completion::common_prefix(candidates);
+                (prefix.len() > cursor - start).then(|| prefix.to_string())
+            }
+        };
+
+        match replacement {
+            Some(replacement) if replacement != self.buffer.text()[start..cursor] => {
+                self.undo.push(self.buffer.clone());
+                self.buffer.remove(start..cursor);
+                self.buffer.insert(&replacement);
+            }
+            _ if listing && candidates.len() > 1 => self.list(out, prompt, candidates)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Prints the candidates below the input; the prompt is drawn again
+    /// underneath.
+    fn list(
+        &mut self,
+        out: &mut impl Write,
+        prompt: &str,
+        candidates: &[Candidate],
+    ) -> io::Result<()> {
+        let cursor = self.buffer.cursor();
+        self.buffer.set_cursor(self.buffer.text().len());
+        self.redraw(out, prompt)?;
+        self.buffer.set_cursor(cursor);
+        write!(out, "\r\n")?;
+        self.screen = Screen::default();
+
+        if candidates.len() > COMPLETION_QUERY_ITEMS {
+            write!(
+                out,
+                "Display all {} possibilities? (y or n)",
+                candidates.len()
+            )?;
+            out.flush()?;
+            let answer = loop {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Release {
+                        break key.code;
+                    }
+                }
+            };
+            write!(out, "\r\n")?;
+            if !matches!(answer, KeyCode::Char('y' | 'Y' | ' ')) {
+                return Ok(());
+            }
+        }
+
+        let items: Vec<&str> = candidates.iter().map(|c| c.display.as_str()).collect();
+        for line in completion::columns(&items, terminal_width()) {
+            write!(out, "{}\r\n", line)?;
+        }
+        Ok(())
+    }
+
+    fn history_prev(&mut self) {
+        let index = match self.history_index {
+            None => self.history.entries().len().checked_sub(1),
+            Some(index) => index.checked_sub(1),
+        };
+        if let Some(index) = index {
+            if self.history_index.is_none() {
+                self.draft = self.buffer.text().to_string();
+            }
+            self.history_index = Some(index);
+            self.buffer.set(&self.history.entries()[index].command);
+        }
+    }
+
+    fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        match self.history.entries().get(index + 1) {
+            Some(entry) => {
+                self.history_index = Some(index + 1);
+                self.buffer.set(&entry.command);
+            }
+            None => {
+                self.history_index = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.buffer.set(&draft);
+            }
+        }
+    }
+
+    /// Handles a key during Ctrl+R. Typing refines the search and Ctrl+R
+    /// again finds an older match; Esc or Ctrl+G give up and put the line
+    /// back. Any other key keeps the match and is then handled as usual.
+    fn search_key(&mut self, key: KeyEvent) -> SearchKey {
+        let Some(search) = &mut self.search else {
+            return SearchKey::Pass;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let entries = self.history.entries();
+
+        let older_than = match key.code {
+            KeyCode::Char('r') if ctrl => search.found.unwrap_or(entries.len()),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                search.query.push(c);
+                search.found.map_or(entries.len(), |found| found + 1)
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                entries.len()
+            }
+            KeyCode::Esc => {
+                self.buffer = search.saved.clone();
+                self.search = None;
+                return SearchKey::Cancel;
+            }
+            KeyCode::Char('g') if ctrl => {
+                self.buffer = search.saved.clone();
+                self.search = None;
+                return SearchKey::Cancel;
+            }
+            KeyCode::Enter => {
+                self.search = None;
+                return SearchKey::Accept;
+            }
+            _ => {
+                self.search = None;
+                return SearchKey::Pass;
+            }
+        };
+
+        let query = &search.query;
+        if query.is_empty() {
+            search.found = None;
+            self.buffer = search.saved.clone();
+            return SearchKey::Handled;
+        }
+        let found = entries[..older_than.min(entries.len())]
+            .iter()
+            .rposition(|entry| entry.command.contains(query.as_str()));
+        if let Some(index) = found {
+            search.found = Some(index);
+            let command = &entries[index].command;
+            let at = command.find(query.as_str()).unwrap_or(0);
+            self.buffer.set(command);
+            self.buffer.set_cursor(at);
+        }
+        SearchKey::Handled
+    }
+
+    fn redraw(&mut self, out: &mut impl Write, prompt: &str) -> io::Result<()> {
+        let search_prompt;
+        let prompt = match &self.search {
+            Some(search) => {
+                let failed = search.found.is_none() && !search.query.is_empty();
+                search_prompt = format!(
+                    "({}reverse-i-search)`{}': ",
+                    if failed { "failed " } else { "" },
+                    search.query
+                );
+                &search_prompt
+            }
+            None => prompt,
+        };
+        let editor = &self.editor;
+        self.screen.render(
+            out,
+            (prompt, &editor.continuation, &editor.right),
+            self.buffer.text(),
+            self.buffer.cursor(),
+        )
+    }
+
+    /// Leaves the cursor below the input, ready for the command's output.
+    fn finish(&mut self, out: &mut impl Write, prompt: &str, input: Input) -> io::Result<Input> {
+        self.search = None;
+        let end = self.buffer.text().len();
+        self.buffer.set_cursor(end);
+        self.redraw(out, prompt)?;
+        if input == Input::Interrupted {
+            write!(out, "^C")?;
+        }
+        write!(out, "\r\n")?;
+        out.flush()?;
+        Ok(input)
+    }
+}
+
+enum SearchKey {
+    /// The key was part of the search.
+    Handled,
+    /// Enter: run the line that was found.
+    Accept,
+    /// Esc or Ctrl+G: the line is back as it was.
+    Cancel,
+    /// The search ended and the key still needs handling.
+    Pass,
+}
+
+/// What was drawn last, so it can be redrawn in place.
+#[derive(Debug, Default)]
+struct Screen {
+    /// The row the cursor is on, counted from the prompt's first row.
+    cursor_row: u16,
+}
+
+impl Screen {
+    /// Draws the prompts, the first one, the one for every line after it
+    /// and the one on the right, with `text` and the cursor.
+    fn render(
+        &mut self,
+        out: &mut impl Write,
+        (prompt, continuation, right): (&str, &str, &str),
+        text: &str,
+        cursor: usize,
+    ) -> io::Result<()> {
+        let width = terminal_width();
+
+        if self.cursor_row > 0 {
+            queue!(out, MoveUp(self.cursor_row))?;
+        }
+        queue!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))?;
+
+        write!(out, "{}", prompt.replace('\n', "\r\n"))?;
+        let start = advance((0, 0), prompt, width);
+
+        // The right prompt ends a column short of the margin, and only
+        // while the first line of input stays clear of it.
+        let right_width = advance((0, 0), right, usize::MAX).1;
+        let first_line = text.split('\n').next().unwrap_or_default();
+        let (row, column) = advance(start, first_line, width);
+        if !right.is_empty() && row == start.0 && column + right_width + 2 <= width {
+            queue!(out, MoveToColumn((width - right_width - 1) as u16))?;
+            write!(out, "{}", right)?;
+            queue!(out, MoveToColumn(start.1 as u16))?;
+        }
+
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                write!(out, "\r\n{}", continuation)?;
+            }
+            write!(out, "{}", line)?;
+        }
+
+        let (end_row, end_column) = layout(start, continuation, text, text.len(), width);
+        let (row, column) = layout(start, continuation, text, cursor, width);
+        if end_column == 0 && end_row > 0 && !text.ends_with('\n') {
+            // The terminal holds the cursor at the right margin until
+            // something else is printed; move it down for real.
+            write!(out, " \r")?;
+        }
+        if end_row > row {
+            queue!(out, MoveUp((end_row - row) as u16))?;
+        }
+        queue!(out, MoveToColumn(column as u16))?;
+        out.flush()?;
+
+        self.cursor_row = row as u16;
+        Ok(())
+    }
+}
+
+/// The terminal's width, or a guess when it doesn't say.
+fn terminal_width() -> usize {
+    match terminal::size() {
+        Ok((columns, _)) if columns > 0 => columns as usize,
+        _ => 80,
+    }
+}
+
+/// The screen position after `text[..cursor]`, starting from `start` and
+/// counting the `continuation` prompt in front of every new line.
+fn layout(
+    start: (usize, usize),
+    continuation: &str,
+    text: &str,
+    cursor: usize,
+    width: usize,
+) -> (usize, usize) {
+    let mut position = start;
+    for (index, line) in text[..cursor].split('\n').enumerate() {
+        if index > 0 {
+            position = advance((position.0 + 1, 0), continuation, width);
+        }
+        position = advance(position, line, width);
+    }
+    position
+}
+
+/// Moves `(row, column)` past `text`, wrapping at `width` and skipping
+/// escape sequences, which take no room on screen.
+fn advance((mut row, mut column): (usize, usize), text: &str, width: usize) -> (usize, usize) {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // CSI sequences end with a byte in `@`..=`~`.
+                if chars.next() == Some('[') {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\n' => {
+                row += 1;
+                column = 0;
+            }
+            _ => {
+                column += 1;
+                if column == width {
+                    row += 1;
+                    column = 0;
+                }
+            }
+        }
+    }
+    (row, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_wrapped_and_multi_line_input() {
+        let prompt = "\x1b[34mdsh % \x1b[0m";
+        let start = advance((0, 0), prompt, 10);
+        assert_eq!(start, (0, 6));
+
+        assert_eq!(layout(start, "> ", "ls -la", 6, 10), (1, 2));
+        let text = "for f in *\ndo";
+        assert_eq!(layout(start, "> ", text, text.len(), 10), (2, 4));
+        assert_eq!(layout(start, "... ", text, text.len(), 10), (2, 6));
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// A complete input line: and-or lists separated by `;`, `&` or newlines.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    pub and_or: AndOrList,
+    /// Terminated by `&` rather than `;` or a newline.
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    /// Prefixed with `!`.
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+/// One stage of a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(Compound),
+    Function(FunctionDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SimpleCommand {
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A compound command, with redirections that apply to all of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compound {
+    pub kind: CompoundKind,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompoundKind {
+    /// `{ list; }`
+    Group(Program),
+    /// `if list; then list; [elif list; then list;] ... [else list;] fi`:
+    /// each condition with the body it guards, and the `else` body.
+    If {
+        branches: Vec<(Program, Program)>,
+        otherwise: Option<Program>,
+    },
+    /// `while list; do list; done`, or `until` when the loop runs while the
+    /// condition fails.
+    Loop {
+        until: bool,
+        condition: Program,
+        body: Program,
+    },
+    /// `for name [in word ...]; do list; done`. Without `in`, the loop goes
+    /// over the positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: Program,
+    },
+    /// `case word in [(]pattern [| pattern] ...) list;; ... esac`
+    Case { word: Word, items: Vec<CaseItem> },
+}
+
+/// One `pattern | pattern) list;;` of a `case`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: Program,
+}
+
+/// `name() compound-command`. The body is shared with the shell's table
+/// of functions once the definition has run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: Rc<Compound>,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", item.and_or)?;
+            let last = index == self.items.len() - 1;
+            match (item.background, last) {
+                (true, _) => f.write_str(" &")?,
+                (false, false) => f.write_str(";")?,
+                (false, true) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AndOrList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let connector = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            write!(f, " {} {}", connector, pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            f.write_str("! ")?;
+        }
+        for (index, command) in self.commands.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(command) => write!(f, "{}", command),
+            Command::Compound(compound) => write!(f, "{}", compound),
+            Command::Function(function) => write!(f, "{}() {}", function.name, function.body),
+        }
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CompoundKind::Group(body) => write!(f, "{{ {} }}", Terminated(body))?,
+            CompoundKind::If {
+                branches,
+                otherwise,
+            } => {
+                for (index, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if index == 0 { "if" } else { "elif" };
+                    write!(
+                        f,
+                        "{} {} then {} ",
+                        keyword,
+                        Terminated(condition),
+                        Terminated(body)
+                    )?;
+                }
+                if let Some(body) = otherwise {
+                    write!(f, "else {} ", Terminated(body))?;
+                }
+                f.write_str("fi")?;
+            }
+            CompoundKind::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(
+                    f,
+                    "{} {} do {} done",
+                    keyword,
+                    Terminated(condition),
+                    Terminated(body)
+                )?;
+            }
+            CompoundKind::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    f.write_str(" in")?;
+                    for word in words {
+                        write!(f, " {}", word.raw())?;
+                    }
+                }
+                write!(f, "; do {} done", Terminated(body))?;
+            }
+            CompoundKind::Case { word, items } => {
+                write!(f, "case {} in", word.raw())?;
+                for item in items {
+                    let patterns: Vec<&str> = item.patterns.iter().map(Word::raw).collect();
+                    write!(f, " {})", patterns.join(" | "))?;
+                    if !item.body.items.is_empty() {
+                        write!(f, " {}", item.body)?;
+                    }
+                    f.write_str(";;")?;
+                }
+                f.write_str(" esac")?;
+            }
+        }
+        for redirect in &self.redirects {
+            write!(f, " {}", redirect)?;
+        }
+        Ok(())
+    }
+}
+
+/// A list inside a compound command, with the `;` that has to end it
+/// before the next reserved word unless it ends in `&`.
+struct Terminated<'a>(&'a Program);
+
+impl fmt::Display for Terminated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let background = self.0.items.last().is_some_and(|item| item.background);
+        let separator = if background { "" } else { ";" };
+        write!(f, "{}{}", self.0, separator)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = self.words.iter().map(|word| word.raw().to_string());
+        let redirects = self.redirects.iter().map(|redirect| redirect.to_string());
+        let parts: Vec<String> = words.chain(redirects).collect();
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// A word as written in the input, quotes included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word(pub String);
+
+impl Word {
+    pub fn raw(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    /// Explicit file descriptor, as the `2` in `2>err.log`.
+    pub fd: Option<u32>,
+    pub kind: RedirectKind,
+    pub target: Word,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(fd) = self.fd {
+            write!(f, "{}", fd)?;
+        }
+        write!(f, "{}{}", self.kind.as_str(), self.target.raw())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `&>`
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+    /// `<<<`
+    HereString,
+}
+
+impl RedirectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RedirectKind::Input => "<",
+            RedirectKind::Output => ">",
+            RedirectKind::Append => ">>",
+            RedirectKind::Clobber => ">|",
+            RedirectKind::ReadWrite => "<>",
+            RedirectKind::DupInput => "<&",
+            RedirectKind::DupOutput => ">&",
+            RedirectKind::OutputAll => "&>",
+            RedirectKind::AppendAll => "&>>",
+            RedirectKind::HereString => "<<<",
+        }
+    }
+}
@@ -1,76 +1,313 @@
-use core::time;
-use std::process::{ChildStdout, Stdio};
-use tokio::process::Command;
-use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    sync::mpsc,
-};
-
-use ai_engine::AIEngine;
+use std::fs::File;
+use std::io;
+use std::process::Command;
+use std::rc::Rc;
+
 use anyhow::{Error, Result};
-use regex::Regex;
 
-enum Message {
-    Data(String),
-    Done,
+use crate::builtins::{self, Builtin};
+
+use super::ast::{self, Compound, FunctionDefinition};
+use super::diagnosis::FailureContext;
+use super::exec;
+use super::expand::Expanded;
+use super::jobs::{Group, Job, Pid, Process};
+use super::redirect::{self, Capture, FdTable, Sink, Slot, TeeEnd};
+use super::shell::ShellState;
+use super::status::ExitStatus;
+use super::subshell::{self, Fork};
+
+/// The result of running a command, with the captured context if it failed.
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: ExitStatus,
+    pub failure: Option<FailureContext>,
 }
 
-pub async fn run_single_command(command: &str, engine: &mut AIEngine) -> Result<(), Error> {
-    let commands: Vec<&str> = command.split_whitespace().map(|c| c.trim()).collect();
-    let program = commands[0];
-    let args = &commands[1..];
+impl Outcome {
+    pub fn new(status: ExitStatus) -> Self {
+        Outcome {
+            status,
+            failure: None,
+        }
+    }
+}
 
-    let mut child = Command::new(program)
-        .args(args)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+/// One command of a pipeline, ready to run.
+pub struct Stage<'a> {
+    pub command: &'a ast::Command,
+    /// A simple command's words, assignments and redirections after
+    /// expansion; for a compound command only its redirections.
+    pub expanded: Expanded,
+}
 
-    let stdout = child.stdout.take().expect("Error getting stdout");
-    let stderr = child.stderr.take().unwrap();
+/// A command the shell runs itself rather than in a child process.
+#[derive(Clone)]
+pub enum InShell<'a> {
+    Builtin(&'static dyn Builtin),
+    Function(Rc<Compound>),
+    Compound(&'a Compound),
+    Definition(&'a FunctionDefinition),
+}
 
-    let mut reader = BufReader::new(stdout).lines();
-    let mut err_reader = BufReader::new(stderr).lines();
+impl<'a> Stage<'a> {
+    /// How the shell runs the stage itself, or `None` if it is a program
+    /// to start. Functions come before builtins of the same name.
+    pub fn in_shell(&self, state: &ShellState) -> Option<InShell<'a>> {
+        match self.command {
+            ast::Command::Compound(compound) => Some(InShell::Compound(compound)),
+            ast::Command::Function(definition) => Some(InShell::Definition(definition)),
+            ast::Command::Simple(_) => {
+                let name = self.expanded.argv.first()?;
+                match state.functions.get(name) {
+                    Some(body) => Some(InShell::Function(body.clone())),
+                    None => builtins::lookup(name).map(InShell::Builtin),
+                }
+            }
+        }
+    }
+}
 
-    let (mut tx, mut rx) = mpsc::channel::<String>(32);
+pub async fn run_single_command(
+    stage: &Stage<'_>,
+    command_line: String,
+    state: &mut ShellState,
+    background: bool,
+) -> Result<Outcome, Error> {
+    run_piped_commands(std::slice::from_ref(stage), command_line, state, background).await
+}
 
-    // Ensure the child process is spawned in the runtime so it can
-    // make progress on its own while we await for any output.
-    tokio::spawn(async move {
-        let _ = child
-            .wait()
-            .await
-            .expect("child process encountered an error");
-    });
+/// Runs a builtin, function or compound command on its own, in the shell,
+/// with its redirections applied to the shell's descriptors while it runs.
+/// Assignments in front of it stay set.
+pub async fn run_alone(
+    in_shell: InShell<'_>,
+    command: &Expanded,
+    state: &mut ShellState,
+) -> ExitStatus {
+    let mut fds = FdTable::new(Slot::Inherit, Slot::Inherit, Slot::Inherit);
+    let applied = command
+        .redirects
+        .iter()
+        .try_for_each(|(redirect, target)| fds.apply(redirect, target));
+    if let Err(err) = applied {
+        report!("{}", err);
+        return ExitStatus::FAILURE;
+    }
 
-    let mut prompt = String::new();
+    match run_in_shell(in_shell, command, &fds, state).await {
+        Ok(status) => status,
+        Err(err) => {
+            report!("{}", err);
+            ExitStatus::FAILURE
+        }
+    }
+}
 
-    let error_re = Regex::new(r"(?i)error")?;
+/// Points the shell's descriptors at `fds`, runs the command and puts them
+/// back. Tees are started first, so that the command never waits for them,
+/// and finished before returning, so that the next command finds all of
+/// the output in the file.
+async fn run_in_shell(
+    in_shell: InShell<'_>,
+    command: &Expanded,
+    fds: &FdTable,
+    state: &mut ShellState,
+) -> Result<ExitStatus, Error> {
+    for (name, value) in &command.assignments {
+        state.variables.set(name, value)?;
+    }
+    let mut redirect = fds.redirect_shell()?;
+    let tees: Vec<_> = redirect.tees.drain(..).map(TeeEnd::spawn).collect();
+    let status = match in_shell {
+        InShell::Builtin(builtin) => builtin.run(&command.argv, state),
+        InShell::Function(body) => Box::pin(exec::call_function(&body, &command.argv, state)).await,
+        InShell::Compound(compound) => exec::run_compound(compound, state).await,
+        InShell::Definition(definition) => exec::define_function(definition, state),
+    };
+    // Putting the descriptors back closes the shell's ends of the pipes,
+    // which ends the copies.
+    drop(redirect);
+    for (_, tee) in tees {
+        tee.await?;
+    }
+    Ok(status)
+}
 
-    tokio::spawn(async move {
-        while let Ok(Some(line)) = reader.next_line().await {
-            println!("{}", line);
-            if error_re.is_match(&line) {
-                if let Err(_) = tx.send(line).await {
-                    println!("receiver dropped");
+/// Runs a builtin, function or compound command in a forked copy of the
+/// shell, with `fds` as its descriptors.
+fn fork_in_shell(
+    in_shell: InShell<'_>,
+    command: &Expanded,
+    fds: &FdTable,
+    state: &mut ShellState,
+    group: Group,
+) -> io::Result<(Pid, Vec<TeeEnd>)> {
+    match subshell::fork(state, fds, group)? {
+        Fork::Parent { pid, tees } => Ok((pid, tees)),
+        Fork::Child => {
+            // The copy's own descriptors are already `fds`.
+            let fds = FdTable::new(Slot::Inherit, Slot::Inherit, Slot::Inherit);
+            let status = subshell::run(async {
+                match run_in_shell(in_shell, command, &fds, state).await {
+                    Ok(status) => status,
+                    Err(err) => {
+                        report!("{}", err);
+                        ExitStatus::FAILURE
+                    }
                 }
-            }
+            });
+            subshell::exit(state, status)
         }
-    });
+    }
+}
 
+/// Runs `a | b | c`, wiring each stage's stdout into the next stage's stdin
+/// before applying the stage's own redirections. All stages form one job.
+///
+/// In the foreground, every stage's stderr and the last stage's stdout are
+/// streamed to the terminal (or the file they were redirected to) as they
+/// arrive and captured on the side, through a pseudo-terminal where they
+/// go to a terminal. The last stage's stdout is left alone when it is the
+/// terminal. If any stage fails, the captured output comes back as a
+/// [`FailureContext`], so a stage failing in the middle of the pipeline
+/// can still be diagnosed. The reported status is the last stage's.
+///
+/// In the background, output goes straight to the terminal and the job is
+/// left in `jobs`.
+///
+/// Each stage gets the exported variables as its environment, plus the
+/// assignments written in front of it.
+///
+/// Builtins, functions and compound commands run in forked copies of the
+/// shell, so that they run alongside the other stages and nothing they
+/// change outlives the pipeline.
+pub async fn run_piped_commands(
+    stages: &[Stage<'_>],
+    command_line: String,
+    state: &mut ShellState,
+    background: bool,
+) -> Result<Outcome, Error> {
+    let mut processes = Vec::with_capacity(stages.len());
+    let mut pgid = None;
+    let mut tees = Vec::new();
+    let mut errors = String::new();
+    let mut next_stdin: Option<File> = None;
+    // Full-screen programs set the terminal's modes through their stdout,
+    // which only works on the terminal itself.
+    let stdout_is_terminal = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
 
-    while let Some(msg) = rx.recv().await {
-        prompt.push_str(&msg);
+    for (index, stage) in stages.iter().enumerate() {
+        let is_first = index == 0;
+        let is_last = index == stages.len() - 1;
+        let command = &stage.expanded;
 
-    }
+        let stdin = match next_stdin.take() {
+            Some(reader) => Slot::file(reader),
+            // Without job control a background job can't be kept off the
+            // terminal, so it gets nothing to read.
+            None if is_first && (!background || state.jobs.job_control()) => Slot::Inherit,
+            // The previous stage never started, so there is nothing to read.
+            None => Slot::file(File::open("/dev/null")?),
+        };
+        let stdout = if !is_last {
+            let (reader, writer) = redirect::pipe()?;
+            next_stdin = Some(reader);
+            Slot::file(writer)
+        } else if background || stdout_is_terminal {
+            Slot::Inherit
+        } else {
+            Slot::tee(Sink::Stdout, Capture::Stdout)
+        };
+        let stderr = if background {
+            Slot::Inherit
+        } else {
+            Slot::tee(Sink::Stderr, Capture::Stderr)
+        };
 
-    if let Err(err) = engine.inference_openai(&prompt).await {
-        println!("error with generating a fix. {:?}", err)
+        let mut fds = FdTable::new(stdin, stdout, stderr);
+        let applied = command
+            .redirects
+            .iter()
+            .try_for_each(|(redirect, target)| fds.apply(redirect, target));
+        if let Err(err) = applied {
+            report(&mut errors, &err.to_string());
+            processes.push(Process::finished(ExitStatus::FAILURE));
+            continue;
+        }
+
+        let parts = &command.argv;
+        let spawned = if let Some(in_shell) = stage.in_shell(state) {
+            let group = Group::Job {
+                pgid,
+                foreground: !background,
+            };
+            fork_in_shell(in_shell, command, &fds, state, group).map_err(|err| {
+                report(&mut errors, &err.to_string());
+                ExitStatus::FAILURE
+            })
+        } else if parts.is_empty() {
+            // Only redirections, as in `> file`: opening them was the point.
+            processes.push(Process::finished(ExitStatus::SUCCESS));
+            continue;
+        } else {
+            let mut child = Command::new(&parts[0]);
+            child
+                .args(&parts[1..])
+                .env_clear()
+                .envs(state.variables.exported())
+                .envs(
+                    command
+                        .assignments
+                        .iter()
+                        .map(|(name, value)| (name, value)),
+                );
+            let prepared = fds.configure(&mut child)?;
+            state.jobs.prepare(&mut child, pgid, !background);
+            child
+                .spawn()
+                .map(|child| (child.id() as Pid, prepared.tees))
+                .map_err(|err| {
+                    let (message, status) = match err.kind() {
+                        io::ErrorKind::NotFound => {
+                            ("command not found".to_string(), ExitStatus::NOT_FOUND)
+                        }
+                        _ => (err.to_string(), ExitStatus::NOT_EXECUTABLE),
+                    };
+                    report(&mut errors, &format!("{}: {}", parts[0], message));
+                    status
+                })
+        };
+
+        match spawned {
+            Ok((pid, stage_tees)) => {
+                state.jobs.adopt(pid, &mut pgid);
+                tees.extend(stage_tees.into_iter().map(|tee| tee.spawn()));
+                processes.push(Process::running(pid));
+            }
+            Err(status) => processes.push(Process::finished(status)),
+        }
     }
 
-   
+    let jobs = &mut state.jobs;
+    let job = Job::new(command_line, pgid, processes, tees, errors);
 
-    Ok(())
+    if background {
+        let pid = job.last_pid();
+        let id = jobs.add_background(job);
+        match pid {
+            Some(pid) => println!("[{}] {}", id, pid),
+            None => println!("[{}]", id),
+        }
+        return Ok(Outcome::new(ExitStatus::SUCCESS));
+    }
+
+    jobs.foreground(job, false).await
 }
 
-pub fn run_piped_commands() {}
+/// Prints a shell error and keeps it for the diagnosis context.
+fn report(errors: &mut String, message: &str) {
+    let line = format!("{}\n", exec::error_line(message));
+    eprint!("{}", line);
+    errors.push_str(&line);
+}
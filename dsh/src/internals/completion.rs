@@ -0,0 +1,714 @@
+//! Tab completion: command names from the builtins and `$PATH`, file
+//! names, environment variables, and whole commands from the history when
+//! nothing else fits. Commands can register a [`CompletionSpec`] to
+//! complete their own arguments, as git and cargo do here.
+
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::builtins::{self, which::is_executable};
+use crate::editor::{Candidate, Completer, Completion};
+
+use super::shell::History;
+use super::variables::Variables;
+
+/// Completes the arguments of one command.
+pub trait CompletionSpec {
+    /// Candidates for `word`, given the words before it on the command line
+    /// with the command name first. Both are unquoted, and so should the
+    /// candidates' replacements be; they are quoted afterwards. `None`
+    /// falls back to completing file names.
+    fn complete(&self, words: &[String], word: &str) -> Option<Vec<Candidate>>;
+}
+
+/// The completion specs, by command name.
+pub struct CompletionSpecs {
+    specs: HashMap<String, Box<dyn CompletionSpec>>,
+}
+
+impl CompletionSpecs {
+    /// The specs that come with the shell.
+    pub fn new() -> Self {
+        let mut specs = CompletionSpecs {
+            specs: HashMap::new(),
+        };
+        specs.register("cd", Directories);
+        specs.register("pushd", Directories);
+        specs.register("git", Git);
+        specs.register("cargo", Cargo);
+        specs
+    }
+
+    pub fn register(&mut self, command: &str, spec: impl CompletionSpec + 'static) {
+        self.specs.insert(command.to_string(), Box::new(spec));
+    }
+
+    fn get(&self, command: &str) -> Option<&dyn CompletionSpec> {
+        self.specs.get(command).map(Box::as_ref)
+    }
+}
+
+/// The [`Completer`] the prompt uses.
+pub struct ShellCompleter<'a> {
+    history: &'a History,
+    specs: &'a CompletionSpecs,
+    vars: &'a Variables,
+}
+
+impl<'a> ShellCompleter<'a> {
+    pub fn new(history: &'a History, specs: &'a CompletionSpecs, vars: &'a Variables) -> Self {
+        ShellCompleter {
+            history,
+            specs,
+            vars,
+        }
+    }
+
+    fn history(&self, line: &str) -> Completion {
+        let mut seen = BTreeSet::new();
+        let candidates = self
+            .history
+            .entries()
+            .iter()
+            .rev()
+            .map(|entry| entry.command.as_str())
+            .filter(|command| command.starts_with(line) && *command != line)
+            .filter(|command| seen.insert(*command))
+            .map(|command| Candidate::new(command, command, false))
+            .collect();
+        Completion {
+            start: 0,
+            candidates,
+        }
+    }
+}
+
+impl Completer for ShellCompleter<'_> {
+    fn complete(&self, line: &str, pos: usize) -> Completion {
+        let line = &line[..pos];
+        let context = Context::parse(line);
+
+        if context.quote != Some('\'') {
+            if let Some((start, name, braced)) = variable(line) {
+                return Completion {
+                    start,
+                    candidates: variables(self.vars, name, braced),
+                };
+            }
+        }
+
+        let mut candidates = if context.redirect {
+            paths(&context.word, Filter::Any)
+        } else if let Some(command) = context.words.first() {
+            self.specs
+                .get(command)
+                .and_then(|spec| spec.complete(&context.words, &context.word))
+                .unwrap_or_else(|| paths(&context.word, Filter::Any))
+        } else if context.word.contains('/') {
+            paths(&context.word, Filter::Executables)
+        } else {
+            commands(self.vars, &context.word)
+        };
+
+        if candidates.is_empty() && !line.trim().is_empty() && !line.contains('\n') {
+            return self.history(line);
+        }
+
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.replacement == b.replacement);
+        for candidate in &mut candidates {
+            candidate.replacement =
+                quote(&candidate.replacement, context.quote, candidate.finished);
+        }
+        Completion {
+            start: context.start,
+            candidates,
+        }
+    }
+}
+
+/// Where the cursor is on the command line.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Context {
+    /// The words of the current command before the one being completed.
+    words: Vec<String>,
+    /// The word being completed, with its quotes removed.
+    word: String,
+    /// Where that word starts in the line.
+    start: usize,
+    /// The quote still open at the cursor, if any.
+    quote: Option<char>,
+    /// Whether the word names a redirection's file.
+    redirect: bool,
+}
+
+impl Context {
+    /// Splits the line up to the cursor the way the lexer would, keeping
+    /// only what completion needs. Unlike the lexer, it copes with a quote
+    /// that is still open.
+    fn parse(line: &str) -> Context {
+        let mut context = Context::default();
+        let mut in_word = false;
+        let mut redirect_next = false;
+        let mut chars = line.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            if !in_word
+                && !matches!(
+                    c,
+                    ' ' | '\t' | '|' | '&' | ';' | '\n' | '(' | ')' | '<' | '>'
+                )
+            {
+                in_word = true;
+                context.start = i;
+                context.redirect = redirect_next;
+                redirect_next = false;
+            }
+
+            match (context.quote, c) {
+                (Some(quote), c) if c == quote => context.quote = None,
+                (Some('"'), '\\') => match chars.next() {
+                    Some((_, c @ ('"' | '\\' | '$' | '`'))) => context.word.push(c),
+                    Some((_, c)) => {
+                        context.word.push('\\');
+                        context.word.push(c);
+                    }
+                    None => context.word.push('\\'),
+                },
+                (Some(_), c) => context.word.push(c),
+                (None, '\\') => {
+                    if let Some((_, c)) = chars.next() {
+                        context.word.push(c);
+                    }
+                }
+                (None, '\'' | '"') => context.quote = Some(c),
+                (None, ' ' | '\t') => context.end_word(&mut in_word),
+                (None, '&') if chars.peek().map(|&(_, c)| c) == Some('>') => {
+                    context.end_word(&mut in_word);
+                }
+                (None, '|' | '&' | ';' | '\n' | '(' | ')') => {
+                    context.end_word(&mut in_word);
+                    context.words.clear();
+                    redirect_next = false;
+                }
+                (None, '<' | '>') => {
+                    // The `2` of `2>` is a file descriptor, not an argument.
+                    if in_word && context.word.chars().all(|c| c.is_ascii_digit()) {
+                        context.word.clear();
+                        in_word = false;
+                    }
+                    context.end_word(&mut in_word);
+                    redirect_next = true;
+                }
+                (None, c) => context.word.push(c),
+            }
+        }
+
+        if !in_word {
+            context.start = line.len();
+            context.redirect = redirect_next;
+        }
+        context
+    }
+
+    fn end_word(&mut self, in_word: &mut bool) {
+        if !*in_word {
+            return;
+        }
+        *in_word = false;
+        let word = std::mem::take(&mut self.word);
+        if !self.redirect {
+            self.words.push(word);
+        }
+        self.redirect = false;
+    }
+}
+
+/// Quotes a completed word for the line, inside `quote` if one is open.
+fn quote(word: &str, quote: Option<char>, finished: bool) -> String {
+    let mut quoted = String::new();
+    match quote {
+        Some('\'') => {
+            quoted.push('\'');
+            quoted.push_str(&word.replace('\'', "'\\''"));
+        }
+        Some(_) => {
+            quoted.push('"');
+            for c in word.chars() {
+                if matches!(c, '"' | '\\' | '$' | '`') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+        }
+        None => {
+            for c in word.chars() {
+                if c.is_whitespace() || "'\"\\$`|&;<>()*?[]{}!#".contains(c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            return quoted;
+        }
+    }
+    if finished {
+        quoted.push(quote.unwrap_or('"'));
+    }
+    quoted
+}
+
+/// A `$NAME` or `${NAME` right before the cursor: where it starts, the
+/// name so far and whether it is braced.
+fn variable(line: &str) -> Option<(usize, &str, bool)> {
+    let dollar = line.rfind('$')?;
+    let rest = &line[dollar + 1..];
+    let (name, braced) = match rest.strip_prefix('{') {
+        Some(name) => (name, true),
+        None => (rest, false),
+    };
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        .then_some((dollar, name, braced))
+}
+
+fn variables(vars: &Variables, prefix: &str, braced: bool) -> Vec<Candidate> {
+    vars.names()
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| {
+            let replacement = if braced {
+                format!("${{{}}}", name)
+            } else {
+                format!("${}", name)
+            };
+            Candidate::new(replacement, name, true)
+        })
+        .collect()
+}
+
+/// Builtins and executables on `$PATH` whose names start with `prefix`.
+fn commands(vars: &Variables, prefix: &str) -> Vec<Candidate> {
+    let mut names: BTreeSet<String> = builtins::all()
+        .map(|builtin| builtin.name())
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| name.to_string())
+        .collect();
+
+    let path = vars.get("PATH").unwrap_or_default();
+    for dir in env::split_paths(path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with(prefix) && !names.contains(&name) && is_executable(&entry.path()) {
+                names.insert(name);
+            }
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| Candidate::new(name.clone(), name, true))
+        .collect()
+}
+
+/// Which files [`paths`] offers. Directories always qualify, since the
+/// file might be inside one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Any,
+    Directories,
+    Executables,
+}
+
+/// Completes `word` as a file name. Relative names are looked up from the
+/// current directory, and a leading `~/` from `$HOME`. Dot files only
+/// show up once the name being completed starts with a dot.
+pub fn paths(word: &str, filter: Filter) -> Vec<Candidate> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+
+    let mut lookup = PathBuf::from(dir);
+    if let Some(rest) = dir.strip_prefix("~/") {
+        match env::var_os("HOME") {
+            Some(home) => lookup = Path::new(&home).join(rest),
+            None => return Vec::new(),
+        }
+    }
+    if lookup.is_relative() {
+        match env::current_dir() {
+            Ok(cwd) => lookup = cwd.join(lookup),
+            Err(_) => return Vec::new(),
+        }
+    }
+    let Ok(entries) = fs::read_dir(&lookup) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let path = entry.path();
+            let is_dir = path.is_dir();
+            let wanted = match filter {
+                Filter::Any => true,
+                Filter::Directories => is_dir,
+                Filter::Executables => is_dir || is_executable(&path),
+            };
+            if !wanted {
+                return None;
+            }
+            Some(if is_dir {
+                Candidate::new(format!("{}{}/", dir, name), format!("{}/", name), false)
+            } else {
+                Candidate::new(format!("{}{}", dir, name), name, true)
+            })
+        })
+        .collect()
+}
+
+/// Candidates for the plain `words` that start with `prefix`.
+pub fn words<'a>(words: impl IntoIterator<Item = &'a str>, prefix: &str) -> Vec<Candidate> {
+    words
+        .into_iter()
+        .filter(|word| word.starts_with(prefix))
+        .map(|word| Candidate::new(word, word, true))
+        .collect()
+}
+
+/// `cd` and `pushd` only go into directories.
+struct Directories;
+
+impl CompletionSpec for Directories {
+    fn complete(&self, _words: &[String], word: &str) -> Option<Vec<Candidate>> {
+        Some(paths(word, Filter::Directories))
+    }
+}
+
+const GIT_COMMANDS: &[&str] = &[
+    "add",
+    "am",
+    "bisect",
+    "blame",
+    "branch",
+    "checkout",
+    "cherry-pick",
+    "clean",
+    "clone",
+    "commit",
+    "config",
+    "describe",
+    "diff",
+    "fetch",
+    "grep",
+    "init",
+    "log",
+    "merge",
+    "mv",
+    "pull",
+    "push",
+    "rebase",
+    "reflog",
+    "remote",
+    "reset",
+    "restore",
+    "revert",
+    "rm",
+    "show",
+    "stash",
+    "status",
+    "submodule",
+    "switch",
+    "tag",
+    "worktree",
+];
+
+/// Git subcommands, then branches, tags and remotes where those go.
+struct Git;
+
+impl CompletionSpec for Git {
+    fn complete(&self, words: &[String], word: &str) -> Option<Vec<Candidate>> {
+        let mut args = words[1..].iter().filter(|word| !word.starts_with('-'));
+        let Some(subcommand) = args.next() else {
+            return Some(self::words(GIT_COMMANDS.iter().copied(), word));
+        };
+        if word.starts_with('-') {
+            return None;
+        }
+
+        match subcommand.as_str() {
+            "push" | "pull" | "fetch" if args.next().is_none() => Some(self::words(
+                git(&["remote"]).iter().map(String::as_str),
+                word,
+            )),
+            "branch" | "switch" | "merge" | "rebase" | "cherry-pick" | "push" | "pull"
+            | "fetch" | "tag" => Some(self::words(git_refs().iter().map(String::as_str), word)),
+            "checkout" | "diff" | "log" | "reset" | "show" => {
+                let mut candidates = self::words(git_refs().iter().map(String::as_str), word);
+                candidates.extend(paths(word, Filter::Any));
+                Some(candidates)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn git_refs() -> Vec<String> {
+    git(&[
+        "for-each-ref",
+        "--format=%(refname:short)",
+        "refs/heads",
+        "refs/remotes",
+        "refs/tags",
+    ])
+}
+
+/// The lines git prints, or nothing when it fails, for example outside a
+/// repository.
+fn git(args: &[&str]) -> Vec<String> {
+    Command::new("git")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+const CARGO_COMMANDS: &[&str] = &[
+    "add",
+    "bench",
+    "build",
+    "check",
+    "clean",
+    "clippy",
+    "doc",
+    "fetch",
+    "fix",
+    "fmt",
+    "init",
+    "install",
+    "metadata",
+    "new",
+    "publish",
+    "remove",
+    "run",
+    "search",
+    "test",
+    "tree",
+    "uninstall",
+    "update",
+];
+
+const CARGO_FLAGS: &[&str] = &[
+    "--all-features",
+    "--all-targets",
+    "--bench",
+    "--bin",
+    "--example",
+    "--features",
+    "--lib",
+    "--manifest-path",
+    "--no-default-features",
+    "--package",
+    "--quiet",
+    "--release",
+    "--target",
+    "--test",
+    "--verbose",
+    "--workspace",
+];
+
+/// Cargo subcommands and flags, and the targets of the package in the
+/// current directory after `--bin`, `--example`, `--test`, `--bench` and
+/// `-p`.
+struct Cargo;
+
+impl CompletionSpec for Cargo {
+    fn complete(&self, words: &[String], word: &str) -> Option<Vec<Candidate>> {
+        if words.len() == 1 && !word.starts_with('-') {
+            return Some(self::words(CARGO_COMMANDS.iter().copied(), word));
+        }
+        let kind = match words.last().map(String::as_str) {
+            Some("--bin") => "bin",
+            Some("--example") => "example",
+            Some("--test") => "test",
+            Some("--bench") => "bench",
+            Some("-p" | "--package") => "package",
+            _ if word.starts_with('-') => {
+                return Some(self::words(CARGO_FLAGS.iter().copied(), word));
+            }
+            _ => return None,
+        };
+        let targets = env::current_dir()
+            .ok()
+            .and_then(|cwd| cargo_root(&cwd))
+            .map(|root| cargo_targets(&root, kind))
+            .unwrap_or_default();
+        Some(self::words(targets.iter().map(String::as_str), word))
+    }
+}
+
+/// The nearest directory with a Cargo.toml, going up from `dir`.
+fn cargo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Names the targets of one `kind` in the package at `root`, following
+/// cargo's conventions for where they live. Packages are looked for in
+/// `root` and the directories right below it, which covers the usual
+/// workspace layouts.
+fn cargo_targets(root: &Path, kind: &str) -> Vec<String> {
+    let manifest = manifest_names(&root.join("Cargo.toml"));
+    let mut names: BTreeSet<String> = manifest
+        .iter()
+        .filter(|(table, _)| table == kind)
+        .map(|(_, name)| name.clone())
+        .collect();
+
+    match kind {
+        "package" => {
+            for dir in fs::read_dir(root).into_iter().flatten().flatten() {
+                names.extend(
+                    manifest_names(&dir.path().join("Cargo.toml"))
+                        .into_iter()
+                        .filter(|(table, _)| table == "package")
+                        .map(|(_, name)| name),
+                );
+            }
+        }
+        "bin" => {
+            if root.join("src/main.rs").is_file() {
+                names.extend(
+                    manifest
+                        .iter()
+                        .filter(|(table, _)| table == "package")
+                        .map(|(_, name)| name.clone()),
+                );
+            }
+            names.extend(source_targets(&root.join("src/bin")));
+        }
+        "example" => names.extend(source_targets(&root.join("examples"))),
+        "test" => names.extend(source_targets(&root.join("tests"))),
+        "bench" => names.extend(source_targets(&root.join("benches"))),
+        _ => {}
+    }
+    names.into_iter().collect()
+}
+
+/// Targets cargo discovers in `dir`: `name.rs` files and `name/main.rs`.
+fn source_targets(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "rs") {
+                path.file_stem()?.to_str().map(str::to_string)
+            } else if path.join("main.rs").is_file() {
+                entry.file_name().into_string().ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The `name = "..."` keys of a Cargo.toml, each with the table it is in,
+/// such as `package` or `bin`. This is only as much TOML as finding target
+/// names takes.
+fn manifest_names(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let mut table = String::new();
+    let mut names = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            table = line
+                .trim_matches(|c| c == '[' || c == ']')
+                .trim()
+                .to_string();
+        } else if let Some(value) = line
+            .strip_prefix("name")
+            .and_then(|rest| rest.trim_start().strip_prefix('='))
+        {
+            let value = value.trim();
+            if let Some(name) = value.strip_prefix('"').and_then(|v| v.split('"').next()) {
+                names.push((table.clone(), name.to_string()));
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_finds_the_word_and_its_command() {
+        let context = Context::parse("ls -l | grep 'foo b");
+        assert_eq!(context.words, vec!["grep"]);
+        assert_eq!(context.word, "foo b");
+        assert_eq!(context.start, 13);
+        assert_eq!(context.quote, Some('\''));
+
+        let context = Context::parse("cat a\\ b 2> out");
+        assert_eq!(context.words, vec!["cat", "a b"]);
+        assert!(context.redirect);
+
+        let context = Context::parse("make && ");
+        assert!(context.words.is_empty());
+        assert_eq!(context.start, 8);
+    }
+
+    #[test]
+    fn quoting_completed_words() {
+        assert_eq!(quote("my file", None, true), "my\\ file");
+        assert_eq!(quote("it's", Some('\''), true), "'it'\\''s'");
+        assert_eq!(quote("dir/", Some('"'), false), "\"dir/");
+        assert_eq!(variable("echo ${HO"), Some((5, "HO", true)));
+        assert_eq!(variable("echo $HOME/x"), None);
+    }
+
+    #[test]
+    fn manifest_target_names() {
+        let dir = env::temp_dir().join(format!("dsh-completion-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        fs::create_dir_all(dir.join("examples/demo")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"tool\"\n\n[[bin]]\nname = \"extra\"\npath = \"x.rs\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("src/bin/helper.rs"), "").unwrap();
+        fs::write(dir.join("examples/demo/main.rs"), "").unwrap();
+
+        assert_eq!(cargo_targets(&dir, "bin"), vec!["extra", "helper", "tool"]);
+        assert_eq!(cargo_targets(&dir, "example"), vec!["demo"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
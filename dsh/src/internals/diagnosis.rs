@@ -0,0 +1,267 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use ai_engine::{Backend, LocalBackend, MockBackend, OpenAiBackend};
+use anyhow::{anyhow, Error};
+
+use crate::config::AiConfig;
+
+use super::signals;
+use super::status::ExitStatus;
+
+/// How many trailing lines of each stream are handed to the AI engine.
+const CONTEXT_LINES: usize = 40;
+/// How many earlier commands are handed to the AI engine.
+pub const HISTORY_CONTEXT: usize = 10;
+
+/// When the shell asks the AI engine about a failed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AiPolicy {
+    /// Whenever a command fails and leaves some output behind.
+    #[default]
+    Auto,
+    /// Only when asked with `explain` or `?`.
+    OnRequest,
+    /// Never, not even when asked.
+    Never,
+}
+
+impl AiPolicy {
+    /// Reads the policy from `DSH_AI_POLICY`, falling back to the
+    /// `configured` one, or the default, when it is unset or invalid.
+    /// Scripts only get AI help when they ask for it there: their fallback
+    /// is `Never`. Without a backend `chosen`, the default is `OnRequest`,
+    /// since the local model it falls back to is a download of gigabytes.
+    pub fn from_env(interactive: bool, configured: Option<AiPolicy>, chosen: bool) -> Self {
+        let fallback = match configured {
+            _ if !interactive => AiPolicy::Never,
+            Some(policy) => policy,
+            None if chosen => AiPolicy::default(),
+            None => AiPolicy::OnRequest,
+        };
+        match std::env::var("DSH_AI_POLICY") {
+            Ok(value) => value.parse().unwrap_or_else(|err| {
+                eprintln!("dsh: {}", err);
+                fallback
+            }),
+            Err(_) => fallback,
+        }
+    }
+
+    /// Commands the user interrupted are left alone; they know why those
+    /// stopped.
+    pub fn should_diagnose(&self, failure: &FailureContext) -> bool {
+        *self == AiPolicy::Auto && failure.status != ExitStatus::INTERRUPTED && failure.has_output()
+    }
+}
+
+impl FromStr for AiPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(AiPolicy::Auto),
+            "ask" | "on-request" | "manual" => Ok(AiPolicy::OnRequest),
+            "never" | "off" => Ok(AiPolicy::Never),
+            other => Err(anyhow!(
+                "invalid AI policy `{}', expected auto, ask or never",
+                other
+            )),
+        }
+    }
+}
+
+/// Which model answers: OpenAI's API, the local one, or a canned reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiBackend {
+    OpenAi,
+    Local,
+    Mock,
+}
+
+impl AiBackend {
+    /// Reads the backend from `DSH_AI_BACKEND`, falling back to the
+    /// configured one. Without either, OpenAI is chosen when its API key is
+    /// set, and nothing otherwise, which leaves the local model.
+    pub fn from_env(config: &AiConfig) -> Option<Self> {
+        let fallback = || {
+            config.backend.or_else(|| {
+                let key = config.engine_args().openai_api_key_env;
+                std::env::var_os(key).map(|_| AiBackend::OpenAi)
+            })
+        };
+        match std::env::var("DSH_AI_BACKEND") {
+            Ok(value) => match value.parse() {
+                Ok(backend) => Some(backend),
+                Err(err) => {
+                    eprintln!("dsh: {}", err);
+                    fallback()
+                }
+            },
+            Err(_) => fallback(),
+        }
+    }
+
+    /// The backend, set up as `config` says. Nothing is loaded or asked
+    /// until the first question.
+    pub fn open(self, config: &AiConfig) -> Result<Box<dyn Backend>, Error> {
+        Ok(match self {
+            AiBackend::OpenAi => Box::new(OpenAiBackend::new(&config.engine_args())?),
+            AiBackend::Local => Box::new(LocalBackend::new(config.engine_args())),
+            AiBackend::Mock => Box::new(MockBackend::new(
+                config.mock_reply.clone().unwrap_or_default(),
+            )),
+        })
+    }
+}
+
+impl FromStr for AiBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "openai" => Ok(AiBackend::OpenAi),
+            "local" | "candle" => Ok(AiBackend::Local),
+            "mock" => Ok(AiBackend::Mock),
+            other => Err(anyhow!(
+                "invalid AI backend `{}', expected openai, local or mock",
+                other
+            )),
+        }
+    }
+}
+
+/// What the AI engine gets to see about a command that failed.
+#[derive(Debug, Clone)]
+pub struct FailureContext {
+    pub command_line: String,
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// The commands run before this one, oldest first.
+    pub history: Vec<String>,
+}
+
+impl FailureContext {
+    pub fn has_output(&self) -> bool {
+        !self.stdout.trim().is_empty() || !self.stderr.trim().is_empty()
+    }
+
+    pub fn prompt(&self) -> String {
+        let mut prompt = format!(
+            "The shell command `{}` failed with {}.\n",
+            self.command_line, self.status
+        );
+
+        for (name, output) in [("stderr", &self.stderr), ("stdout", &self.stdout)] {
+            let tail = tail_lines(output, CONTEXT_LINES);
+            if !tail.trim().is_empty() {
+                prompt.push_str(&format!("\nLast lines of {}:\n{}\n", name, tail));
+            }
+        }
+
+        if !self.history.is_empty() {
+            prompt.push_str("\nCommands run just before it, oldest first:\n");
+            for command in &self.history {
+                prompt.push_str(&format!("{}\n", command));
+            }
+        }
+
+        prompt
+    }
+}
+
+/// Asks the AI engine for a fix, printing the answer as it streams in and
+/// reporting (not propagating) any error. Ctrl+C drops the request, even in
+/// the middle of the answer.
+pub async fn diagnose(engine: &mut dyn Backend, prompt: &str) {
+    println!("{} says:", engine.name());
+    println!();
+    let answer = ai_engine::explain_error(engine, prompt, |text| {
+        print!("{}", text);
+        let _ = io::stdout().flush();
+    });
+    tokio::select! {
+        result = answer => match result {
+            Ok(_) => println!("\n[Done.]"),
+            Err(err) => println!("\nerror with generating a fix. {:?}", err),
+        },
+        _ = signals::interrupted() => println!("\n[Interrupted.]"),
+    }
+}
+
+fn tail_lines(output: &str, count: usize) -> &str {
+    let trimmed = output.trim_end();
+    match trimmed.rmatch_indices('\n').nth(count.saturating_sub(1)) {
+        Some((index, _)) => &trimmed[index + 1..],
+        None => trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_last_lines() {
+        assert_eq!(tail_lines("a\nb\nc\n", 2), "b\nc");
+        assert_eq!(tail_lines("a\nb\n", 5), "a\nb");
+        assert_eq!(tail_lines("", 5), "");
+    }
+
+    #[test]
+    fn parses_policy_names() {
+        assert_eq!("auto".parse::<AiPolicy>().unwrap(), AiPolicy::Auto);
+        assert_eq!("Ask".parse::<AiPolicy>().unwrap(), AiPolicy::OnRequest);
+        assert_eq!("never".parse::<AiPolicy>().unwrap(), AiPolicy::Never);
+        assert!("sometimes".parse::<AiPolicy>().is_err());
+        assert_eq!("OpenAI".parse::<AiBackend>().unwrap(), AiBackend::OpenAi);
+        assert_eq!("candle".parse::<AiBackend>().unwrap(), AiBackend::Local);
+        assert_eq!("mock".parse::<AiBackend>().unwrap(), AiBackend::Mock);
+    }
+
+    #[test]
+    fn only_explains_failures_unasked_once_a_backend_is_chosen() {
+        if std::env::var_os("DSH_AI_POLICY").is_some() {
+            return;
+        }
+        assert_eq!(AiPolicy::from_env(true, None, false), AiPolicy::OnRequest);
+        assert_eq!(AiPolicy::from_env(true, None, true), AiPolicy::Auto);
+        let configured = Some(AiPolicy::Auto);
+        assert_eq!(AiPolicy::from_env(true, configured, false), AiPolicy::Auto);
+        assert_eq!(AiPolicy::from_env(false, configured, true), AiPolicy::Never);
+    }
+
+    #[test]
+    fn prompt_mentions_command_and_status() {
+        let context = FailureContext {
+            command_line: "cargo build".to_string(),
+            status: ExitStatus::Exited(101),
+            stdout: String::new(),
+            stderr: "error[E0425]: cannot find value `x`\n".to_string(),
+            history: Vec::new(),
+        };
+        let prompt = context.prompt();
+        assert!(prompt.contains("`cargo build` failed with exit code 101"));
+        assert!(prompt.contains("Last lines of stderr:\nerror[E0425]"));
+        assert!(!prompt.contains("stdout"));
+        assert!(!prompt.contains("Commands run just before it"));
+        assert!(AiPolicy::Auto.should_diagnose(&context));
+        assert!(!AiPolicy::OnRequest.should_diagnose(&context));
+
+        let interrupted = FailureContext {
+            status: ExitStatus::INTERRUPTED,
+            ..context
+        };
+        assert_eq!(interrupted.status.code(), 130);
+        assert!(!AiPolicy::Auto.should_diagnose(&interrupted));
+
+        let with_history = FailureContext {
+            history: vec!["$ git pull  (in /src, exit 0, 1.2s)".to_string()],
+            ..interrupted
+        };
+        assert!(with_history
+            .prompt()
+            .contains("oldest first:\n$ git pull  (in /src, exit 0, 1.2s)\n"));
+    }
+}
@@ -0,0 +1,649 @@
+use super::ast::{AndOrList, CaseItem, Command, Compound, CompoundKind, Connector};
+use super::ast::{FunctionDefinition, Pipeline, Program, Word};
+use super::commands::{self, InShell, Outcome, Stage};
+use super::diagnosis;
+use super::expand::{Expanded, Expander};
+use super::glob;
+use super::parser::{self, ParseError};
+use super::shell::{Flow, ShellState};
+use super::signals;
+use super::status::ExitStatus;
+use super::variables;
+
+use std::fmt;
+use std::sync::Mutex;
+
+/// The script, and the line in it, of the command running, for errors to be
+/// reported against. Errors are reported from code that has no access to
+/// the shell's state, so it can't live there.
+static LOCATION: Mutex<Option<(String, usize)>> = Mutex::new(None);
+
+/// Prints an error, saying where it happened when a script is running. Used
+/// through `report!`.
+pub fn report(message: fmt::Arguments) {
+    eprintln!("{}", error_line(message));
+}
+
+/// An error the way [`report`] prints it.
+pub fn error_line(message: impl fmt::Display) -> String {
+    match &*LOCATION.lock().unwrap() {
+        Some((name, line)) => format!("dsh: {}: line {}: {}", name, line, message),
+        None => format!("dsh: {}", message),
+    }
+}
+
+/// Sets where errors are reported from, returning where they were.
+fn locate(location: Option<(String, usize)>) -> Option<(String, usize)> {
+    std::mem::replace(&mut *LOCATION.lock().unwrap(), location)
+}
+
+/// Runs every list in `program` in order and returns the status of the last
+/// command that ran, which is also left in `state.last_status` for `$?`.
+/// Once a whole command line has run, the AI engine may be asked about a
+/// failure in it.
+pub async fn run_program(program: &Program, state: &mut ShellState) -> ExitStatus {
+    state.program_depth += 1;
+    let status = run_items(program, state).await;
+    state.program_depth -= 1;
+    if state.program_depth == 0 {
+        diagnose_failure(state).await;
+    }
+    status
+}
+
+async fn run_items(program: &Program, state: &mut ShellState) -> ExitStatus {
+    for item in &program.items {
+        if item.background && !item.and_or.rest.is_empty() {
+            report!("only a single pipeline can be run in the background");
+            state.last_status = ExitStatus::FAILURE;
+            continue;
+        }
+        let status = run_and_or(&item.and_or, item.background, state).await;
+        if stops(status, state) {
+            break;
+        }
+    }
+    state.last_status
+}
+
+/// Runs shell code read from a file, one complete command at a time so
+/// that each sees what the ones before it did. Errors are reported against
+/// `name` and the line they come from, and a syntax error stops it.
+pub async fn run_script(source: &str, name: &str, state: &mut ShellState) -> ExitStatus {
+    run_lines(source.lines().map(str::to_string), name, state).await
+}
+
+/// Like [`run_script`], for lines that arrive one at a time, as from a pipe:
+/// each command runs as soon as the lines it spans have been read.
+pub async fn run_lines(
+    lines: impl Iterator<Item = String>,
+    name: &str,
+    state: &mut ShellState,
+) -> ExitStatus {
+    let mut status = ExitStatus::SUCCESS;
+    let mut pending = String::new();
+    let mut line_number = 0;
+    let mut first_line = 1;
+    let outer = locate(None);
+
+    for line in lines {
+        line_number += 1;
+        if pending.is_empty() {
+            first_line = line_number;
+        }
+        pending.push_str(&line);
+        pending.push('\n');
+        let program = match parser::parse_with_aliases(&pending, &state.aliases) {
+            Ok(program) => program,
+            Err(ParseError::Incomplete(_)) => continue,
+            Err(err) => {
+                locate(outer);
+                return syntax_error(name, line_number, err, state);
+            }
+        };
+        pending.clear();
+        if program.items.is_empty() {
+            continue;
+        }
+        // Errors point at the line the command starts on.
+        locate(Some((name.to_string(), first_line)));
+        status = run_program(&program, state).await;
+        if stops(status, state) {
+            break;
+        }
+    }
+    locate(outer);
+
+    match parser::parse_with_aliases(&pending, &state.aliases) {
+        Err(err) if !pending.is_empty() => syntax_error(name, line_number, err, state),
+        _ => status,
+    }
+}
+
+fn syntax_error(name: &str, line: usize, err: ParseError, state: &mut ShellState) -> ExitStatus {
+    eprintln!("dsh: {}: line {}: {}", name, line, err);
+    state.last_status = ExitStatus::Exited(2);
+    state.last_status
+}
+
+/// Whether the rest of a command line is abandoned: after Ctrl+C, once
+/// `exit` has run, or until the loop, function or script that `break`,
+/// `continue` or `return` leaves is reached.
+fn stops(status: ExitStatus, state: &ShellState) -> bool {
+    status == ExitStatus::INTERRUPTED || state.exiting.is_some() || state.flow.is_some()
+}
+
+/// Runs `a && b || c`, skipping a pipeline when the status of the one before
+/// it already decides the outcome.
+async fn run_and_or(list: &AndOrList, background: bool, state: &mut ShellState) -> ExitStatus {
+    let checked = !list.rest.is_empty();
+    let mut status = run_checked(&list.first, background, checked, state).await;
+
+    for (index, (connector, pipeline)) in list.rest.iter().enumerate() {
+        if stops(status, state) {
+            break;
+        }
+        let run_next = match connector {
+            Connector::And => status.success(),
+            Connector::Or => !status.success(),
+        };
+        if run_next {
+            let checked = index < list.rest.len() - 1;
+            status = run_checked(pipeline, false, checked, state).await;
+        }
+    }
+    status
+}
+
+/// Runs a pipeline, `checked` when a `&&` or `||` after it looks at its
+/// status. With `set -e`, a failure ends the shell unless something was
+/// there to check it: that, a `!` in front, or an `if`, `while` or `until`
+/// condition it is part of, however deep in functions it runs.
+async fn run_checked(
+    pipeline: &Pipeline,
+    background: bool,
+    checked: bool,
+    state: &mut ShellState,
+) -> ExitStatus {
+    let checked = checked || pipeline.negated;
+    if checked {
+        state.conditions += 1;
+    }
+    let status = run_pipeline(pipeline, background, state).await;
+    if checked {
+        state.conditions -= 1;
+    }
+
+    let failed = !matches!(status, ExitStatus::SUCCESS | ExitStatus::Stopped(_));
+    if state.options.errexit && failed && !checked && state.conditions == 0 {
+        state.exiting.get_or_insert(status.code());
+    }
+    status
+}
+
+/// Runs a pipeline, in the background when it was followed by `&`.
+/// Builtins, functions and compound commands on their own run in the shell
+/// itself; after `&` or in a longer pipeline they run in copies of it.
+async fn run_pipeline(pipeline: &Pipeline, background: bool, state: &mut ShellState) -> ExitStatus {
+    let mut stages = Vec::with_capacity(pipeline.commands.len());
+    for command in &pipeline.commands {
+        let mut expander = Expander::new(state);
+        let expanded = match command {
+            Command::Simple(simple) => expander.command(simple),
+            Command::Compound(compound) => {
+                expander
+                    .redirects(&compound.redirects)
+                    .map(|redirects| Expanded {
+                        redirects,
+                        ..Expanded::default()
+                    })
+            }
+            Command::Function(_) => Ok(Expanded::default()),
+        };
+        match expanded {
+            Ok(expanded) => stages.push(Stage { command, expanded }),
+            Err(err) => return expansion_error(err, state),
+        }
+    }
+    if state.options.xtrace {
+        trace(&stages, state);
+    }
+    let command_line: Vec<String> = pipeline.commands.iter().map(|c| c.to_string()).collect();
+    let command_line = command_line.join(" | ");
+
+    let stage = &stages[0];
+    let command = &stage.expanded;
+    let status = match (stages.len(), stage.in_shell(state)) {
+        (1, Some(in_shell)) if !background => commands::run_alone(in_shell, command, state).await,
+        // Assignments on their own stay set.
+        (1, None) if command.argv.is_empty() && command.redirects.is_empty() => {
+            match assign(&command.assignments, state) {
+                Ok(()) => command.substitution.unwrap_or(ExitStatus::SUCCESS),
+                Err(err) => {
+                    report!("{}", err);
+                    ExitStatus::FAILURE
+                }
+            }
+        }
+        (1, None) => {
+            let outcome =
+                commands::run_single_command(stage, command_line, state, background).await;
+            record_outcome(outcome, state).await
+        }
+        _ => {
+            let outcome =
+                commands::run_piped_commands(&stages, command_line, state, background).await;
+            record_outcome(outcome, state).await
+        }
+    };
+
+    let status = match (pipeline.negated, status.success()) {
+        (false, _) => status,
+        (true, true) => ExitStatus::FAILURE,
+        (true, false) => ExitStatus::SUCCESS,
+    };
+    state.last_status = status;
+    status
+}
+
+/// Reports a word that failed to expand, which ends a shell that isn't
+/// interactive: only a person at the prompt gets to try again.
+fn expansion_error(err: anyhow::Error, state: &mut ShellState) -> ExitStatus {
+    report!("{}", err);
+    if !state.interactive {
+        state.exiting.get_or_insert(ExitStatus::FAILURE.code());
+    }
+    state.last_status = ExitStatus::FAILURE;
+    ExitStatus::FAILURE
+}
+
+/// Runs the body of a compound command.
+pub async fn run_compound(compound: &Compound, state: &mut ShellState) -> ExitStatus {
+    match &compound.kind {
+        CompoundKind::Group(body) => Box::pin(run_program(body, state)).await,
+        CompoundKind::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, body) in branches {
+                let status = run_condition(condition, state).await;
+                if stops(status, state) {
+                    return status;
+                }
+                if status.success() {
+                    return Box::pin(run_program(body, state)).await;
+                }
+            }
+            match otherwise {
+                Some(body) => Box::pin(run_program(body, state)).await,
+                None => ExitStatus::SUCCESS,
+            }
+        }
+        CompoundKind::Loop {
+            until,
+            condition,
+            body,
+        } => {
+            enter_loop(state);
+            let mut status = ExitStatus::SUCCESS;
+            loop {
+                let mut tested = run_condition(condition, state).await;
+                if !next_round(&mut tested, state) {
+                    status = tested;
+                    break;
+                }
+                if tested.success() == *until {
+                    break;
+                }
+                status = Box::pin(run_program(body, state)).await;
+                if !next_round(&mut status, state) {
+                    break;
+                }
+            }
+            state.loop_depth -= 1;
+            status
+        }
+        CompoundKind::For { name, words, body } => {
+            let values = match words {
+                Some(words) => match Expander::new(state).fields(words) {
+                    Ok(values) => values,
+                    Err(err) => return expansion_error(err, state),
+                },
+                None => state.variables.positional().to_vec(),
+            };
+            enter_loop(state);
+            let mut status = ExitStatus::SUCCESS;
+            for value in values {
+                if let Err(err) = state.variables.set(name, &value) {
+                    report!("{}", err);
+                    status = ExitStatus::FAILURE;
+                    break;
+                }
+                status = Box::pin(run_program(body, state)).await;
+                if !next_round(&mut status, state) {
+                    break;
+                }
+            }
+            state.loop_depth -= 1;
+            status
+        }
+        CompoundKind::Case { word, items } => match case_item(word, items, state) {
+            Ok(Some(item)) if !item.body.items.is_empty() => {
+                Box::pin(run_program(&item.body, state)).await
+            }
+            Ok(_) => ExitStatus::SUCCESS,
+            Err(err) => expansion_error(err, state),
+        },
+    }
+}
+
+/// Runs the condition of an `if`, `while` or `until`, where a failure is
+/// an answer rather than an error for `set -e`.
+async fn run_condition(condition: &Program, state: &mut ShellState) -> ExitStatus {
+    state.conditions += 1;
+    let status = Box::pin(run_program(condition, state)).await;
+    state.conditions -= 1;
+    status
+}
+
+fn enter_loop(state: &mut ShellState) {
+    // A Ctrl+C from before the outermost loop started isn't for it.
+    if state.loop_depth == 0 {
+        signals::take_interrupt();
+    }
+    state.loop_depth += 1;
+}
+
+/// Whether a loop goes on after its condition or body ran with `status`.
+/// A `break` or `continue` meant for a loop further out is passed on to it
+/// with one loop fewer to go. Ctrl+C ends the loop as interrupted.
+fn next_round(status: &mut ExitStatus, state: &mut ShellState) -> bool {
+    let goes_on = match state.flow {
+        Some(Flow::Break(count)) => {
+            state.flow = (count > 1).then(|| Flow::Break(count - 1));
+            false
+        }
+        Some(Flow::Continue(count)) if count > 1 => {
+            state.flow = Some(Flow::Continue(count - 1));
+            false
+        }
+        Some(Flow::Continue(_)) => {
+            state.flow = None;
+            true
+        }
+        Some(Flow::Return) => false,
+        None => !stops(*status, state),
+    };
+    if goes_on && signals::take_interrupt() {
+        *status = ExitStatus::INTERRUPTED;
+        state.last_status = *status;
+        return false;
+    }
+    goes_on
+}
+
+/// The first item of a `case` with a pattern that matches the word.
+fn case_item<'a>(
+    word: &Word,
+    items: &'a [CaseItem],
+    state: &mut ShellState,
+) -> anyhow::Result<Option<&'a CaseItem>> {
+    let mut expander = Expander::new(state);
+    let subject = expander.string(word.raw())?;
+    for item in items {
+        for pattern in &item.patterns {
+            if glob::matches(&expander.pattern(pattern.raw())?, &subject) {
+                return Ok(Some(item));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// How deep function calls can nest before the shell gives up, well before
+/// its own stack would.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Calls a function with `args[1..]` as its positional parameters. Its
+/// `local` variables and the caller's parameters come back when it returns,
+/// by running off its end or through `return`.
+pub async fn call_function(body: &Compound, args: &[String], state: &mut ShellState) -> ExitStatus {
+    if state.call_depth >= MAX_CALL_DEPTH {
+        report!("{}: maximum function nesting level exceeded", args[0]);
+        return ExitStatus::FAILURE;
+    }
+    let saved = state.variables.set_positional(args[1..].to_vec());
+    state.variables.push_scope();
+    state.call_depth += 1;
+    // Loops around the call can't be left from inside it.
+    let loop_depth = std::mem::take(&mut state.loop_depth);
+
+    let status = match Expander::new(state).redirects(&body.redirects) {
+        Ok(redirects) => {
+            let expanded = Expanded {
+                redirects,
+                ..Expanded::default()
+            };
+            commands::run_alone(InShell::Compound(body), &expanded, state).await
+        }
+        Err(err) => {
+            report!("{}", err);
+            ExitStatus::FAILURE
+        }
+    };
+
+    state.loop_depth = loop_depth;
+    state.call_depth -= 1;
+    state.variables.pop_scope();
+    state.variables.set_positional(saved);
+    state.flow = None;
+    status
+}
+
+/// Defines, or redefines, a function.
+pub fn define_function(definition: &FunctionDefinition, state: &mut ShellState) -> ExitStatus {
+    state
+        .functions
+        .insert(definition.name.clone(), definition.body.clone());
+    ExitStatus::SUCCESS
+}
+
+/// `set -x`: prints each simple command as it will run, after `$PS4`.
+fn trace(stages: &[Stage], state: &ShellState) {
+    let prefix = state.variables.get("PS4").unwrap_or("+ ");
+    for stage in stages {
+        if !matches!(stage.command, Command::Simple(_)) {
+            continue;
+        }
+        let expanded = &stage.expanded;
+        let words: Vec<String> = expanded
+            .assignments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, variables::quote_if_needed(value)))
+            .chain(
+                expanded
+                    .argv
+                    .iter()
+                    .map(|arg| variables::quote_if_needed(arg)),
+            )
+            .collect();
+        eprintln!("{}{}", prefix, words.join(" "));
+    }
+}
+
+fn assign(assignments: &[(String, String)], state: &mut ShellState) -> anyhow::Result<()> {
+    for (name, value) in assignments {
+        state.variables.set(name, value)?;
+    }
+    Ok(())
+}
+
+/// Remembers a failed command for `explain`, and for the AI engine to be
+/// asked about once the command line is done if nothing checked it: not an
+/// `if` or `while` condition, nor the left of `&&` or `||`.
+pub async fn record_outcome(
+    outcome: anyhow::Result<Outcome>,
+    state: &mut ShellState,
+) -> ExitStatus {
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            report!("{}", err);
+            return ExitStatus::FAILURE;
+        }
+    };
+
+    let mut status = outcome.status;
+    if let Some(mut failure) = outcome.failure {
+        if state.options.pipefail {
+            status = failure.status;
+        }
+        failure.history = state
+            .history
+            .recent(diagnosis::HISTORY_CONTEXT)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        if state.conditions == 0 {
+            state.undiagnosed = Some(failure.clone());
+        }
+        state.last_failure = Some(failure);
+    }
+    status
+}
+
+/// Asks the AI engine about the last unchecked failure of the command line
+/// that just ran, if the policy says so: once per line, however many
+/// commands in it failed.
+async fn diagnose_failure(state: &mut ShellState) {
+    let Some(failure) = state.undiagnosed.take() else {
+        return;
+    };
+    if let Some(engine) = &mut state.engine {
+        if state.ai_policy.should_diagnose(&failure) {
+            diagnosis::diagnose(engine.as_mut(), &failure.prompt()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::internals::diagnosis::AiPolicy;
+    use ai_engine::{Args, Backend, MockBackend, TextStream};
+    use std::cell::Cell;
+    use std::env;
+    use std::fs;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// A mock that counts the questions it is asked.
+    struct Counting(MockBackend, Rc<Cell<usize>>);
+
+    impl Backend for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn generate<'a>(&'a mut self, prompt: &'a str) -> TextStream<'a> {
+            self.1.set(self.1.get() + 1);
+            self.0.generate(prompt)
+        }
+
+        fn reconfigure(&mut self, _args: &Args) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn run(line: &str, state: &mut ShellState) -> ExitStatus {
+        run_program(&parser::parse(line).unwrap(), state).await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn diagnoses_only_unchecked_failures_once_per_line() {
+        let asked = Rc::new(Cell::new(0));
+        let mut state = ShellState::new(false, Config::default());
+        state.ai_policy = AiPolicy::Auto;
+        state.engine = Some(Box::new(Counting(
+            MockBackend::new("fix it"),
+            asked.clone(),
+        )));
+        let fail = "sh -c 'echo broken >&2; exit 3'";
+
+        run(
+            &format!("if {fail}; then :; fi; {fail} || true"),
+            &mut state,
+        )
+        .await;
+        run(&format!("while {fail}; do :; done; ! {fail}"), &mut state).await;
+        assert_eq!(asked.get(), 0);
+        assert_eq!(state.last_failure.as_ref().unwrap().status.code(), 3);
+
+        run(&format!("for i in 1 2 3; do {fail}; done"), &mut state).await;
+        assert_eq!(asked.get(), 1);
+        run(&format!("true && {fail}"), &mut state).await;
+        assert_eq!(asked.get(), 2);
+    }
+
+    #[test]
+    fn ends_a_builtin_loop_once_its_reader_is_gone() {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let mut state = ShellState::new(false, Config::default());
+            let line = "while :; do echo y; done | head -1 > /dev/null";
+            let _ = sender.send(runtime.block_on(run(line, &mut state)));
+        });
+
+        let status = receiver.recv_timeout(Duration::from_secs(10));
+        assert_eq!(status, Ok(ExitStatus::SUCCESS));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_pipeline_builtins_out_of_the_shell() {
+        let mut state = ShellState::new(false, Config::default());
+        let cwd = std::env::current_dir().unwrap();
+
+        run("cd / | true; A=1 | true; f() { :; } | true", &mut state).await;
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+        assert_eq!(state.variables.get("A"), None);
+        assert!(state.functions.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn puts_shell_commands_in_the_background() {
+        let mut state = ShellState::new(false, Config::default());
+        let started = Instant::now();
+
+        let line = "f() { sleep 5; }; f & { A=1; sleep 5; } & while :; do sleep 5; done &";
+        assert_eq!(run(line, &mut state).await, ExitStatus::SUCCESS);
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert_eq!(state.jobs.len(), 3);
+        assert_eq!(state.variables.get("A"), None);
+
+        run("kill %1 %2 %3; wait", &mut state).await;
+        // Jobs already reaped when `wait` began are left for the prompt to
+        // report.
+        state.jobs.forget_finished();
+        assert_eq!(state.jobs.len(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn finishes_writing_redirected_shell_commands_first() {
+        let dir = env::temp_dir().join(format!("dsh-exec-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut state = ShellState::new(false, Config::default());
+
+        let line = format!(
+            "f() {{ echo f; }}; echo echo > {0}/a; {{ echo group; }} > {0}/b; f > {0}/c; \
+             A=$(cat {0}/a {0}/b {0}/c)",
+            dir.display()
+        );
+        run(&line, &mut state).await;
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(state.variables.get("A"), Some("echo\ngroup\nf"));
+    }
+}
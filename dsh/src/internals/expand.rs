@@ -0,0 +1,848 @@
+//! Word expansion, turning the raw words of a command into the arguments
+//! it runs with: braces, tildes, parameters and command substitution, then
+//! field splitting, pathname expansion and quote removal, in that order.
+
+use std::ffi::{CStr, CString};
+use std::io::Read;
+use std::mem;
+use std::process;
+use std::ptr;
+
+use anyhow::{anyhow, Result};
+
+use super::ast::{Redirect, SimpleCommand, Word};
+use super::exec;
+use super::glob;
+use super::jobs::Group;
+use super::parser;
+use super::redirect::{self, FdTable, Slot};
+use super::shell::ShellState;
+use super::status::ExitStatus;
+use super::subshell::{self, Fork};
+use super::variables;
+
+/// Field separators when `$IFS` isn't set.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A simple command after expansion.
+#[derive(Debug, Clone, Default)]
+pub struct Expanded {
+    /// The `NAME=value` words in front of the command.
+    pub assignments: Vec<(String, String)>,
+    pub argv: Vec<String>,
+    /// Each redirection with its target expanded.
+    pub redirects: Vec<(Redirect, String)>,
+    /// The status of the last command substitution, which is what a line
+    /// of only assignments reports.
+    pub substitution: Option<ExitStatus>,
+}
+
+/// Expands words against the shell's parameters.
+pub struct Expander<'a> {
+    state: &'a mut ShellState,
+    substitution: Option<ExitStatus>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(state: &'a mut ShellState) -> Self {
+        Expander {
+            state,
+            substitution: None,
+        }
+    }
+
+    pub fn command(&mut self, command: &SimpleCommand) -> Result<Expanded> {
+        let mut expanded = Expanded::default();
+        self.substitution = None;
+
+        let count = command
+            .words
+            .iter()
+            .take_while(|word| assignment(word.raw()).is_some())
+            .count();
+        for word in &command.words[..count] {
+            if let Some((name, value)) = assignment(word.raw()) {
+                let value = self.string(value)?;
+                expanded.assignments.push((name.to_string(), value));
+            }
+        }
+
+        expanded.argv = match command.words[count..].split_first() {
+            // A lone `?` is `explain`, not a pattern for one-letter names.
+            Some((first, rest)) if first.raw() == "?" => {
+                let mut argv = vec!["?".to_string()];
+                argv.extend(self.fields(rest)?);
+                argv
+            }
+            _ => self.fields(&command.words[count..])?,
+        };
+        expanded.redirects = self.redirects(&command.redirects)?;
+        expanded.substitution = self.substitution;
+        Ok(expanded)
+    }
+
+    /// Expands the targets of `redirects`.
+    pub fn redirects(&mut self, redirects: &[Redirect]) -> Result<Vec<(Redirect, String)>> {
+        redirects
+            .iter()
+            .map(|redirect| Ok((redirect.clone(), self.string(redirect.target.raw())?)))
+            .collect()
+    }
+
+    /// Expands `words` into fields, splitting the results of unquoted
+    /// expansions on `$IFS` and replacing fields with unquoted wildcards by
+    /// the paths they match, if any.
+    pub fn fields(&mut self, words: &[Word]) -> Result<Vec<String>> {
+        let mut fields = Vec::new();
+        for word in words {
+            for raw in braces(word.raw()) {
+                // With no positional parameters, "$@" is no field at all.
+                if raw == "\"$@\"" && self.state.variables.positional().is_empty() {
+                    continue;
+                }
+                for field in self.expand(&raw, true)? {
+                    let paths = field.glob.then(|| glob::expand(&field.pattern));
+                    match paths {
+                        Some(paths) if !paths.is_empty() => fields.extend(paths),
+                        _ => fields.push(field.text),
+                    }
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Expands a word into a single string, as for assignments and
+    /// redirection targets, which are never split.
+    pub fn string(&mut self, raw: &str) -> Result<String> {
+        let fields: Vec<String> = self
+            .expand(raw, false)?
+            .into_iter()
+            .map(|field| field.text)
+            .collect();
+        Ok(fields.join(" "))
+    }
+
+    /// Expands a word into a single glob pattern, as for `case`, in which
+    /// quoted wildcards only match themselves.
+    pub fn pattern(&mut self, raw: &str) -> Result<String> {
+        let patterns: Vec<String> = self
+            .expand(raw, false)?
+            .into_iter()
+            .map(|field| field.pattern)
+            .collect();
+        Ok(patterns.join(" "))
+    }
+
+    fn expand(&mut self, raw: &str, split: bool) -> Result<Vec<Field>> {
+        let chars: Vec<char> = raw.chars().collect();
+        let ifs = self
+            .state
+            .variables
+            .get("IFS")
+            .unwrap_or(DEFAULT_IFS)
+            .to_string();
+        let mut fields = Fields::default();
+        let mut double = false;
+        let mut i = self.tilde(&chars, &mut fields);
+
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            match c {
+                '\\' if !double => {
+                    if let Some(&escaped) = chars.get(i) {
+                        fields.push(escaped, true);
+                        i += 1;
+                    }
+                }
+                '\\' => match chars.get(i) {
+                    Some(&escaped @ ('$' | '`' | '"' | '\\' | '\n')) => {
+                        fields.push(escaped, true);
+                        i += 1;
+                    }
+                    _ => fields.push('\\', true),
+                },
+                '\'' if !double => {
+                    fields.started = true;
+                    while let Some(&c) = chars.get(i) {
+                        i += 1;
+                        if c == '\'' {
+                            break;
+                        }
+                        fields.push(c, true);
+                    }
+                }
+                '"' => {
+                    double = !double;
+                    fields.started = true;
+                }
+                '$' => {
+                    let (value, next) = self.parameter(&chars, i, double)?;
+                    i = next;
+                    match value {
+                        Value::Literal => fields.push('$', double),
+                        Value::Text(text) if double || !split => fields.push_str(&text, double),
+                        Value::Text(text) => fields.split(&text, &ifs),
+                        Value::Each(values) if double => {
+                            for (index, value) in values.iter().enumerate() {
+                                if index > 0 {
+                                    fields.end();
+                                }
+                                fields.push_str(value, true);
+                            }
+                        }
+                        Value::Each(values) => {
+                            for (index, value) in values.iter().enumerate() {
+                                if index > 0 {
+                                    fields.end();
+                                }
+                                fields.split(value, &ifs);
+                            }
+                        }
+                    }
+                }
+                c => fields.push(c, double),
+            }
+        }
+
+        Ok(fields.finish())
+    }
+
+    /// Expands a `~` or `~user` at the start of a word, returning where the
+    /// rest of the word starts. `~+` and `~-` are `$PWD` and `$OLDPWD`.
+    fn tilde(&self, chars: &[char], fields: &mut Fields) -> usize {
+        if chars.first() != Some(&'~') {
+            return 0;
+        }
+        let end = chars.iter().position(|&c| c == '/').unwrap_or(chars.len());
+        let user: String = chars[1..end].iter().collect();
+        let home = match user.as_str() {
+            "" => self.state.variables.get("HOME").map(str::to_string),
+            "+" => self.state.variables.get("PWD").map(str::to_string),
+            "-" => self.state.variables.get("OLDPWD").map(str::to_string),
+            // Anything quoted or expanded in the name turns this off.
+            user if user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) =>
+            {
+                home_dir(user)
+            }
+            _ => None,
+        };
+        match home {
+            Some(home) => {
+                fields.push_str(&home, true);
+                end
+            }
+            None => 0,
+        }
+    }
+
+    /// Reads the parameter after a `$` at `chars[start..]`, returning its
+    /// value and where the text after it starts.
+    fn parameter(&mut self, chars: &[char], start: usize, double: bool) -> Result<(Value, usize)> {
+        match chars.get(start) {
+            Some('{') => {
+                let end = closing(chars, start + 1, '{', '}')
+                    .ok_or_else(|| anyhow!("bad substitution: missing `}}'"))?;
+                let inner: String = chars[start + 1..end].iter().collect();
+                Ok((self.braced(&inner, double)?, end + 1))
+            }
+            Some('(') => {
+                let end = closing(chars, start + 1, '(', ')')
+                    .ok_or_else(|| anyhow!("bad substitution: missing `)'"))?;
+                let script: String = chars[start + 1..end].iter().collect();
+                Ok((Value::Text(self.substitute(&script)?), end + 1))
+            }
+            Some(&c) if is_special(c) => Ok((self.simple(&c.to_string(), double)?, start + 1)),
+            Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+                let end = chars[start..]
+                    .iter()
+                    .position(|c| !c.is_ascii_alphanumeric() && *c != '_')
+                    .map_or(chars.len(), |len| start + len);
+                let name: String = chars[start..end].iter().collect();
+                Ok((self.simple(&name, double)?, end))
+            }
+            _ => Ok((Value::Literal, start)),
+        }
+    }
+
+    /// `$name` or `${name}`.
+    fn simple(&self, name: &str, double: bool) -> Result<Value> {
+        Ok(match name {
+            "@" => Value::Each(self.state.variables.positional().to_vec()),
+            "*" if double => {
+                let ifs = self.state.variables.get("IFS").unwrap_or(DEFAULT_IFS);
+                let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+                Value::Text(self.state.variables.positional().join(&separator))
+            }
+            name => Value::Text(self.set_value(name)?),
+        })
+    }
+
+    /// The value of a parameter that has to be set under `set -u`.
+    fn set_value(&self, name: &str) -> Result<String> {
+        match self.lookup(name) {
+            Some(value) => Ok(value),
+            None if self.state.options.nounset => Err(anyhow!("{}: unbound variable", name)),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// The inside of `${...}`: a parameter, optionally with `#` in front
+    /// for its length or an operator and word after it.
+    fn braced(&mut self, inner: &str, double: bool) -> Result<Value> {
+        if let Some(name) = inner.strip_prefix('#').filter(|name| !name.is_empty()) {
+            if !is_parameter(name) {
+                return Err(anyhow!("${{{}}}: bad substitution", inner));
+            }
+            let length = self.set_value(name)?.chars().count();
+            return Ok(Value::Text(length.to_string()));
+        }
+
+        let name_len = match inner.chars().next() {
+            Some(c) if is_special(c) => 1,
+            _ => inner
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(inner.len()),
+        };
+        let (name, rest) = inner.split_at(name_len);
+        if name.is_empty() || !is_parameter(name) {
+            return Err(anyhow!("${{{}}}: bad substitution", inner));
+        }
+        if rest.is_empty() {
+            return self.simple(name, double);
+        }
+
+        // With a colon, an empty value counts as unset.
+        let (colon, rest) = match rest.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let mut operator = rest.chars();
+        let op = operator.next();
+        let word = operator.as_str();
+        let value = self.lookup(name);
+        let is_set = value
+            .as_ref()
+            .is_some_and(|value| !(colon && value.is_empty()));
+
+        let text = match op {
+            Some('-') if is_set => value.unwrap_or_default(),
+            Some('-') => self.string(word)?,
+            Some('=') if is_set => value.unwrap_or_default(),
+            Some('=') => {
+                let word = self.string(word)?;
+                if !variables::is_name(name) {
+                    return Err(anyhow!("${}: cannot assign in this way", name));
+                }
+                self.state.variables.set(name, &word)?;
+                word
+            }
+            Some('+') if is_set => self.string(word)?,
+            Some('+') => String::new(),
+            Some('?') if is_set => value.unwrap_or_default(),
+            Some('?') => {
+                let message = self.string(word)?;
+                return Err(anyhow!(
+                    "{}: {}",
+                    name,
+                    if message.is_empty() {
+                        "parameter null or not set"
+                    } else {
+                        &message
+                    }
+                ));
+            }
+            _ => return Err(anyhow!("${{{}}}: bad substitution", inner)),
+        };
+        Ok(Value::Text(text))
+    }
+
+    /// Runs the commands of a `$(...)` in a copy of the shell and returns
+    /// what they print, minus trailing newlines. Their status is left in
+    /// `$?`.
+    fn substitute(&mut self, script: &str) -> Result<String> {
+        let program = parser::parse(script)?;
+        let (mut reader, writer) = redirect::pipe()?;
+        let fds = FdTable::new(Slot::Inherit, Slot::file(writer), Slot::Inherit);
+        let pid = match subshell::fork(self.state, &fds, Group::Shell)? {
+            Fork::Parent { pid, .. } => pid,
+            Fork::Child => {
+                let status = subshell::run(exec::run_program(&program, self.state));
+                subshell::exit(self.state, status)
+            }
+        };
+        // Closes the shell's copy of the writer, so the read ends with the
+        // copy's output.
+        drop(fds);
+
+        let mut output = Vec::new();
+        let read = reader.read_to_end(&mut output);
+        let status = subshell::wait(pid)?;
+        read?;
+        self.state.last_status = status;
+        self.substitution = Some(status);
+
+        let output = String::from_utf8_lossy(&output);
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        let positional = self.state.variables.positional();
+        match name {
+            "?" => Some(self.state.last_status.code().to_string()),
+            "$" => Some(process::id().to_string()),
+            "!" => self.state.jobs.last_background().map(|pid| pid.to_string()),
+            "0" => Some(self.state.variables.arg0().to_string()),
+            "#" => Some(positional.len().to_string()),
+            "@" | "*" => Some(positional.join(" ")),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => {
+                let index: usize = name.parse().ok()?;
+                positional.get(index.checked_sub(1)?).cloned()
+            }
+            _ => self.state.variables.get(name).map(str::to_string),
+        }
+    }
+}
+
+/// What a parameter expands to.
+enum Value {
+    /// A `$` that doesn't start an expansion.
+    Literal,
+    Text(String),
+    /// `$@`: one field per positional parameter, even in double quotes.
+    Each(Vec<String>),
+}
+
+/// One field of an expanded word.
+struct Field {
+    text: String,
+    /// The field as a glob pattern, with quoted wildcards escaped.
+    pattern: String,
+    /// Whether it has unquoted wildcards, and so is a pattern at all.
+    glob: bool,
+}
+
+/// The fields a word expands to, as they are built.
+#[derive(Default)]
+struct Fields {
+    done: Vec<Field>,
+    current: String,
+    pattern: String,
+    /// Whether the current field has an unquoted `*`, `?` or `[`.
+    glob: bool,
+    /// Whether the current field exists even if empty, as `""` does.
+    started: bool,
+}
+
+impl Fields {
+    fn push(&mut self, c: char, quoted: bool) {
+        self.current.push(c);
+        if quoted && matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+        self.glob |= !quoted && matches!(c, '*' | '?' | '[');
+        self.started = true;
+    }
+
+    fn push_str(&mut self, text: &str, quoted: bool) {
+        for c in text.chars() {
+            self.push(c, quoted);
+        }
+        self.started = true;
+    }
+
+    /// Adds the result of an unquoted expansion, starting a new field at
+    /// each run of separators.
+    fn split(&mut self, text: &str, ifs: &str) {
+        for c in text.chars() {
+            if ifs.contains(c) {
+                self.end();
+            } else {
+                self.push(c, false);
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        if self.started {
+            self.done.push(Field {
+                text: mem::take(&mut self.current),
+                pattern: mem::take(&mut self.pattern),
+                glob: self.glob,
+            });
+            self.started = false;
+            self.glob = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        self.end();
+        self.done
+    }
+}
+
+/// Splits `NAME=value` into its name and raw value, if the word is an
+/// assignment.
+pub fn assignment(raw: &str) -> Option<(&str, &str)> {
+    let (name, value) = raw.split_once('=')?;
+    variables::is_name(name).then_some((name, value))
+}
+
+fn is_special(c: char) -> bool {
+    matches!(c, '?' | '$' | '!' | '#' | '*' | '@') || c.is_ascii_digit()
+}
+
+fn is_parameter(name: &str) -> bool {
+    variables::is_name(name)
+        || name.chars().all(|c| c.is_ascii_digit())
+        || (name.len() == 1 && name.chars().all(is_special))
+}
+
+/// Finds the `close` that matches an `open` whose inside starts at
+/// `chars[start]`, skipping quoted text.
+fn closing(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = start;
+    while i < chars.len() {
+        match (quote, chars[i]) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => i += 1,
+            (Some(_), _) => {}
+            (None, c @ ('\'' | '"')) => quote = Some(c),
+            (None, c) if c == open => depth += 1,
+            (None, c) if c == close && depth == 0 => return Some(i),
+            (None, c) if c == close => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Brace expansion, which comes before everything else: `a{b,c}d` is
+/// `abd acd`, and `{1..10}`, `{a..e}` and `{0..100..5}` are sequences.
+/// Braces with anything else in them stay as they are, as do quoted ones
+/// and those of `${...}`.
+pub fn braces(raw: &str) -> Vec<String> {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            quote @ ('\'' | '"') => {
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    if quote == '"' && chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            '$' => {
+                if let Some(&open @ ('{' | '(')) = chars.get(i + 1) {
+                    let close = if open == '{' { '}' } else { ')' };
+                    match closing(&chars, i + 2, open, close) {
+                        Some(end) => i = end,
+                        None => break,
+                    }
+                }
+            }
+            '{' => {
+                let end = closing(&chars, i + 1, '{', '}');
+                let inner: Option<String> = end.map(|end| chars[i + 1..end].iter().collect());
+                let items =
+                    inner.and_then(|inner| alternatives(&inner).or_else(|| sequence(&inner)));
+                if let (Some(end), Some(items)) = (end, items) {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffix: String = chars[end + 1..].iter().collect();
+                    return items
+                        .iter()
+                        .flat_map(|item| braces(&format!("{}{}", item, suffix)))
+                        .map(|rest| format!("{}{}", prefix, rest))
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![raw.to_string()]
+}
+
+/// The comma-separated items inside `{...}`, if there are at least two.
+fn alternatives(inner: &str) -> Option<Vec<String>> {
+    let chars: Vec<char> = inner.chars().collect();
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                item.push(c);
+                i += 1;
+                if let Some(&escaped) = chars.get(i) {
+                    item.push(escaped);
+                }
+                i += 1;
+                continue;
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(mem::take(&mut item));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        item.push(c);
+        i += 1;
+    }
+    items.push(item);
+    (items.len() > 1).then_some(items)
+}
+
+/// The items of a sequence like `1..10`, `10..1..3`, `01..10` or `a..e`.
+fn sequence(inner: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = inner.split("..").collect();
+    let (first, last, step) = match parts[..] {
+        [first, last] => (first, last, 1),
+        [first, last, step] => (first, last, step.parse::<i64>().ok()?),
+        _ => return None,
+    };
+    let step = step.unsigned_abs().max(1) as usize;
+
+    if let (Ok(from), Ok(to)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        // A leading zero on either end pads every item to the same width.
+        let padded = |n: &str| {
+            n.trim_start_matches('-').len() > 1 && n.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(first) || padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        let items = steps(from, to, step)
+            .map(|n| format!("{:0width$}", n, width = width))
+            .collect();
+        return Some(items);
+    }
+
+    let mut first = first.chars();
+    let mut last = last.chars();
+    match (first.next(), first.next(), last.next(), last.next()) {
+        (Some(from), None, Some(to), None)
+            if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() =>
+        {
+            let items = steps(from as i64, to as i64, step)
+                .filter_map(|c| char::from_u32(c as u32))
+                .map(String::from)
+                .collect();
+            Some(items)
+        }
+        _ => None,
+    }
+}
+
+/// `from` to `to` inclusive, counting down if `to` is smaller.
+fn steps(from: i64, to: i64, step: usize) -> Box<dyn Iterator<Item = i64>> {
+    if from <= to {
+        Box::new((from..=to).step_by(step))
+    } else {
+        Box::new((to..=from).rev().step_by(step))
+    }
+}
+
+/// The home directory of `user` from the password database.
+fn home_dir(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut entry: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    let err = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if err != 0 || result.is_null() || entry.pw_dir.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr(entry.pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::internals::ast::Command;
+    use std::{env, fs};
+
+    fn shell() -> ShellState {
+        let mut state = ShellState::new(false, Config::default());
+        state.last_status = ExitStatus::Exited(3);
+        state
+    }
+
+    fn expand(words: &str, state: &mut ShellState) -> Result<Vec<String>> {
+        let words: Vec<Word> = words.split(' ').map(|w| Word(w.to_string())).collect();
+        Expander::new(state).fields(&words)
+    }
+
+    #[test]
+    fn expands_parameters() {
+        let mut state = shell();
+        state.variables.set("NAME", "world").unwrap();
+        state.variables.set("EMPTY", "").unwrap();
+
+        assert_eq!(
+            expand("$NAME ${NAME}s '$NAME' \"$?\" \\$x", &mut state).unwrap(),
+            vec!["world", "worlds", "$NAME", "3", "$x"]
+        );
+        assert_eq!(
+            expand(
+                "${EMPTY:-fallback} ${EMPTY-fallback}x ${NAME:+set} ${#NAME}",
+                &mut state
+            )
+            .unwrap(),
+            vec!["fallback", "x", "set", "5"]
+        );
+        assert_eq!(
+            expand("${NEW:=made} $NEW", &mut state).unwrap(),
+            vec!["made", "made"]
+        );
+        assert!(expand("${MISSING:?no}", &mut state).is_err());
+        assert!(expand("${NAME!}", &mut state).is_err());
+    }
+
+    #[test]
+    fn splits_unquoted_expansions() {
+        let mut state = shell();
+        state.variables.set("FLAGS", " -l  -a ").unwrap();
+        state.variables.set("EMPTY", "").unwrap();
+
+        assert_eq!(
+            expand("ls $FLAGS", &mut state).unwrap(),
+            vec!["ls", "-l", "-a"]
+        );
+        assert_eq!(expand("\"$FLAGS\"", &mut state).unwrap(), vec![" -l  -a "]);
+        assert_eq!(
+            expand("a $EMPTY \"\" b", &mut state).unwrap(),
+            vec!["a", "", "b"]
+        );
+        assert_eq!(assignment("A=1=2"), Some(("A", "1=2")));
+        assert_eq!(assignment("1A=x"), None);
+    }
+
+    #[test]
+    fn leaves_a_lone_question_mark_command_alone() {
+        let dir = env::temp_dir().join(format!("dsh-question-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0"), "").unwrap();
+        let (pattern, file) = (
+            format!("{}/?", dir.display()),
+            format!("{}/0", dir.display()),
+        );
+        let mut state = shell();
+        let command = |line: &str| SimpleCommand {
+            words: line.split(' ').map(|w| Word(w.to_string())).collect(),
+            redirects: Vec::new(),
+        };
+        let mut expander = Expander::new(&mut state);
+        let explain = expander
+            .command(&command(&format!("? {pattern}")))
+            .map(|e| e.argv);
+        let echo = expander
+            .command(&command(&format!("echo {pattern}")))
+            .map(|e| e.argv);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(explain.unwrap(), vec!["?", &file]);
+        assert_eq!(echo.unwrap(), vec!["echo", &file]);
+    }
+
+    #[test]
+    fn joins_positional_parameters_to_the_text_around_them() {
+        let mut state = shell();
+        state
+            .variables
+            .set_positional(vec!["p".to_string(), "q r".to_string()]);
+
+        assert_eq!(
+            expand("a$@b \"a$@b\" x$*", &mut state).unwrap(),
+            vec!["ap", "q", "rb", "ap", "q rb", "xp", "q", "r"]
+        );
+    }
+
+    #[test]
+    fn expands_braces_tildes_and_substitutions() {
+        let mut state = shell();
+        state.variables.set("HOME", "/home/me").unwrap();
+
+        assert_eq!(
+            expand(
+                "a{b,c{d,e}}f x{1..3} {05..1..2} {c..a} {x} '{a,b}' ${HOME}",
+                &mut state
+            )
+            .unwrap(),
+            vec![
+                "abf", "acdf", "acef", "x1", "x2", "x3", "05", "03", "01", "c", "b", "a", "{x}",
+                "{a,b}", "/home/me"
+            ]
+        );
+        assert_eq!(
+            expand("~ ~/src \"~\" a~ ~no-such-user-here", &mut state).unwrap(),
+            vec!["/home/me", "/home/me/src", "~", "a~", "~no-such-user-here"]
+        );
+        assert_eq!(
+            expand("no-match-*.xyz \"*\"", &mut state).unwrap(),
+            vec!["no-match-*.xyz", "*"]
+        );
+
+        let words = [
+            Word("$(echo 'a  b' | tr a A)".to_string()),
+            Word("\"$(printf 'x\\n\\n'; false)\"".to_string()),
+            Word("$?".to_string()),
+        ];
+        let mut expander = Expander::new(&mut state);
+        assert_eq!(expander.fields(&words).unwrap(), vec!["A", "b", "x", "1"]);
+        assert_eq!(expander.substitution, Some(ExitStatus::FAILURE));
+    }
+
+    #[test]
+    fn runs_functions_and_compound_commands_in_substitutions() {
+        let mut state = shell();
+        let program = parser::parse("greet() { echo hello $1; }").unwrap();
+        let Command::Function(definition) = &program.items[0].and_or.first.commands[0] else {
+            panic!("not a function definition");
+        };
+        exec::define_function(definition, &mut state);
+        let cwd = std::env::current_dir().unwrap();
+
+        let words = [
+            Word("\"$(greet you)\"".to_string()),
+            Word("\"$(for i in 1 2; do echo $i; done | { cat; echo end; })\"".to_string()),
+            Word("$(cd /; echo $PWD)".to_string()),
+        ];
+        assert_eq!(
+            Expander::new(&mut state).fields(&words).unwrap(),
+            vec!["hello you", "1\n2\nend", "/"]
+        );
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+    }
+}
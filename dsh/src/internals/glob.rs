@@ -0,0 +1,267 @@
+//! Pathname expansion: `*`, `?` and `[...]` within a path component, and
+//! `**` for any number of directories. A backslash makes the next character
+//! literal; the expander uses that for characters that were quoted.
+
+use std::fs;
+use std::path::Path;
+
+/// Whether `pattern` has an unescaped `*`, `?` or `[` in it.
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The paths matching `pattern`, sorted. Names starting with a dot are only
+/// matched by a component that starts with one too, and `**` doesn't
+/// descend into them.
+pub fn expand(pattern: &str) -> Vec<String> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => ("/", rest.trim_start_matches('/')),
+        None => ("", pattern),
+    };
+    // Empty components are dropped, except a trailing one: `*/` matches
+    // only directories, and keeps the slash.
+    let parts: Vec<&str> = rest.split('/').collect();
+    let last = parts.len() - 1;
+    let components: Vec<&str> = parts
+        .into_iter()
+        .enumerate()
+        .filter(|(index, part)| !part.is_empty() || *index == last)
+        .map(|(_, part)| part)
+        .collect();
+
+    let mut found = Vec::new();
+    walk(root, &components, &mut found);
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Matches `components` below `dir`, which is empty for the current
+/// directory or ends with a slash.
+fn walk(dir: &str, components: &[&str], found: &mut Vec<String>) {
+    let Some((&component, rest)) = components.split_first() else {
+        found.push(dir.to_string());
+        return;
+    };
+
+    if component == "**" {
+        let visible = entries(dir)
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with('.'));
+        if rest.is_empty() {
+            // A trailing `**` is everything below, at any depth.
+            for (name, is_dir) in visible {
+                let path = format!("{}{}", dir, name);
+                if is_dir {
+                    walk(&format!("{}/", path), components, found);
+                }
+                found.push(path);
+            }
+        } else {
+            walk(dir, rest, found);
+            for (name, is_dir) in visible {
+                if is_dir {
+                    walk(&format!("{}{}/", dir, name), components, found);
+                }
+            }
+        }
+        return;
+    }
+
+    if !has_wildcards(component) {
+        let path = format!("{}{}", dir, unescape(component));
+        if rest.is_empty() {
+            if fs::symlink_metadata(&path).is_ok() {
+                found.push(path);
+            }
+        } else if Path::new(&path).is_dir() {
+            walk(&format!("{}/", path), rest, found);
+        }
+        return;
+    }
+
+    let show_hidden = component.starts_with('.') || component.starts_with("\\.");
+    for (name, is_dir) in entries(dir) {
+        if (name.starts_with('.') && !show_hidden) || !matches(component, &name) {
+            continue;
+        }
+        let path = format!("{}{}", dir, name);
+        if rest.is_empty() {
+            found.push(path);
+        } else if is_dir {
+            walk(&format!("{}/", path), rest, found);
+        }
+    }
+}
+
+/// The entries of `dir` and whether each is a directory, following
+/// symlinks.
+fn entries(dir: &str) -> Vec<(String, bool)> {
+    let path = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            Some((name, is_dir))
+        })
+        .collect()
+}
+
+/// Whether `name` matches the single-component `pattern`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            backtrack = Some((p, n));
+            continue;
+        }
+        if let Some(next) = step(&pattern, p, name[n]) {
+            p = next;
+            n += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star, from)) => {
+                p = star;
+                n = from + 1;
+                backtrack = Some((star, from + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the pattern element at `pattern[p]`, returning where
+/// the next element starts.
+fn step(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match class(&pattern[p..], c) {
+            Some((matched, len)) => matched.then_some(p + len),
+            // Without a closing `]` it's an ordinary character.
+            None => (c == '[').then_some(p + 1),
+        },
+        '\\' => match pattern.get(p + 1) {
+            Some(&escaped) => (escaped == c).then_some(p + 2),
+            None => (c == '\\').then_some(p + 1),
+        },
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Matches `c` against the bracket expression at the start of `pattern`,
+/// returning whether it matched and the expression's length, or `None`
+/// if the expression isn't closed.
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let start = i;
+    let mut matched = false;
+
+    loop {
+        let (low, len) = match *pattern.get(i)? {
+            // A `]` right at the start is part of the set.
+            ']' if i > start => return Some((matched != negated, i + 1)),
+            '\\' => (*pattern.get(i + 1)?, 2),
+            low => (low, 1),
+        };
+        i += len;
+
+        let is_range =
+            pattern.get(i) == Some(&'-') && pattern.get(i + 1).is_some_and(|&c| c != ']');
+        if is_range {
+            let (high, len) = match pattern[i + 1] {
+                '\\' => (*pattern.get(i + 2)?, 3),
+                high => (high, 2),
+            };
+            i += len;
+            matched |= low <= c && c <= high;
+        } else {
+            matched |= low == c;
+        }
+    }
+}
+
+/// Drops the backslashes that make characters literal.
+fn unescape(component: &str) -> String {
+    let mut unescaped = String::with_capacity(component.len());
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(matches("?at", "cat"));
+        assert!(matches("[a-c]at", "bat"));
+        assert!(!matches("[!a-c]at", "bat"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches("[oops", "[oops"));
+        assert!(has_wildcards("src/*.rs"));
+        assert!(!has_wildcards("\\*.rs"));
+    }
+
+    #[test]
+    fn expands_against_the_file_system() {
+        let dir = std::env::temp_dir().join(format!("dsh-glob-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in ["a.rs", "b.txt", ".hidden.rs", "src/c.rs", "src/deep/d.rs"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let root = format!("{}/", dir.display());
+        let expand = |pattern: &str| -> Vec<String> {
+            expand(&format!("{}{}", root, pattern))
+                .into_iter()
+                .map(|path| path[root.len()..].to_string())
+                .collect()
+        };
+
+        assert_eq!(expand("*.rs"), vec!["a.rs"]);
+        assert_eq!(expand(".*.rs"), vec![".hidden.rs"]);
+        assert_eq!(expand("*/"), vec!["src/"]);
+        assert_eq!(expand("**/*.rs"), vec!["a.rs", "src/c.rs", "src/deep/d.rs"]);
+        assert!(expand("*.md").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,755 @@
+use std::fmt;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use anyhow::{anyhow, Error, Result};
+use tokio::task::JoinHandle;
+
+use super::commands::Outcome;
+use super::diagnosis::FailureContext;
+use super::redirect::Capture;
+use super::signals;
+use super::status::ExitStatus;
+
+pub type Pid = libc::pid_t;
+
+/// Signals an interactive shell ignores for itself, so that only the
+/// foreground job is stopped by Ctrl+Z or by touching the terminal from the
+/// background. Children get the default behaviour back before exec.
+const JOB_SIGNALS: [libc::c_int; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+/// The process group a forked copy of the shell runs in.
+#[derive(Debug, Clone, Copy)]
+pub enum Group {
+    /// The shell's own, as for the commands of a `$(...)`.
+    Shell,
+    /// A job's, or a new one when `pgid` is `None`.
+    Job { pgid: Option<Pid>, foreground: bool },
+}
+
+/// Signal names understood by `kill`, without the `SIG` prefix.
+pub const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ABRT", libc::SIGABRT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("WINCH", libc::SIGWINCH),
+];
+
+/// Parses `9`, `KILL`, `kill` or `SIGKILL`.
+pub fn signal_number(spec: &str) -> Option<libc::c_int> {
+    if let Ok(number) = spec.parse::<libc::c_int>() {
+        return Some(number);
+    }
+    let name = spec.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, number)| number)
+}
+
+/// One process of a job. A stage that never started has no pid and is
+/// finished from the beginning.
+#[derive(Debug)]
+pub struct Process {
+    pid: Option<Pid>,
+    /// `None` while the process is running.
+    status: Option<ExitStatus>,
+}
+
+impl Process {
+    pub fn running(pid: Pid) -> Self {
+        Process {
+            pid: Some(pid),
+            status: None,
+        }
+    }
+
+    pub fn finished(status: ExitStatus) -> Self {
+        Process {
+            pid: None,
+            status: Some(status),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        !matches!(self.status, None | Some(ExitStatus::Stopped(_)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    /// At least one process was stopped by this signal.
+    Stopped(i32),
+    /// Every process finished; the status is the last stage's.
+    Done(ExitStatus),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => f.write_str("Running"),
+            JobState::Stopped(_) => f.write_str("Stopped"),
+            JobState::Done(ExitStatus::Exited(0)) => f.write_str("Done"),
+            JobState::Done(ExitStatus::Exited(code)) => write!(f, "Exit {}", code),
+            JobState::Done(ExitStatus::Signaled(signal)) => match *signal {
+                libc::SIGHUP => f.write_str("Hangup"),
+                libc::SIGINT => f.write_str("Interrupt"),
+                libc::SIGKILL => f.write_str("Killed"),
+                libc::SIGTERM => f.write_str("Terminated"),
+                signal => write!(f, "Signal {}", signal),
+            },
+            JobState::Done(status) => write!(f, "{}", status),
+        }
+    }
+}
+
+/// A pipeline the shell started, together with everything needed to report
+/// on it once it finishes, even if that happens after a `fg`.
+pub struct Job {
+    id: usize,
+    /// The process group of the job, when job control is on.
+    pgid: Option<Pid>,
+    command: String,
+    processes: Vec<Process>,
+    tees: Vec<(Capture, JoinHandle<Vec<u8>>)>,
+    /// Errors the shell itself reported while starting the job.
+    errors: String,
+    /// Terminal modes the job had when it was last stopped.
+    modes: Option<libc::termios>,
+    /// Whether the user has already been told about the current state.
+    notified: bool,
+}
+
+impl Job {
+    pub fn new(
+        command: String,
+        pgid: Option<Pid>,
+        processes: Vec<Process>,
+        tees: Vec<(Capture, JoinHandle<Vec<u8>>)>,
+        errors: String,
+    ) -> Self {
+        Job {
+            id: 0,
+            pgid,
+            command,
+            processes,
+            tees,
+            errors,
+            modes: None,
+            notified: false,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// The pid of the last stage that started, as reported for `$!`.
+    pub fn last_pid(&self) -> Option<Pid> {
+        self.processes.iter().rev().find_map(|process| process.pid)
+    }
+
+    fn pids(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.processes
+            .iter()
+            .filter(|process| !process.is_finished())
+            .filter_map(|process| process.pid)
+    }
+
+    pub fn state(&self) -> JobState {
+        let stopped = self
+            .processes
+            .iter()
+            .find_map(|process| match process.status {
+                Some(ExitStatus::Stopped(signal)) => Some(signal),
+                _ => None,
+            });
+        if let Some(signal) = stopped {
+            JobState::Stopped(signal)
+        } else if self
+            .processes
+            .iter()
+            .any(|process| process.status.is_none())
+        {
+            JobState::Running
+        } else {
+            let last = self.processes.last().and_then(|process| process.status);
+            JobState::Done(last.unwrap_or(ExitStatus::SUCCESS))
+        }
+    }
+
+    /// Records a status reported by `waitpid`. Returns whether `pid` belongs
+    /// to this job.
+    fn update(&mut self, pid: Pid, raw: libc::c_int) -> bool {
+        let Some(process) = self.processes.iter_mut().find(|p| p.pid == Some(pid)) else {
+            return false;
+        };
+        process.status = if libc::WIFCONTINUED(raw) {
+            None
+        } else {
+            Some(ExitStatus::from_wait(raw))
+        };
+        self.notified = false;
+        true
+    }
+
+    fn signal(&self, signal: libc::c_int) -> io::Result<()> {
+        let targets: Vec<Pid> = match self.pgid {
+            Some(pgid) => vec![-pgid],
+            None => self.pids().collect(),
+        };
+        for target in targets {
+            if unsafe { libc::kill(target, signal) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Continues a stopped job. Processes that already went away are not an
+    /// error; the next wait picks up their status.
+    fn resume(&mut self) {
+        let _ = self.signal(libc::SIGCONT);
+        for process in &mut self.processes {
+            if let Some(ExitStatus::Stopped(_)) = process.status {
+                process.status = None;
+            }
+        }
+    }
+
+    /// Blocks until every process has finished or the job is stopped. The
+    /// runtime is told, so that the other tasks of the thread, such as the
+    /// one forwarding signals to the job, are moved to another meanwhile.
+    fn wait(&mut self) {
+        tokio::task::block_in_place(|| self.wait_blocking());
+    }
+
+    fn wait_blocking(&mut self) {
+        while self.state() == JobState::Running {
+            let target = match self.pgid {
+                Some(pgid) => -pgid,
+                None => match self.pids().next() {
+                    Some(pid) => pid,
+                    None => break,
+                },
+            };
+
+            let mut raw = 0;
+            let pid = unsafe { libc::waitpid(target, &mut raw, libc::WUNTRACED) };
+            if pid < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                // Nothing left to wait for: the processes were reaped elsewhere.
+                for process in &mut self.processes {
+                    if process.status.is_none() {
+                        process.status = Some(ExitStatus::FAILURE);
+                    }
+                }
+                break;
+            }
+            self.update(pid, raw);
+        }
+    }
+
+    /// Collects what a finished job wrote and turns it into an [`Outcome`],
+    /// with a [`FailureContext`] if any of its stages failed.
+    pub async fn finish(self) -> Result<Outcome, Error> {
+        let statuses: Vec<ExitStatus> = self
+            .processes
+            .iter()
+            .map(|process| process.status.unwrap_or(ExitStatus::FAILURE))
+            .collect();
+
+        let mut stdout = Vec::new();
+        let mut stderr = self.errors.into_bytes();
+        for (capture, tee) in self.tees {
+            let captured = tee.await?;
+            match capture {
+                Capture::Stdout => stdout.extend(captured),
+                Capture::Stderr => stderr.extend(captured),
+            }
+        }
+
+        let status = *statuses.last().expect("job has at least one process");
+
+        let mut failure = None;
+        if statuses.iter().any(|status| !status.success()) {
+            failure = Some(FailureContext {
+                command_line: self.command,
+                status: statuses
+                    .iter()
+                    .rev()
+                    .find(|status| !status.success())
+                    .copied()
+                    .unwrap_or(status),
+                stdout: String::from_utf8_lossy(&stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+                history: Vec::new(),
+            });
+        }
+
+        Ok(Outcome { status, failure })
+    }
+}
+
+/// The controlling terminal of an interactive shell.
+struct Terminal {
+    /// A private copy of the terminal descriptor, so redirecting stdin of a
+    /// child doesn't keep it from taking the terminal.
+    fd: RawFd,
+    shell_pgid: Pid,
+    shell_modes: libc::termios,
+}
+
+impl Terminal {
+    /// Puts the shell in its own process group in the foreground of the
+    /// terminal. Returns `None`, which leaves job control off, when stdin
+    /// isn't a terminal.
+    fn take_control() -> Option<Terminal> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+            let fd = libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10);
+            if fd < 0 {
+                return None;
+            }
+
+            // Started in the background: wait until we are brought forward.
+            loop {
+                let pgid = libc::getpgrp();
+                if libc::tcgetpgrp(fd) == pgid {
+                    break;
+                }
+                libc::kill(-pgid, libc::SIGTTIN);
+            }
+
+            for signal in JOB_SIGNALS {
+                libc::signal(signal, libc::SIG_IGN);
+            }
+
+            let pid = libc::getpid();
+            if libc::getpgrp() != pid && libc::setpgid(pid, pid) < 0 {
+                return None;
+            }
+            libc::tcsetpgrp(fd, pid);
+
+            let mut shell_modes = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut shell_modes) < 0 {
+                return None;
+            }
+            Some(Terminal {
+                fd,
+                shell_pgid: pid,
+                shell_modes,
+            })
+        }
+    }
+
+    fn give(&self, pgid: Pid, modes: Option<&libc::termios>) {
+        unsafe {
+            if let Some(modes) = modes {
+                libc::tcsetattr(self.fd, libc::TCSADRAIN, modes);
+            }
+            libc::tcsetpgrp(self.fd, pgid);
+        }
+    }
+
+    /// Takes the terminal back from a job and restores the shell's modes,
+    /// returning the modes the job left behind.
+    fn reclaim(&self) -> libc::termios {
+        unsafe {
+            let mut modes = std::mem::zeroed();
+            libc::tcgetattr(self.fd, &mut modes);
+            libc::tcsetpgrp(self.fd, self.shell_pgid);
+            libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.shell_modes);
+            modes
+        }
+    }
+}
+
+/// Jobs that are running in the background or stopped.
+///
+/// Instead of handling SIGCHLD asynchronously, the shell polls with
+/// `waitpid(WNOHANG)` before every prompt and whenever a builtin looks at
+/// the table, which keeps all bookkeeping on the main thread.
+pub struct JobTable {
+    /// Ordered by when each job was last stopped or sent to the background,
+    /// so the last one is the current job (`%+`) and the one before it the
+    /// previous job (`%-`).
+    jobs: Vec<Job>,
+    terminal: Option<Terminal>,
+    /// The last process put in the background, for `$!`.
+    last_background: Option<Pid>,
+}
+
+impl JobTable {
+    /// Job control is only for interactive shells, and only when there is
+    /// a terminal to hand to the jobs.
+    pub fn new(job_control: bool) -> Self {
+        JobTable {
+            jobs: Vec::new(),
+            terminal: job_control.then(Terminal::take_control).flatten(),
+            last_background: None,
+        }
+    }
+
+    pub fn job_control(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn get(&self, index: usize) -> &Job {
+        &self.jobs[index]
+    }
+
+    /// Makes a child join the job's process group, or start one when `pgid`
+    /// is `None`, and take the terminal if it runs in the foreground.
+    pub fn prepare(&self, command: &mut Command, pgid: Option<Pid>, foreground: bool) {
+        let terminal = match &self.terminal {
+            Some(terminal) => {
+                command.process_group(pgid.unwrap_or(0));
+                foreground.then_some(terminal.fd)
+            }
+            None => None,
+        };
+        // Only async-signal-safe calls are allowed between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(fd) = terminal {
+                    libc::tcsetpgrp(fd, libc::getpgrp());
+                }
+                for signal in JOB_SIGNALS {
+                    libc::signal(signal, libc::SIG_DFL);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Moves a forked copy of the shell into `group`, as [`prepare`] does
+    /// for a program, and leaves it an empty table without the terminal:
+    /// the jobs are the shell's to wait for.
+    ///
+    /// [`prepare`]: JobTable::prepare
+    pub fn enter(&mut self, group: Group) {
+        if let (Group::Job { pgid, foreground }, Some(terminal)) = (group, &self.terminal) {
+            unsafe {
+                libc::setpgid(0, pgid.unwrap_or(0));
+                if foreground {
+                    libc::tcsetpgrp(terminal.fd, libc::getpgrp());
+                }
+            }
+        }
+        for signal in JOB_SIGNALS {
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+        }
+        let table = JobTable {
+            jobs: Vec::new(),
+            terminal: None,
+            last_background: self.last_background,
+        };
+        // The jobs' tees belong to the shell's runtime, which has no threads
+        // left in the copy to drop them on.
+        std::mem::forget(std::mem::replace(self, table));
+    }
+
+    /// Puts a freshly spawned child in its process group from the shell's
+    /// side as well, since either side may run first.
+    pub fn adopt(&self, pid: Pid, pgid: &mut Option<Pid>) {
+        if self.job_control() {
+            let pgid = *pgid.get_or_insert(pid);
+            unsafe { libc::setpgid(pid, pgid) };
+        }
+    }
+
+    /// Adds a job and returns its number.
+    pub fn add(&mut self, mut job: Job) -> usize {
+        job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        let id = job.id;
+        self.jobs.push(job);
+        id
+    }
+
+    /// Adds a job started with `&`.
+    pub fn add_background(&mut self, job: Job) -> usize {
+        self.last_background = job.last_pid().or(self.last_background);
+        self.add(job)
+    }
+
+    pub fn last_background(&self) -> Option<Pid> {
+        self.last_background
+    }
+
+    /// Removes a job, e.g. to bring it to the foreground.
+    pub fn remove(&mut self, index: usize) -> Job {
+        self.jobs.remove(index)
+    }
+
+    /// Finds a job by spec: `%n`, `%%`, `%+`, `%-`, `%name` for a command
+    /// starting with `name`, or `%?text` for one containing `text`. `None`
+    /// means the current job.
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, Error> {
+        let spec = spec.unwrap_or("%+");
+        let Some(name) = spec.strip_prefix('%') else {
+            return Err(anyhow!("{}: no such job", spec));
+        };
+
+        let found = match name {
+            "" | "%" | "+" => self.jobs.len().checked_sub(1),
+            "-" => self
+                .jobs
+                .len()
+                .checked_sub(2)
+                .or(self.jobs.len().checked_sub(1)),
+            _ => match name.parse::<usize>() {
+                Ok(id) => self.jobs.iter().position(|job| job.id == id),
+                Err(_) => {
+                    let matches: Vec<usize> = (0..self.jobs.len())
+                        .filter(|&index| match name.strip_prefix('?') {
+                            Some(text) => self.jobs[index].command.contains(text),
+                            None => self.jobs[index].command.starts_with(name),
+                        })
+                        .collect();
+                    if matches.len() > 1 {
+                        return Err(anyhow!("{}: ambiguous job spec", spec));
+                    }
+                    matches.first().copied()
+                }
+            },
+        };
+
+        found.ok_or_else(|| match name {
+            "" | "%" | "+" | "-" => anyhow!("{}: no current job", spec),
+            _ => anyhow!("{}: no such job", spec),
+        })
+    }
+
+    /// Finds the job a process belongs to.
+    pub fn find_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs
+            .iter()
+            .position(|job| job.processes.iter().any(|p| p.pid == Some(pid)))
+    }
+
+    /// A line for `jobs` and notifications, e.g. `[1]+  Stopped  vim`.
+    pub fn describe(&self, index: usize) -> String {
+        let marker = match self.jobs.len() - index {
+            1 => '+',
+            2 => '-',
+            _ => ' ',
+        };
+        let job = &self.jobs[index];
+        format!(
+            "[{}]{}  {:<24}{}",
+            job.id,
+            marker,
+            job.state().to_string(),
+            job.command
+        )
+    }
+
+    /// Runs a job in the foreground until it finishes or is stopped. A
+    /// stopped job goes into the table and becomes the current job.
+    pub async fn foreground(&mut self, mut job: Job, resume: bool) -> Result<Outcome, Error> {
+        if let (Some(terminal), Some(pgid)) = (&self.terminal, job.pgid) {
+            terminal.give(pgid, job.modes.as_ref());
+        }
+        if resume {
+            job.resume();
+        }
+
+        signals::set_foreground(job.pgid);
+        job.wait();
+        signals::set_foreground(None);
+
+        if let Some(terminal) = &self.terminal {
+            job.modes = Some(terminal.reclaim());
+        }
+        if job.state() == JobState::Done(ExitStatus::Signaled(libc::SIGINT)) {
+            // The terminal echoed ^C without a newline.
+            println!();
+        }
+
+        match job.state() {
+            JobState::Stopped(signal) => {
+                job.notified = true;
+                self.add(job);
+                println!("\n{}", self.describe(self.jobs.len() - 1));
+                Ok(Outcome::new(ExitStatus::Stopped(signal)))
+            }
+            _ => job.finish().await,
+        }
+    }
+
+    /// Continues a stopped job in the background, as `bg` does.
+    pub fn resume_in_background(&mut self, index: usize) -> Result<(), Error> {
+        match self.jobs[index].state() {
+            JobState::Stopped(_) => {}
+            JobState::Running => {
+                return Err(anyhow!("job {} already in background", self.jobs[index].id))
+            }
+            JobState::Done(_) => return Err(anyhow!("job has terminated")),
+        }
+
+        let mut job = self.jobs.remove(index);
+        job.resume();
+        job.notified = true;
+        self.last_background = job.last_pid().or(self.last_background);
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    /// Sends `signal` to every process of a job. A stopped job is continued
+    /// as well so it can act on the signal.
+    pub fn kill(&self, index: usize, signal: libc::c_int) -> io::Result<()> {
+        let job = &self.jobs[index];
+        job.signal(signal)?;
+        if matches!(job.state(), JobState::Stopped(_))
+            && matches!(signal, libc::SIGHUP | libc::SIGTERM)
+        {
+            job.signal(libc::SIGCONT)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks until a job finishes, as `wait` does, and forgets it. A job
+    /// that stops instead stays in the table.
+    pub fn wait(&mut self, index: usize) -> ExitStatus {
+        self.jobs[index].wait();
+        match self.jobs[index].state() {
+            JobState::Stopped(signal) => ExitStatus::Stopped(signal),
+            JobState::Done(status) => {
+                self.jobs.remove(index);
+                status
+            }
+            JobState::Running => unreachable!("wait returned while the job is running"),
+        }
+    }
+
+    /// Picks up status changes of all jobs without blocking.
+    pub fn reap(&mut self) {
+        loop {
+            let mut raw = 0;
+            let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+            let pid = unsafe { libc::waitpid(-1, &mut raw, flags) };
+            if pid <= 0 {
+                break;
+            }
+            for job in &mut self.jobs {
+                if job.update(pid, raw) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Forgets jobs that have finished.
+    pub fn forget_finished(&mut self) {
+        self.jobs
+            .retain(|job| !matches!(job.state(), JobState::Done(_)));
+    }
+
+    /// Tells the user about jobs that finished or stopped since the last
+    /// prompt, like `[1]+  Done  cargo build`.
+    pub fn notify(&mut self) {
+        self.reap();
+        for index in 0..self.jobs.len() {
+            let job = &self.jobs[index];
+            if !job.notified && job.state() != JobState::Running {
+                println!("{}", self.describe(index));
+                self.jobs[index].notified = true;
+            }
+        }
+        self.forget_finished();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(commands: &[&str]) -> JobTable {
+        let mut table = JobTable {
+            jobs: Vec::new(),
+            terminal: None,
+            last_background: None,
+        };
+        for command in commands {
+            let process = Process::finished(ExitStatus::Stopped(libc::SIGTSTP));
+            table.add(Job::new(
+                command.to_string(),
+                None,
+                vec![process],
+                Vec::new(),
+                String::new(),
+            ));
+        }
+        table
+    }
+
+    #[test]
+    fn finds_jobs_by_spec() {
+        let table = table(&["vim notes.md", "cargo build", "cargo test"]);
+        assert_eq!(table.find(None).unwrap(), 2);
+        assert_eq!(table.find(Some("%-")).unwrap(), 1);
+        assert_eq!(table.find(Some("%1")).unwrap(), 0);
+        assert_eq!(table.find(Some("%vim")).unwrap(), 0);
+        assert_eq!(table.find(Some("%?test")).unwrap(), 2);
+        assert!(table.find(Some("%cargo")).is_err());
+        assert!(table.find(Some("%4")).is_err());
+        assert!(table.find(Some("1")).is_err());
+    }
+
+    #[test]
+    fn describes_jobs() {
+        let table = table(&["vim notes.md", "cargo build"]);
+        assert_eq!(
+            table.describe(1),
+            format!("[2]+  {:<24}cargo build", "Stopped")
+        );
+        assert_eq!(
+            table.describe(0),
+            format!("[1]-  {:<24}vim notes.md", "Stopped")
+        );
+        assert_eq!(
+            JobState::Done(ExitStatus::Signaled(libc::SIGKILL)).to_string(),
+            "Killed"
+        );
+        assert_eq!(JobState::Done(ExitStatus::Exited(2)).to_string(), "Exit 2");
+    }
+
+    #[test]
+    fn parses_signal_names() {
+        assert_eq!(signal_number("9"), Some(9));
+        assert_eq!(signal_number("term"), Some(libc::SIGTERM));
+        assert_eq!(signal_number("SIGCONT"), Some(libc::SIGCONT));
+        assert_eq!(signal_number("BOGUS"), None);
+    }
+}
@@ -0,0 +1,428 @@
+use std::fmt;
+
+use super::parser::ParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Pipe,
+    AndIf,
+    OrIf,
+    Semi,
+    /// `;;`, which ends an item of a `case`.
+    DSemi,
+    Amp,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `<`
+    Less,
+    /// `>`
+    Great,
+    /// `>>`
+    DGreat,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    LessGreat,
+    /// `<&`
+    LessAnd,
+    /// `>&`
+    GreatAnd,
+    /// `&>`
+    AndGreat,
+    /// `&>>`
+    AndDGreat,
+    /// `<<<`
+    TLess,
+}
+
+impl Operator {
+    pub fn is_redirection(&self) -> bool {
+        !matches!(
+            self,
+            Self::Pipe
+                | Self::AndIf
+                | Self::OrIf
+                | Self::Semi
+                | Self::DSemi
+                | Self::Amp
+                | Self::LParen
+                | Self::RParen
+        )
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pipe => "|",
+            Self::AndIf => "&&",
+            Self::OrIf => "||",
+            Self::Semi => ";",
+            Self::DSemi => ";;",
+            Self::Amp => "&",
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::Less => "<",
+            Self::Great => ">",
+            Self::DGreat => ">>",
+            Self::Clobber => ">|",
+            Self::LessGreat => "<>",
+            Self::LessAnd => "<&",
+            Self::GreatAnd => ">&",
+            Self::AndGreat => "&>",
+            Self::AndDGreat => "&>>",
+            Self::TLess => "<<<",
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A word exactly as typed, quotes and escapes included. Quote removal
+    /// happens later so that expansion can still tell quoted text apart.
+    Word(String),
+    /// The file descriptor number in front of a redirection, as in `2>`.
+    IoNumber(u32),
+    Op(Operator),
+    Newline,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => f.write_str(word),
+            Token::IoNumber(fd) => write!(f, "{}", fd),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Newline => f.write_str("newline"),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    Lexer {
+        chars: input.chars().collect(),
+        pos: 0,
+    }
+    .run()
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn run(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.pos += 1;
+                }
+                '\n' => {
+                    self.pos += 1;
+                    tokens.push(Token::Newline);
+                }
+                '#' => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                '\\' if self.peek_at(1) == Some('\n') => {
+                    // Line continuation between words.
+                    self.pos += 2;
+                }
+                '|' | '&' | ';' | '<' | '>' | '(' | ')' => {
+                    tokens.push(Token::Op(self.operator()));
+                }
+                _ => {
+                    // The `1` of `2>&1>out` is the target of `>&`, not the
+                    // descriptor of `>out`.
+                    let is_target =
+                        matches!(tokens.last(), Some(Token::Op(op)) if op.is_redirection());
+                    let word = self.word()?;
+                    let is_io_number = !is_target
+                        && matches!(self.peek(), Some('<' | '>'))
+                        && word.chars().all(|c| c.is_ascii_digit());
+                    match word.parse::<u32>() {
+                        Ok(fd) if is_io_number => tokens.push(Token::IoNumber(fd)),
+                        _ => tokens.push(Token::Word(word)),
+                    }
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn operator(&mut self) -> Operator {
+        let first = self.bump().expect("operator start");
+        let second = self.peek();
+        let third = self.peek_at(1);
+
+        let (op, extra) = match (first, second, third) {
+            ('&', Some('&'), _) => (Operator::AndIf, 1),
+            ('&', Some('>'), Some('>')) => (Operator::AndDGreat, 2),
+            ('&', Some('>'), _) => (Operator::AndGreat, 1),
+            ('&', _, _) => (Operator::Amp, 0),
+            ('|', Some('|'), _) => (Operator::OrIf, 1),
+            ('|', _, _) => (Operator::Pipe, 0),
+            (';', Some(';'), _) => (Operator::DSemi, 1),
+            (';', _, _) => (Operator::Semi, 0),
+            ('(', _, _) => (Operator::LParen, 0),
+            (')', _, _) => (Operator::RParen, 0),
+            ('<', Some('<'), Some('<')) => (Operator::TLess, 2),
+            ('<', Some('&'), _) => (Operator::LessAnd, 1),
+            ('<', Some('>'), _) => (Operator::LessGreat, 1),
+            ('<', _, _) => (Operator::Less, 0),
+            ('>', Some('>'), _) => (Operator::DGreat, 1),
+            ('>', Some('&'), _) => (Operator::GreatAnd, 1),
+            ('>', Some('|'), _) => (Operator::Clobber, 1),
+            ('>', _, _) => (Operator::Great, 0),
+            _ => unreachable!("not an operator character: {}", first),
+        };
+        self.pos += extra;
+        op
+    }
+
+    /// Reads one word, keeping quotes and backslashes in the returned text.
+    fn word(&mut self) -> Result<String, ParseError> {
+        let mut word = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | '|' | '&' | ';' | '<' | '>' | '(' | ')' => break,
+                '\\' => {
+                    self.pos += 1;
+                    match self.bump() {
+                        // An escaped newline joins the two lines.
+                        Some('\n') => {}
+                        Some(escaped) => {
+                            word.push('\\');
+                            word.push(escaped);
+                        }
+                        None => return Err(ParseError::Incomplete("\\")),
+                    }
+                }
+                '\'' => {
+                    word.push(c);
+                    self.pos += 1;
+                    loop {
+                        match self.bump() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => return Err(ParseError::Incomplete("'")),
+                        }
+                    }
+                    word.push('\'');
+                }
+                '"' => {
+                    word.push(c);
+                    self.pos += 1;
+                    loop {
+                        match self.bump() {
+                            Some('"') => break,
+                            Some('\\') => match self.bump() {
+                                Some(escaped) => {
+                                    word.push('\\');
+                                    word.push(escaped);
+                                }
+                                None => return Err(ParseError::Incomplete("\"")),
+                            },
+                            Some('$') if matches!(self.peek(), Some('{' | '(')) => {
+                                self.pos -= 1;
+                                self.substitution(&mut word)?;
+                            }
+                            Some(c) => word.push(c),
+                            None => return Err(ParseError::Incomplete("\"")),
+                        }
+                    }
+                    word.push('"');
+                }
+                '$' if matches!(self.peek_at(1), Some('{' | '(')) => {
+                    self.substitution(&mut word)?;
+                }
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Copies a `${...}` or `$(...)` whole, so that blanks, quotes and
+    /// operators inside it don't end the word. Starts at the `$`.
+    fn substitution(&mut self, word: &mut String) -> Result<(), ParseError> {
+        word.push(self.bump().expect("substitution start"));
+        let open = self.bump().expect("substitution bracket");
+        word.push(open);
+        let (close, incomplete) = match open {
+            '{' => ('}', "${"),
+            _ => (')', "$("),
+        };
+
+        let mut depth = 1;
+        let mut quote = None;
+        while depth > 0 {
+            let c = self.bump().ok_or(ParseError::Incomplete(incomplete))?;
+            word.push(c);
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some('"') | None, '\\') => {
+                    if let Some(escaped) = self.bump() {
+                        word.push(escaped);
+                    }
+                }
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, c) if c == open => depth += 1,
+                (None, c) if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Performs quote removal on a raw word from the lexer, without expanding
+/// anything, to check what the lexer produced.
+#[cfg(test)]
+pub fn unquote(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            }
+            '\'' => {
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    out.push(c);
+                }
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(escaped @ ('$' | '`' | '"' | '\\' | '\n')) => out.push(escaped),
+                            Some(other) => {
+                                out.push('\\');
+                                out.push(other);
+                            }
+                            None => out.push('\\'),
+                        },
+                        _ => out.push(c),
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(unquote(&word)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quoted_arguments_stay_together() {
+        assert_eq!(
+            words(r#"git commit -m "fix bug""#),
+            vec!["git", "commit", "-m", "fix bug"]
+        );
+        assert_eq!(words("echo 'a  b' c\\ d"), vec!["echo", "a  b", "c d"]);
+        assert_eq!(
+            words(r#"echo "say \"hi\" \n""#),
+            vec!["echo", r#"say "hi" \n"#]
+        );
+    }
+
+    #[test]
+    fn operators_and_comments() {
+        let tokens = tokenize("a|b&&c 2>&1 >>log # trailing").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("a".into()),
+                Token::Op(Operator::Pipe),
+                Token::Word("b".into()),
+                Token::Op(Operator::AndIf),
+                Token::Word("c".into()),
+                Token::IoNumber(2),
+                Token::Op(Operator::GreatAnd),
+                Token::Word("1".into()),
+                Token::Op(Operator::DGreat),
+                Token::Word("log".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_incomplete() {
+        assert!(matches!(
+            tokenize("echo \"oops"),
+            Err(ParseError::Incomplete(_))
+        ));
+        assert!(matches!(
+            tokenize("echo ${HOME"),
+            Err(ParseError::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn substitutions_stay_in_one_word() {
+        let tokens = tokenize(r#"echo ${A:-a b}x "$(ls | wc -l)";"#).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("echo".into()),
+                Token::Word("${A:-a b}x".into()),
+                Token::Word(r#""$(ls | wc -l)""#.into()),
+                Token::Op(Operator::Semi),
+            ]
+        );
+    }
+}
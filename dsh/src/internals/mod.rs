@@ -1,2 +1,19 @@
+pub mod ast;
+pub mod commands;
+pub mod completion;
+pub mod diagnosis;
+pub mod exec;
+pub mod expand;
+pub mod glob;
+pub mod jobs;
+pub mod lexer;
+pub mod options;
+pub mod parser;
+pub mod prompt;
+pub mod redirect;
 pub mod shell;
-pub mod commands;
\ No newline at end of file
+pub mod signals;
+pub mod status;
+pub mod subshell;
+pub mod suggest;
+pub mod variables;
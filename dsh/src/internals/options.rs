@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+
+/// The options `set` turns on and off, which dsh also takes on its own
+/// command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    /// `-e`: exit as soon as a command fails where nothing checks it.
+    pub errexit: bool,
+    /// `-u`: expanding an unset variable is an error.
+    pub nounset: bool,
+    /// `-x`: print each command, expanded, before running it.
+    pub xtrace: bool,
+    /// A pipeline fails with the status of its last stage that failed,
+    /// rather than with its last stage's.
+    pub pipefail: bool,
+}
+
+/// Every option's long name, for `-o`, with its letter if it has one.
+pub const NAMES: &[(&str, Option<char>)] = &[
+    ("errexit", Some('e')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+impl Options {
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut options = *self;
+        options.field(name).map(|on| *on)
+    }
+
+    /// Turns the option called `name`, as in `set -o name`, on or off.
+    pub fn set(&mut self, name: &str, on: bool) -> Result<()> {
+        match self.field(name) {
+            Some(option) => {
+                *option = on;
+                Ok(())
+            }
+            None => Err(anyhow!("{}: invalid option name", name)),
+        }
+    }
+
+    /// Turns the option with the letter `flag`, as in `set -x`, on or off.
+    pub fn set_flag(&mut self, flag: char, on: bool) -> Result<()> {
+        let name = NAMES
+            .iter()
+            .find(|(_, letter)| *letter == Some(flag))
+            .map(|(name, _)| *name)
+            .ok_or_else(|| anyhow!("-{}: invalid option", flag))?;
+        self.set(name, on)
+    }
+
+    fn field(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_options_by_name_and_letter() {
+        let mut options = Options::default();
+        options.set_flag('e', true).unwrap();
+        options.set("pipefail", true).unwrap();
+        options.set_flag('x', true).unwrap();
+        options.set_flag('x', false).unwrap();
+
+        assert!(options.errexit && options.pipefail && !options.xtrace);
+        assert_eq!(options.get("nounset"), Some(false));
+        assert!(options.set_flag('q', true).is_err());
+        assert!(options.set("monitor", true).is_err());
+        for (name, _) in NAMES {
+            assert!(options.get(name).is_some());
+        }
+    }
+}
@@ -0,0 +1,714 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::ast::{
+    AndOrList, CaseItem, Command, Compound, CompoundKind, Connector, FunctionDefinition, ListItem,
+    Pipeline, Program, Redirect, RedirectKind, SimpleCommand, Word,
+};
+use super::lexer::{self, Operator, Token};
+use super::variables;
+
+/// Words that end a compound command or one of its parts, and so can't
+/// start a simple one.
+const CLOSING_WORDS: &[&str] = &["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input stopped in the middle of a construct, e.g. an open quote
+    /// or a trailing `|`. More input could still make it valid.
+    Incomplete(&'static str),
+    UnexpectedToken(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete(what) => {
+                write!(
+                    f,
+                    "syntax error: unexpected end of input, expecting `{}'",
+                    what
+                )
+            }
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "syntax error near unexpected token `{}'", token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(input: &str) -> Result<Program, ParseError> {
+    let tokens = lexer::tokenize(input)?;
+    Parser::new(tokens, None).program()
+}
+
+/// Parses `input`, replacing aliases in `aliases` where a command name
+/// could be. An alias isn't expanded again inside its own expansion, so
+/// `alias ls='ls -F'` works, and one whose value ends in a blank makes the
+/// word after it a candidate too.
+pub fn parse_with_aliases(
+    input: &str,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Program, ParseError> {
+    let tokens = lexer::tokenize(input)?;
+    Parser::new(tokens, Some(aliases)).program()
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    aliases: Option<&'a BTreeMap<String, String>>,
+    /// Aliases being expanded, each with the end of the tokens it put in
+    /// place of its name.
+    expanding: Vec<(String, usize)>,
+    /// Where the word after an alias ending in a blank starts.
+    alias_next: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<Token>, aliases: Option<&'a BTreeMap<String, String>>) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            aliases,
+            expanding: Vec::new(),
+            alias_next: None,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: Operator) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => ParseError::UnexpectedToken(token.to_string()),
+            None => ParseError::Incomplete("command"),
+        }
+    }
+
+    fn program(mut self) -> Result<Program, ParseError> {
+        let program = self.list(&[])?;
+        match self.peek() {
+            Some(_) => Err(self.unexpected()),
+            None => Ok(program),
+        }
+    }
+
+    /// Parses and-or lists up to the end of the input, up to `;;`, or up
+    /// to one of the reserved words `ends` at the start of a command. When
+    /// there are `ends`, the input running out first is incomplete.
+    fn list(&mut self, ends: &[&'static str]) -> Result<Program, ParseError> {
+        let mut program = Program::default();
+
+        self.skip_newlines();
+        while let Some(token) = self.peek() {
+            if *token == Token::Op(Operator::DSemi)
+                || self.peek_word().is_some_and(|word| ends.contains(&word))
+            {
+                break;
+            }
+            let and_or = self.and_or()?;
+            let background = match self.peek() {
+                Some(Token::Op(Operator::Amp)) => true,
+                Some(Token::Op(Operator::Semi)) | Some(Token::Newline) => false,
+                // Ends the command as well as the `case` item.
+                Some(Token::Op(Operator::DSemi)) => {
+                    program.items.push(ListItem {
+                        and_or,
+                        background: false,
+                    });
+                    break;
+                }
+                None => match ends.first() {
+                    Some(end) => return Err(ParseError::Incomplete(end)),
+                    None => false,
+                },
+                Some(_) => return Err(self.unexpected()),
+            };
+            self.pos += 1;
+            program.items.push(ListItem { and_or, background });
+            self.skip_newlines();
+        }
+
+        Ok(program)
+    }
+
+    /// Parses the list of a compound command, which has to have at least
+    /// one command in it.
+    fn body(&mut self, ends: &[&'static str]) -> Result<Program, ParseError> {
+        let body = self.list(ends)?;
+        match (body.items.is_empty(), self.peek()) {
+            (false, _) => Ok(body),
+            (true, Some(_)) => Err(self.unexpected()),
+            (true, None) => Err(ParseError::Incomplete(ends[0])),
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOrList, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let connector = if self.eat_op(Operator::AndIf) {
+                Connector::And
+            } else if self.eat_op(Operator::OrIf) {
+                Connector::Or
+            } else {
+                break;
+            };
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+
+        Ok(AndOrList { first, rest })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.peek() == Some(&Token::Word("!".to_string()));
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut commands = vec![self.command()?];
+        while self.eat_op(Operator::Pipe) {
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        while self.expand_alias()? {}
+
+        if let Some(compound) = self.compound()? {
+            return Ok(Command::Compound(compound));
+        }
+        match self.peek_word() {
+            Some(word) if CLOSING_WORDS.contains(&word) => return Err(self.unexpected()),
+            _ => {}
+        }
+        if let Some(function) = self.function()? {
+            return Ok(Command::Function(function));
+        }
+        self.simple_command().map(Command::Simple)
+    }
+
+    /// Parses a compound command and the redirections after it, or returns
+    /// `None` if the next token doesn't start one.
+    fn compound(&mut self) -> Result<Option<Compound>, ParseError> {
+        let kind = match self.peek_word() {
+            Some("{") => {
+                self.pos += 1;
+                let body = self.body(&["}"])?;
+                self.expect("}")?;
+                CompoundKind::Group(body)
+            }
+            Some("if") => {
+                self.pos += 1;
+                self.if_clause()?
+            }
+            Some(keyword @ ("while" | "until")) => {
+                let until = keyword == "until";
+                self.pos += 1;
+                let condition = self.body(&["do"])?;
+                self.expect("do")?;
+                let body = self.body(&["done"])?;
+                self.expect("done")?;
+                CompoundKind::Loop {
+                    until,
+                    condition,
+                    body,
+                }
+            }
+            Some("for") => {
+                self.pos += 1;
+                self.for_clause()?
+            }
+            Some("case") => {
+                self.pos += 1;
+                self.case_clause()?
+            }
+            _ => return Ok(None),
+        };
+
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(Some(Compound { kind, redirects }))
+    }
+
+    /// Parses what follows `if`, up to and including `fi`.
+    fn if_clause(&mut self) -> Result<CompoundKind, ParseError> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.body(&["then"])?;
+            self.expect("then")?;
+            let body = self.body(&["fi", "elif", "else"])?;
+            branches.push((condition, body));
+            match self.peek_word() {
+                Some("elif") => self.pos += 1,
+                Some("else") => {
+                    self.pos += 1;
+                    let otherwise = self.body(&["fi"])?;
+                    self.expect("fi")?;
+                    return Ok(CompoundKind::If {
+                        branches,
+                        otherwise: Some(otherwise),
+                    });
+                }
+                _ => {
+                    self.expect("fi")?;
+                    return Ok(CompoundKind::If {
+                        branches,
+                        otherwise: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Parses what follows `for`, up to and including `done`.
+    fn for_clause(&mut self) -> Result<CompoundKind, ParseError> {
+        let name = match self.next() {
+            Some(Token::Word(name)) if variables::is_name(&name) => name,
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => return Err(ParseError::Incomplete("name")),
+        };
+        self.skip_newlines();
+
+        let mut words = None;
+        if self.peek_word() == Some("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(Word(word.clone()));
+                self.pos += 1;
+            }
+            match self.peek() {
+                Some(Token::Op(Operator::Semi)) | Some(Token::Newline) => self.pos += 1,
+                Some(_) => return Err(self.unexpected()),
+                None => return Err(ParseError::Incomplete("do")),
+            }
+            words = Some(list);
+        } else {
+            self.eat_op(Operator::Semi);
+        }
+        self.skip_newlines();
+
+        self.expect("do")?;
+        let body = self.body(&["done"])?;
+        self.expect("done")?;
+        Ok(CompoundKind::For { name, words, body })
+    }
+
+    /// Parses what follows `case`, up to and including `esac`.
+    fn case_clause(&mut self) -> Result<CompoundKind, ParseError> {
+        let word = match self.next() {
+            Some(Token::Word(word)) => Word(word),
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => return Err(ParseError::Incomplete("word")),
+        };
+        self.skip_newlines();
+        self.expect("in")?;
+        self.skip_newlines();
+
+        let mut items = Vec::new();
+        while self.peek_word() != Some("esac") {
+            if self.peek().is_none() {
+                return Err(ParseError::Incomplete("esac"));
+            }
+            self.eat_op(Operator::LParen);
+            let mut patterns = Vec::new();
+            loop {
+                match self.next() {
+                    Some(Token::Word(pattern)) => patterns.push(Word(pattern)),
+                    Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+                    None => return Err(ParseError::Incomplete(")")),
+                }
+                if self.eat_op(Operator::RParen) {
+                    break;
+                }
+                if !self.eat_op(Operator::Pipe) {
+                    return Err(self.unexpected());
+                }
+            }
+
+            let body = self.list(&["esac"])?;
+            items.push(CaseItem { patterns, body });
+            if !self.eat_op(Operator::DSemi) {
+                // Only the last item can go without `;;`.
+                self.skip_newlines();
+                break;
+            }
+            self.skip_newlines();
+        }
+        self.expect("esac")?;
+        Ok(CompoundKind::Case { word, items })
+    }
+
+    /// Parses `name() compound-command`, or returns `None` if the next
+    /// tokens aren't a name followed by `()`.
+    fn function(&mut self) -> Result<Option<FunctionDefinition>, ParseError> {
+        let name = match (self.peek_word(), self.tokens.get(self.pos + 1)) {
+            (Some(name), Some(Token::Op(Operator::LParen))) if is_function_name(name) => {
+                name.to_string()
+            }
+            _ => return Ok(None),
+        };
+        self.pos += 2;
+        if !self.eat_op(Operator::RParen) {
+            return Err(self.unexpected());
+        }
+        self.skip_newlines();
+
+        match self.compound()? {
+            Some(body) => Ok(Some(FunctionDefinition {
+                name,
+                body: Rc::new(body),
+            })),
+            None => Err(self.unexpected()),
+        }
+    }
+
+    /// Consumes the reserved word `word`, which has to come next.
+    fn expect(&mut self, word: &'static str) -> Result<(), ParseError> {
+        match self.peek_word() {
+            Some(next) if next == word => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.unexpected()),
+            None => Err(ParseError::Incomplete(word)),
+        }
+    }
+
+    /// Replaces the word at the current position with the tokens of the
+    /// alias it names, if it does. Returns whether it did.
+    fn expand_alias(&mut self) -> Result<bool, ParseError> {
+        let Some(aliases) = self.aliases else {
+            return Ok(false);
+        };
+        let pos = self.pos;
+        self.expanding.retain(|(_, end)| *end > pos);
+        let Some(Token::Word(name)) = self.tokens.get(pos) else {
+            return Ok(false);
+        };
+        let Some(value) = aliases.get(name) else {
+            return Ok(false);
+        };
+        if self
+            .expanding
+            .iter()
+            .any(|(expanding, _)| expanding == name)
+        {
+            return Ok(false);
+        }
+
+        let name = name.clone();
+        let replacement = lexer::tokenize(value)?;
+        let len = replacement.len();
+        self.tokens.splice(pos..pos + 1, replacement);
+        for (_, end) in &mut self.expanding {
+            *end = *end + len - 1;
+        }
+        self.expanding.push((name, pos + len));
+        self.alias_next = value.ends_with([' ', '\t']).then_some(pos + len);
+        Ok(true)
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+
+        loop {
+            if self.alias_next == Some(self.pos) && !command.words.is_empty() {
+                self.alias_next = None;
+                while self.expand_alias()? {}
+            }
+            match self.peek() {
+                Some(Token::Word(_)) => {
+                    if let Some(Token::Word(word)) = self.next() {
+                        command.words.push(Word(word));
+                    }
+                }
+                Some(Token::IoNumber(_)) | Some(Token::Op(_)) => match self.redirect()? {
+                    Some(redirect) => command.redirects.push(redirect),
+                    None => break,
+                },
+                Some(Token::Newline) | None => break,
+            }
+        }
+
+        if command.words.is_empty() && command.redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(command)
+    }
+
+    /// Parses `[n]op target`, or returns `None` when the next token doesn't
+    /// start a redirection.
+    fn redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let start = self.pos;
+        let fd = match self.peek() {
+            Some(Token::IoNumber(fd)) => {
+                let fd = *fd;
+                self.pos += 1;
+                Some(fd)
+            }
+            _ => None,
+        };
+
+        let kind = match self.peek() {
+            Some(Token::Op(op)) if op.is_redirection() => match op {
+                Operator::Less => RedirectKind::Input,
+                Operator::Great => RedirectKind::Output,
+                Operator::DGreat => RedirectKind::Append,
+                Operator::Clobber => RedirectKind::Clobber,
+                Operator::LessGreat => RedirectKind::ReadWrite,
+                Operator::LessAnd => RedirectKind::DupInput,
+                Operator::GreatAnd => RedirectKind::DupOutput,
+                Operator::AndGreat => RedirectKind::OutputAll,
+                Operator::AndDGreat => RedirectKind::AppendAll,
+                Operator::TLess => RedirectKind::HereString,
+                _ => unreachable!("not a redirection operator: {}", op),
+            },
+            _ if fd.is_some() => return Err(self.unexpected()),
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        self.pos += 1;
+
+        match self.next() {
+            Some(Token::Word(target)) => Ok(Some(Redirect {
+                fd,
+                kind,
+                target: Word(target),
+            })),
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Err(ParseError::Incomplete("filename")),
+        }
+    }
+}
+
+/// Whether `name` can name a function: a plain word, without quotes or
+/// anything else expansion would change.
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !CLOSING_WORDS.contains(&name)
+        && !name
+            .chars()
+            .any(|c| "'\"\\$`=*?[{~".contains(c) || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(command) => command,
+            _ => panic!("not a simple command: {}", command),
+        }
+    }
+
+    fn argv(command: &Command) -> Vec<String> {
+        simple(command)
+            .words
+            .iter()
+            .map(|word| lexer::unquote(word.raw()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_lists_and_pipelines() {
+        let program = parse("cargo build && cargo test || echo 'it broke'; ls | wc -l &").unwrap();
+        assert_eq!(program.items.len(), 2);
+
+        let first = &program.items[0];
+        assert!(!first.background);
+        assert_eq!(
+            argv(&first.and_or.first.commands[0]),
+            vec!["cargo", "build"]
+        );
+        assert_eq!(first.and_or.rest[0].0, Connector::And);
+        assert_eq!(first.and_or.rest[1].0, Connector::Or);
+        assert_eq!(
+            argv(&first.and_or.rest[1].1.commands[0]),
+            vec!["echo", "it broke"]
+        );
+
+        let second = &program.items[1];
+        assert!(second.background);
+        assert_eq!(second.and_or.first.commands.len(), 2);
+    }
+
+    #[test]
+    fn parses_redirections() {
+        let program = parse("cmd < in.txt > out.log 2>&1").unwrap();
+        let command = simple(&program.items[0].and_or.first.commands[0]);
+        assert_eq!(argv(&Command::Simple(command.clone())), vec!["cmd"]);
+        assert_eq!(
+            command.redirects,
+            vec![
+                Redirect {
+                    fd: None,
+                    kind: RedirectKind::Input,
+                    target: Word("in.txt".into()),
+                },
+                Redirect {
+                    fd: None,
+                    kind: RedirectKind::Output,
+                    target: Word("out.log".into()),
+                },
+                Redirect {
+                    fd: Some(2),
+                    kind: RedirectKind::DupOutput,
+                    target: Word("1".into()),
+                },
+            ]
+        );
+
+        let program = parse("ls 2>&1>/dev/null").unwrap();
+        let command = simple(&program.items[0].and_or.first.commands[0]);
+        assert_eq!(
+            command.redirects,
+            vec![
+                Redirect {
+                    fd: Some(2),
+                    kind: RedirectKind::DupOutput,
+                    target: Word("1".into()),
+                },
+                Redirect {
+                    fd: None,
+                    kind: RedirectKind::Output,
+                    target: Word("/dev/null".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_groups_and_functions() {
+        let program = parse("greet() {\n  echo hi \"$1\"\n} 2> err; { a; b; } | c").unwrap();
+        let Command::Function(definition) = &program.items[0].and_or.first.commands[0] else {
+            panic!("not a function definition");
+        };
+        assert_eq!(definition.name, "greet");
+        assert_eq!(definition.body.to_string(), "{ echo hi \"$1\"; } 2>err");
+
+        let pipeline = &program.items[1].and_or.first;
+        assert!(matches!(pipeline.commands[0], Command::Compound(_)));
+        assert_eq!(pipeline.to_string(), "{ a; b; } | c");
+        assert_eq!(parse("{ }"), Err(ParseError::UnexpectedToken("}".into())));
+        assert_eq!(parse("{ a; "), Err(ParseError::Incomplete("}")));
+    }
+
+    #[test]
+    fn parses_control_flow() {
+        let script = "if a; then b\nelif c\nthen d; else e; fi\n\
+                      while x; do y; done > log\n\
+                      for f in *.rs; do until g; do :; done; done\n\
+                      case $1 in\n  (-h | --help) usage;;\n  *) ;;\nesac";
+        let program = parse(script).unwrap();
+        let lines: Vec<String> = program
+            .items
+            .iter()
+            .map(|item| item.and_or.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "if a; then b; elif c; then d; else e; fi",
+                "while x; do y; done >log",
+                "for f in *.rs; do until g; do :; done; done",
+                "case $1 in -h | --help) usage;; *);; esac",
+            ]
+        );
+
+        assert_eq!(parse("if a; then b"), Err(ParseError::Incomplete("fi")));
+        assert_eq!(parse("for x in a b"), Err(ParseError::Incomplete("do")));
+        assert_eq!(
+            parse("case x in a) b;;"),
+            Err(ParseError::Incomplete("esac"))
+        );
+        assert_eq!(
+            parse("while a; done"),
+            Err(ParseError::UnexpectedToken("done".into()))
+        );
+        assert_eq!(
+            parse("a;; b"),
+            Err(ParseError::UnexpectedToken(";;".into()))
+        );
+    }
+
+    #[test]
+    fn expands_aliases() {
+        let aliases = BTreeMap::from([
+            ("ll".to_string(), "ls -l".to_string()),
+            ("ls".to_string(), "ls -F".to_string()),
+            ("loop".to_string(), "loop".to_string()),
+            ("sudo".to_string(), "sudo ".to_string()),
+        ]);
+        let program = parse_with_aliases("ll x; loop; sudo ll; echo ll", &aliases).unwrap();
+        let argvs: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| argv(&item.and_or.first.commands[0]))
+            .collect();
+        assert_eq!(
+            argvs,
+            vec![
+                vec!["ls", "-F", "-l", "x"],
+                vec!["loop"],
+                vec!["sudo", "ls", "-F", "-l"],
+                vec!["echo", "ll"],
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            parse("ls | | wc"),
+            Err(ParseError::UnexpectedToken("|".into()))
+        );
+        assert_eq!(parse("ls |"), Err(ParseError::Incomplete("command")));
+        assert_eq!(parse("ls >"), Err(ParseError::Incomplete("filename")));
+        assert_eq!(parse("# only a comment"), Ok(Program::default()));
+    }
+}
@@ -0,0 +1,301 @@
+//! The prompts, drawn from templates such as `$PS1`. Text stands for
+//! itself, apart from:
+//!
+//! - `{segment}`, or `{segment:arg}`, which shows one of:
+//!   - `user`
+//!   - `host`, or `{host:full}` with the domain
+//!   - `cwd`, with `~` for the home directory; `{cwd:2}` keeps only the
+//!     last two directories
+//!   - `git`: the branch, with `*` when there are uncommitted changes
+//!   - `status`: the last exit status, unless it was 0
+//!   - `duration`: how long the last command took, if it took 2 seconds
+//!     or more, or `{duration:N}` seconds
+//!   - `jobs`: how many jobs there are, if any
+//!   - `ai`: the AI backend, unless AI assistance is off
+//! - `[...]`, which only shows when a segment in it shows something, so
+//!   that `[ on {git}]` disappears outside a repository.
+//! - the escapes bash has for some of those, `\u`, `\h`, `\H`, `\w`, `\W`
+//!   and `\j`, as well as `\$` (`#` for root and `$` for everyone else),
+//!   `\n`, `\e` for the escape character, and `\\`, `\{`, `\}`, `\[` and
+//!   `\]` for the characters themselves.
+//!
+//! Segments have the colors the config gives them, and the rest of the
+//! prompt the prompt's color.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crossterm::style::{Color, Stylize};
+
+use crate::builtins::cd;
+use crate::config::PromptConfig;
+
+use super::shell::ShellState;
+
+/// The prompt without `$PS1` or a configured one.
+pub const PS1: &str = "{user} @ {cwd}[ ({git})] dsh % ";
+
+/// How long a command has to take for `{duration}` to show it.
+const SLOW: Duration = Duration::from_secs(2);
+
+/// The prompt `template` stands for, as things are in `state`.
+pub fn render(template: &str, state: &ShellState) -> String {
+    let renderer = Renderer {
+        state,
+        config: &state.config.prompt,
+    };
+    let mut rest = template;
+    renderer.sequence(&mut rest, false).0
+}
+
+struct Renderer<'a> {
+    state: &'a ShellState,
+    config: &'a PromptConfig,
+}
+
+impl Renderer<'_> {
+    /// Renders `rest` up to its end or, `in_group`, up to the `]` closing
+    /// the group, and returns the text and whether any segment in it showed
+    /// something.
+    fn sequence(&self, rest: &mut &str, in_group: bool) -> (String, bool) {
+        let mut out = String::new();
+        let mut literal = String::new();
+        let mut shown = false;
+        while let Some(c) = rest.chars().next() {
+            *rest = &rest[c.len_utf8()..];
+            let (text, color) = match c {
+                ']' if in_group => break,
+                '[' => {
+                    let (text, group_shown) = self.sequence(rest, true);
+                    if group_shown {
+                        self.flush(&mut out, &mut literal);
+                        out.push_str(&text);
+                        shown = true;
+                    }
+                    continue;
+                }
+                '{' => match self.braced(rest) {
+                    Some(segment) => segment,
+                    None => {
+                        literal.push('{');
+                        continue;
+                    }
+                },
+                '\\' => match self.escape(rest, &mut literal) {
+                    Some(segment) => segment,
+                    None => continue,
+                },
+                c => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            self.flush(&mut out, &mut literal);
+            shown |= !text.is_empty();
+            out.push_str(&paint(&text, color));
+        }
+        self.flush(&mut out, &mut literal);
+        (out, shown)
+    }
+
+    /// Moves the text so far to `out`, in the prompt's color.
+    fn flush(&self, out: &mut String, literal: &mut String) {
+        out.push_str(&paint(literal, self.config.color));
+        literal.clear();
+    }
+
+    /// The segment named after a `{`, if `rest` has one, with `rest`
+    /// moved past it.
+    fn braced(&self, rest: &mut &str) -> Option<(String, Color)> {
+        let (spec, after) = rest.split_once('}')?;
+        let segment = match spec.split_once(':') {
+            Some((name, arg)) => self.segment(name, Some(arg)),
+            None => self.segment(spec, None),
+        }?;
+        *rest = after;
+        Some(segment)
+    }
+
+    /// Reads the escape after a `\`, which is either a segment or text for
+    /// `literal`.
+    fn escape(&self, rest: &mut &str, literal: &mut String) -> Option<(String, Color)> {
+        let Some(escaped) = rest.chars().next() else {
+            literal.push('\\');
+            return None;
+        };
+        *rest = &rest[escaped.len_utf8()..];
+        match escaped {
+            'u' => return self.segment("user", None),
+            'h' => return self.segment("host", None),
+            'H' => return self.segment("host", Some("full")),
+            'w' => return self.segment("cwd", None),
+            'W' => return self.segment("cwd", Some("1")),
+            'j' => return self.segment("jobs", None),
+            '$' => {
+                // SAFETY: geteuid can't fail.
+                let root = unsafe { libc::geteuid() } == 0;
+                literal.push(if root { '#' } else { '$' });
+            }
+            'n' => literal.push('\n'),
+            'e' => literal.push('\x1b'),
+            '\\' | '{' | '}' | '[' | ']' => literal.push(escaped),
+            other => {
+                literal.push('\\');
+                literal.push(other);
+            }
+        }
+        None
+    }
+
+    /// What a segment shows, in its color, or `None` for one that doesn't
+    /// exist.
+    fn segment(&self, name: &str, arg: Option<&str>) -> Option<(String, Color)> {
+        let colors = &self.config.colors;
+        let state = self.state;
+        let (text, color) = match name {
+            "user" => (whoami::username(), colors.user),
+            "host" => {
+                let host = whoami::fallible::hostname().unwrap_or_default();
+                let host = match arg {
+                    Some("full") => host,
+                    _ => host.split('.').next().unwrap_or_default().to_string(),
+                };
+                (host, colors.host)
+            }
+            "cwd" => {
+                let keep = arg.and_then(|arg| arg.parse().ok());
+                (self.cwd(keep), colors.cwd)
+            }
+            "git" => (git().unwrap_or_default(), colors.git),
+            "status" => {
+                let text = match state.last_status.code() {
+                    0 => String::new(),
+                    code => code.to_string(),
+                };
+                (text, colors.status)
+            }
+            "duration" => {
+                let slow = arg
+                    .and_then(|arg| arg.parse().ok())
+                    .map_or(SLOW, Duration::from_secs);
+                let text = match state.last_duration {
+                    took if took >= slow => format_duration(took),
+                    _ => String::new(),
+                };
+                (text, colors.duration)
+            }
+            "jobs" => {
+                let text = match state.jobs.len() {
+                    0 => String::new(),
+                    jobs => jobs.to_string(),
+                };
+                (text, colors.jobs)
+            }
+            "ai" => {
+                let text = state.engine.as_ref().map_or("", |engine| engine.name());
+                (text.to_string(), colors.ai)
+            }
+            _ => return None,
+        };
+        Some((text, color.unwrap_or(self.config.color)))
+    }
+
+    /// The working directory, with `~` for home, and only the last `keep`
+    /// directories of it, if given.
+    fn cwd(&self, keep: Option<usize>) -> String {
+        let cwd = cd::logical_cwd(self.state);
+        let home = self
+            .state
+            .variables
+            .get("HOME")
+            .filter(|home| !home.is_empty());
+        let (start, rest) = match home.and_then(|home| cwd.strip_prefix(home).ok()) {
+            Some(rest) => ("~", rest),
+            None => ("/", cwd.strip_prefix("/").unwrap_or(&cwd)),
+        };
+        let names: Vec<_> = rest.iter().map(|name| name.to_string_lossy()).collect();
+        match keep {
+            Some(keep) if names.len() > keep => {
+                format!("…/{}", names[names.len() - keep..].join("/"))
+            }
+            _ if names.is_empty() => start.to_string(),
+            _ => Path::new(start).join(names.join("/")).display().to_string(),
+        }
+    }
+}
+
+fn paint(text: &str, color: Color) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    text.with(color).to_string()
+}
+
+/// The current branch, followed by `*` when the work tree has changes, or
+/// `None` outside a repository.
+fn git() -> Option<String> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--branch"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    // `## main...origin/main [ahead 1]`, or `## No commits yet on main`.
+    let header = lines.next()?.strip_prefix("## ")?;
+    let branch = match header.strip_prefix("No commits yet on ") {
+        Some(branch) => branch,
+        None => header.split(['.', ' ']).next().unwrap_or(header),
+    };
+    let dirty = if lines.next().is_some() { "*" } else { "" };
+    Some(format!("{}{}", branch, dirty))
+}
+
+/// As `850ms`, `4.2s`, `3m05s` or `1h02m`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0 => format!("{}ms", duration.as_millis()),
+        1..60 => format!("{:.1}s", duration.as_secs_f64()),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::internals::status::ExitStatus;
+
+    #[test]
+    fn renders_segments_groups_and_escapes() {
+        let mut state = ShellState::new(false, Config::default());
+        let cwd = std::env::current_dir().unwrap();
+        state.variables.set("HOME", &cwd.to_string_lossy()).unwrap();
+        // Without the colors' escape sequences.
+        let plain = |template: &str, state: &ShellState| {
+            let mut escape = false;
+            let mut text = render(template, state);
+            text.retain(|c| {
+                let keep = !escape && c != '\x1b';
+                escape = (escape || c == '\x1b') && c != 'm';
+                keep
+            });
+            text
+        };
+
+        assert_eq!(plain("\\w [{status} ]% ", &state), "~ % ");
+        state.last_status = ExitStatus::Exited(2);
+        state.last_duration = Duration::from_millis(2500);
+        assert_eq!(
+            plain("[{status} ]{duration}[ {jobs} jobs]% ", &state),
+            "2 2.5s% "
+        );
+        assert_eq!(plain("\\[{nope}\\] \\{x\\} [a", &state), "[{nope}] {x} ");
+        assert_eq!(format_duration(Duration::from_secs(185)), "3m05s");
+    }
+}
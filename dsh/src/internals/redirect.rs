@@ -0,0 +1,634 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Error, Result};
+
+use super::ast::{Redirect, RedirectKind};
+
+/// How much of each output stream is kept around for diagnosis.
+const CAPTURE_LIMIT: usize = 16 * 1024;
+
+/// Which diagnosis buffer a tee'd stream is collected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    Stdout,
+    Stderr,
+}
+
+/// Where a stream routed through the shell finally ends up.
+#[derive(Debug)]
+pub enum Sink {
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// A stream the child writes into a pipe, which the shell copies to `sink`
+/// while keeping the tail of it for diagnosis.
+#[derive(Debug)]
+pub struct TeeSpec {
+    sink: Sink,
+    capture: Capture,
+}
+
+impl TeeSpec {
+    /// The shell's descriptor the sink is, if that is a terminal.
+    fn terminal(&self) -> Option<RawFd> {
+        let fd = match self.sink {
+            Sink::Stdout => 1,
+            Sink::Stderr => 2,
+            Sink::File(_) => return None,
+        };
+        (unsafe { libc::isatty(fd) } == 1).then_some(fd)
+    }
+}
+
+/// What a file descriptor of the child is connected to.
+#[derive(Debug, Clone)]
+pub enum Slot {
+    /// Whatever the shell itself has open on that descriptor.
+    Inherit,
+    /// Closed with `>&-`.
+    Closed,
+    File(Arc<File>),
+    Tee(Arc<TeeSpec>),
+}
+
+impl Slot {
+    pub fn file(file: File) -> Slot {
+        Slot::File(Arc::new(file))
+    }
+
+    pub fn tee(sink: Sink, capture: Capture) -> Slot {
+        Slot::Tee(Arc::new(TeeSpec { sink, capture }))
+    }
+}
+
+/// The read side of a tee, copied by the shell once the child is running.
+pub struct TeeEnd {
+    reader: File,
+    spec: Arc<TeeSpec>,
+    /// What [`Sink::Stdout`] or [`Sink::Stderr`] was when the tee was made,
+    /// so that pointing the shell's own descriptors elsewhere for a builtin
+    /// doesn't change where the copy goes.
+    out: Option<File>,
+}
+
+/// The shell's own descriptors, pointed at a builtin's slots while it
+/// runs. Dropping this puts them back.
+pub struct ShellRedirect {
+    /// Each descriptor changed, with a copy of what it was, if it was open.
+    saved: Vec<(RawFd, Option<File>)>,
+    pub tees: Vec<TeeEnd>,
+}
+
+/// Keeps the shell's ends of everything passed to a child open until the
+/// child has been spawned.
+pub struct Prepared {
+    pub tees: Vec<TeeEnd>,
+    _keep_alive: FdTable,
+}
+
+/// The file descriptor table of a command about to be spawned.
+pub struct FdTable {
+    slots: BTreeMap<RawFd, Slot>,
+}
+
+impl FdTable {
+    pub fn new(stdin: Slot, stdout: Slot, stderr: Slot) -> Self {
+        FdTable {
+            slots: BTreeMap::from([(0, stdin), (1, stdout), (2, stderr)]),
+        }
+    }
+
+    /// Applies `redirect` on top of the current table, left to right as
+    /// POSIX requires, so `> out.log 2>&1` and `2>&1 > out.log` differ.
+    /// `target` is the redirection's target after expansion.
+    pub fn apply(&mut self, redirect: &Redirect, target: &str) -> Result<(), Error> {
+        let fd = redirect.fd.map(|fd| fd as RawFd);
+
+        match redirect.kind {
+            RedirectKind::Input => {
+                let file = File::open(target).map_err(|err| file_error(target, err))?;
+                self.slots.insert(fd.unwrap_or(0), Slot::file(file));
+            }
+            RedirectKind::Output | RedirectKind::Clobber => {
+                let fd = fd.unwrap_or(1);
+                let slot = output_slot(target, false, fd)?;
+                self.slots.insert(fd, slot);
+            }
+            RedirectKind::Append => {
+                let fd = fd.unwrap_or(1);
+                let slot = output_slot(target, true, fd)?;
+                self.slots.insert(fd, slot);
+            }
+            RedirectKind::ReadWrite => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(target)
+                    .map_err(|err| file_error(target, err))?;
+                self.slots.insert(fd.unwrap_or(0), Slot::file(file));
+            }
+            // `>& file` without a descriptor number is the csh spelling of `&>`.
+            RedirectKind::DupOutput
+                if fd.is_none() && target != "-" && target.parse::<RawFd>().is_err() =>
+            {
+                self.apply_all(target, false)?;
+            }
+            RedirectKind::DupInput | RedirectKind::DupOutput => {
+                let default = if redirect.kind == RedirectKind::DupInput {
+                    0
+                } else {
+                    1
+                };
+                let slot = if target == "-" {
+                    Slot::Closed
+                } else {
+                    let source = target
+                        .parse::<RawFd>()
+                        .map_err(|_| anyhow!("{}: ambiguous redirect", target))?;
+                    self.get(source)?
+                };
+                self.slots.insert(fd.unwrap_or(default), slot);
+            }
+            RedirectKind::OutputAll => self.apply_all(target, false)?,
+            RedirectKind::AppendAll => self.apply_all(target, true)?,
+            RedirectKind::HereString => {
+                let reader = here_string(format!("{}\n", target))?;
+                self.slots.insert(fd.unwrap_or(0), Slot::file(reader));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_all(&mut self, target: &str, append: bool) -> Result<(), Error> {
+        let slot = output_slot(target, append, 1)?;
+        self.slots.insert(1, slot.clone());
+        self.slots.insert(2, slot);
+        Ok(())
+    }
+
+    /// The current slot for `fd`, falling back to a copy of what the shell
+    /// itself has open there, as in `>&5`. An inherited descriptor is copied
+    /// too, so that `>&2` doesn't turn into "whatever the shell has on 1".
+    fn get(&self, fd: RawFd) -> Result<Slot, Error> {
+        match self.slots.get(&fd) {
+            Some(Slot::Inherit) | None => {
+                let copy = duplicate(fd).map_err(|err| anyhow!("{}: {}", fd, err))?;
+                Ok(Slot::file(copy))
+            }
+            Some(slot) => Ok(slot.clone()),
+        }
+    }
+
+    /// Replaces every tee with the write end of a new pipe, returning the
+    /// read ends for the shell to copy from. Slots that share a tee share
+    /// its pipe. A tee to a terminal gets a pseudo-terminal instead, so
+    /// that the child still finds a terminal there.
+    pub fn resolve(&self) -> io::Result<(FdTable, Vec<TeeEnd>)> {
+        let mut tees = Vec::new();
+        let mut writers: Vec<(Arc<TeeSpec>, Arc<File>)> = Vec::new();
+        let mut slots = BTreeMap::new();
+        for (&fd, slot) in &self.slots {
+            let slot = match slot {
+                Slot::Tee(spec) => {
+                    let existing = writers.iter().find(|(other, _)| Arc::ptr_eq(spec, other));
+                    let writer = match existing {
+                        Some((_, writer)) => writer.clone(),
+                        None => {
+                            let (reader, writer) = match spec.terminal() {
+                                Some(terminal) => pty(terminal)?,
+                                None => pipe()?,
+                            };
+                            tees.push(TeeEnd::new(reader, spec.clone()));
+                            let writer = Arc::new(writer);
+                            writers.push((spec.clone(), writer.clone()));
+                            writer
+                        }
+                    };
+                    Slot::File(writer)
+                }
+                slot => slot.clone(),
+            };
+            slots.insert(fd, slot);
+        }
+        Ok((FdTable { slots }, tees))
+    }
+
+    /// Points the shell's own descriptors at the slots, for a builtin that
+    /// runs in the shell rather than in a child.
+    pub fn redirect_shell(&self) -> io::Result<ShellRedirect> {
+        io::stdout().flush()?;
+        // Every tee is set up before any descriptor changes, so that each
+        // copies to where the shell's stdout and stderr were to begin with.
+        let (files, tees) = self.resolve()?;
+        let mut redirect = ShellRedirect {
+            saved: Vec::new(),
+            tees,
+        };
+
+        for (&fd, slot) in &files.slots {
+            let result = match slot {
+                Slot::Inherit => continue,
+                Slot::Closed => {
+                    redirect.saved.push((fd, duplicate(fd).ok()));
+                    unsafe { libc::close(fd) }
+                }
+                Slot::File(file) => {
+                    redirect.saved.push((fd, duplicate(fd).ok()));
+                    unsafe { libc::dup2(file.as_raw_fd(), fd) }
+                }
+                Slot::Tee(_) => unreachable!("tees are resolved to pipes"),
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(redirect)
+    }
+
+    /// Points the descriptors of a forked copy of the shell at the slots for
+    /// good, and closes every other pipe it inherited, so that a reader or
+    /// writer at the other end isn't kept waiting for it. The slots' pipes
+    /// are closed too, so the table must not be dropped afterwards.
+    pub fn install(&self) -> io::Result<()> {
+        std::mem::forget(self.redirect_shell()?);
+        let open: Vec<RawFd> = std::fs::read_dir("/dev/fd")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        for fd in open {
+            let kept = matches!(self.slots.get(&fd), Some(slot) if !matches!(slot, Slot::Closed));
+            if fd > 2 && !kept && is_pipe(fd) {
+                unsafe { libc::close(fd) };
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the child's descriptors at their slots. The returned value has
+    /// to be kept alive until the child is spawned and dropped right after,
+    /// or readers of the pipes will never see end of file.
+    pub fn configure(&self, command: &mut Command) -> io::Result<Prepared> {
+        let (files, tees) = self.resolve()?;
+        let mut dups: Vec<(RawFd, RawFd)> = Vec::new();
+        let mut closed: Vec<RawFd> = Vec::new();
+
+        for (&fd, slot) in &files.slots {
+            let file = match slot {
+                Slot::Inherit => continue,
+                Slot::Closed => {
+                    closed.push(fd);
+                    continue;
+                }
+                Slot::File(file) => file,
+                Slot::Tee(_) => unreachable!("tees are resolved to pipes"),
+            };
+
+            match fd {
+                0 => {
+                    command.stdin(Stdio::from(file.try_clone()?));
+                }
+                1 => {
+                    command.stdout(Stdio::from(file.try_clone()?));
+                }
+                2 => {
+                    command.stderr(Stdio::from(file.try_clone()?));
+                }
+                _ => dups.push((file.as_raw_fd(), fd)),
+            }
+        }
+
+        if !dups.is_empty() || !closed.is_empty() {
+            // Only async-signal-safe calls are allowed between fork and exec.
+            unsafe {
+                command.pre_exec(move || {
+                    for &(source, target) in &dups {
+                        if source == target {
+                            let flags = libc::fcntl(target, libc::F_GETFD);
+                            libc::fcntl(target, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+                        } else if libc::dup2(source, target) < 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    for &fd in &closed {
+                        libc::close(fd);
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        Ok(Prepared {
+            tees,
+            _keep_alive: files,
+        })
+    }
+}
+
+impl Drop for ShellRedirect {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        for (fd, saved) in self.saved.drain(..).rev() {
+            match saved {
+                Some(file) => unsafe { libc::dup2(file.as_raw_fd(), fd) },
+                None => unsafe { libc::close(fd) },
+            };
+        }
+    }
+}
+
+impl TeeEnd {
+    fn new(reader: File, spec: Arc<TeeSpec>) -> Self {
+        let out = match spec.sink {
+            Sink::Stdout => duplicate(1).ok(),
+            Sink::Stderr => duplicate(2).ok(),
+            Sink::File(_) => None,
+        };
+        TeeEnd { reader, spec, out }
+    }
+
+    /// Copies the stream to its sink on a blocking thread and resolves to
+    /// the last `CAPTURE_LIMIT` bytes that went through.
+    pub fn spawn(self) -> (Capture, tokio::task::JoinHandle<Vec<u8>>) {
+        let capture = self.spec.capture;
+        let handle = tokio::task::spawn_blocking(move || self.copy());
+        (capture, handle)
+    }
+
+    /// Copies the stream to its sink until the writers close it, returning
+    /// the last `CAPTURE_LIMIT` bytes.
+    pub fn copy(self) -> Vec<u8> {
+        let TeeEnd {
+            mut reader,
+            spec,
+            out,
+        } = self;
+        let mut captured = Vec::new();
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let _ = match (&out, &spec.sink) {
+                (Some(out), _) | (None, Sink::File(out)) => write_flush(&mut &*out, &buf[..n]),
+                (None, _) => Ok(()),
+            };
+
+            captured.extend_from_slice(&buf[..n]);
+            if captured.len() > CAPTURE_LIMIT {
+                captured.drain(..captured.len() - CAPTURE_LIMIT);
+            }
+        }
+
+        captured
+    }
+}
+
+fn write_flush(writer: &mut impl Write, chunk: &[u8]) -> io::Result<()> {
+    writer.write_all(chunk)?;
+    writer.flush()
+}
+
+/// Opens `target` for output. Regular files are fed through a tee so that
+/// what lands in them is still available for diagnosis; anything else, such
+/// as `/dev/null` or a terminal, is handed to the child directly.
+fn output_slot(target: &str, append: bool, fd: RawFd) -> Result<Slot, Error> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(target)
+        .map_err(|err| file_error(target, err))?;
+
+    let capture = match fd {
+        1 => Capture::Stdout,
+        2 => Capture::Stderr,
+        _ => return Ok(Slot::file(file)),
+    };
+    let is_regular = file.metadata().map(|m| m.is_file()).unwrap_or(false);
+    if is_regular {
+        Ok(Slot::tee(Sink::File(file), capture))
+    } else {
+        Ok(Slot::file(file))
+    }
+}
+
+/// Describes a file that couldn't be opened the way shells do, without
+/// the OS error number.
+pub fn file_error(target: &str, err: io::Error) -> Error {
+    let message = match err.kind() {
+        io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        _ => err.to_string(),
+    };
+    anyhow!("{}: {}", target, message)
+}
+
+/// Copies `fd` to a new descriptor that is closed on exec, kept above the
+/// ones redirections normally use.
+fn duplicate(fd: RawFd) -> io::Result<File> {
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+    if copy < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(copy) })
+}
+
+fn is_pipe(fd: RawFd) -> bool {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    unsafe { libc::fstat(fd, &mut stat) == 0 && stat.st_mode & libc::S_IFMT == libc::S_IFIFO }
+}
+
+/// Creates a pipe whose ends are closed on exec.
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0 as RawFd; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Creates a pseudo-terminal with the modes and size of the terminal on
+/// `fd`, returning its master and slave ends, closed on exec. Output
+/// processing is left to the terminal the master is copied to.
+fn pty(fd: RawFd) -> io::Result<(File, File)> {
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    unsafe {
+        if libc::tcgetattr(fd, &mut modes) != 0 || libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    modes.c_oflag &= !libc::OPOST;
+
+    let mut fds = [0 as RawFd; 2];
+    let opened = unsafe {
+        libc::openpty(
+            &mut fds[0],
+            &mut fds[1],
+            std::ptr::null_mut(),
+            &modes,
+            &size,
+        )
+    };
+    if opened != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Returns the read end of a pipe that yields `text`. Writing happens on a
+/// thread so large strings can't fill the pipe and block the shell.
+fn here_string(text: String) -> io::Result<File> {
+    let (reader, mut writer) = pipe()?;
+    thread::spawn(move || {
+        let _ = writer.write_all(text.as_bytes());
+    });
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internals::{ast, lexer, parser};
+    use std::path::PathBuf;
+    use std::{env, fs, process, ptr};
+
+    /// Runs `sh -c script` with its stdout and stderr on pipes and the
+    /// redirections in `redirects` on top, returning what reached the pipes.
+    fn run(script: &str, redirects: &str) -> (String, String) {
+        let program = parser::parse(&format!("sh {}", redirects)).unwrap();
+        let ast::Command::Simple(command) = &program.items[0].and_or.first.commands[0] else {
+            panic!("not a simple command");
+        };
+        let (mut out, out_writer) = pipe().unwrap();
+        let (mut err, err_writer) = pipe().unwrap();
+        let mut fds = FdTable::new(
+            Slot::Inherit,
+            Slot::file(out_writer),
+            Slot::file(err_writer),
+        );
+        for redirect in &command.redirects {
+            let target = lexer::unquote(redirect.target.raw());
+            fds.apply(redirect, &target).unwrap();
+        }
+
+        let mut sh = Command::new("sh");
+        sh.args(["-c", script]);
+        let Prepared { tees, _keep_alive } = fds.configure(&mut sh).unwrap();
+        let mut child = sh.spawn().unwrap();
+        // Whatever still holds a writer would keep the tees from ending.
+        drop((sh, fds, _keep_alive));
+        for tee in tees {
+            tee.copy();
+        }
+        child.wait().unwrap();
+
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        out.read_to_string(&mut stdout).unwrap();
+        err.read_to_string(&mut stderr).unwrap();
+        (stdout, stderr)
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dsh-redirect-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn applies_redirections_left_to_right() {
+        let both = "echo out; echo err >&2";
+        let file = scratch("order");
+        let path = file.display();
+
+        assert_eq!(
+            run(both, &format!("> {path} 2>&1")),
+            (String::new(), String::new())
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "out\nerr\n");
+
+        let moved = run(both, &format!("2>&1 > {path}"));
+        assert_eq!(moved, ("err\n".to_string(), String::new()));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "out\n");
+
+        assert_eq!(
+            run(both, &format!("&> {path}")),
+            (String::new(), String::new())
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "out\nerr\n");
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn closes_descriptors_and_feeds_here_strings() {
+        let (stdout, stderr) = run("echo out || echo closed >&2", ">&-");
+        assert_eq!(stdout, "");
+        assert!(stderr.ends_with("closed\n"), "{:?}", stderr);
+
+        let fed = run("cat; cat <&3", "<<< 'one two' 3<<< three");
+        assert_eq!(fed, ("one two\nthree\n".to_string(), String::new()));
+    }
+
+    #[test]
+    fn gives_tees_to_a_terminal_a_terminal_of_the_same_size() {
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: 100,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let mut fds = [0 as RawFd; 2];
+        let opened = unsafe {
+            libc::openpty(
+                &mut fds[0],
+                &mut fds[1],
+                ptr::null_mut(),
+                ptr::null(),
+                &size,
+            )
+        };
+        assert_eq!(opened, 0);
+        let (_master, terminal) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        let (reader, writer) = pty(terminal.as_raw_fd()).unwrap();
+        let mut copied: libc::winsize = unsafe { std::mem::zeroed() };
+        unsafe { libc::ioctl(writer.as_raw_fd(), libc::TIOCGWINSZ, &mut copied) };
+        assert_eq!(unsafe { libc::isatty(writer.as_raw_fd()) }, 1);
+        assert_eq!((copied.ws_row, copied.ws_col), (24, 100));
+
+        let path = env::temp_dir().join(format!("dsh-pty-{}", process::id()));
+        let spec = TeeSpec {
+            sink: Sink::File(File::create(&path).unwrap()),
+            capture: Capture::Stderr,
+        };
+        let tee = TeeEnd::new(reader, Arc::new(spec));
+        (&writer).write_all(b"one\ntwo\n").unwrap();
+        drop(writer);
+        assert_eq!(tee.copy(), b"one\ntwo\n");
+        assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
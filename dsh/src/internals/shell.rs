@@ -1 +1,579 @@
-// history, user data, etc. interface
\ No newline at end of file
+// history, user data, etc. interface
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ai_engine::Backend;
+use anyhow::{anyhow, Error, Result};
+
+use crate::builtins::cd;
+use crate::config::Config;
+
+use super::ast::Compound;
+use super::completion::CompletionSpecs;
+use super::diagnosis::{AiBackend, AiPolicy, FailureContext};
+use super::jobs::JobTable;
+use super::options::Options;
+use super::status::ExitStatus;
+use super::variables::Variables;
+
+/// A jump out of what is running, pending until the commands it skips have
+/// unwound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// `break n`: leave the `n` innermost loops.
+    Break(usize),
+    /// `continue n`: leave `n - 1` loops and start the next round of the
+    /// one around them.
+    Continue(usize),
+    /// `return`: leave the function or sourced file.
+    Return,
+}
+
+/// Everything the shell remembers between commands.
+pub struct ShellState {
+    /// Status of the last pipeline, reported through `$?`.
+    pub last_status: ExitStatus,
+    /// How long the last command line typed at the prompt took to run.
+    pub last_duration: Duration,
+    /// Whether a person is typing the commands, as opposed to a script or
+    /// `-c` string supplying them.
+    pub interactive: bool,
+    /// Started as a login shell.
+    pub login: bool,
+    pub options: Options,
+    pub ai_policy: AiPolicy,
+    pub ai_backend: AiBackend,
+    /// The most recent command that failed, kept for `explain`.
+    pub last_failure: Option<FailureContext>,
+    /// The last failure on the command line running that nothing checked,
+    /// which the AI engine is asked about once the line is done.
+    pub undiagnosed: Option<FailureContext>,
+    pub jobs: JobTable,
+    pub history: History,
+    pub completions: CompletionSpecs,
+    pub variables: Variables,
+    /// The directories `pushd` put aside, most recent first. The current
+    /// directory is the top of the stack and not kept here.
+    pub dir_stack: Vec<PathBuf>,
+    /// Names defined with `alias` and the text they stand for.
+    pub aliases: BTreeMap<String, String>,
+    /// Functions by name, with their bodies.
+    pub functions: BTreeMap<String, Rc<Compound>>,
+    /// How many function calls and sourced files are running, which is
+    /// what `return` can return from.
+    pub call_depth: usize,
+    /// How many loops are running in the current function, which is what
+    /// `break` and `continue` can leave.
+    pub loop_depth: usize,
+    /// Set by `break`, `continue` and `return` until the loop, function or
+    /// file they leave has stopped running commands.
+    pub flow: Option<Flow>,
+    /// How many conditions are being evaluated: `if` and `while` lists and
+    /// the left of `&&` and `||`, where a failure doesn't trip `set -e`.
+    pub conditions: usize,
+    /// How many command lists are running inside one another. The one at
+    /// depth 1 is the command line itself.
+    pub program_depth: usize,
+    /// The settings from the config file, as last loaded.
+    pub config: Config,
+    /// Set by `exit` to the status the shell should exit with, once the
+    /// command line it is on has been cleaned up after.
+    pub exiting: Option<i32>,
+    /// Only loaded when the policy allows asking it anything.
+    pub engine: Option<Box<dyn Backend>>,
+}
+
+impl ShellState {
+    /// A shell set up as `config` says. Only interactive shells take over
+    /// the terminal for job control and read and write the history file.
+    pub fn new(interactive: bool, config: Config) -> Self {
+        let backend = AiBackend::from_env(&config.ai);
+        let mut state = ShellState {
+            last_status: ExitStatus::SUCCESS,
+            last_duration: Duration::ZERO,
+            interactive,
+            login: false,
+            options: Options::default(),
+            ai_policy: AiPolicy::from_env(interactive, config.ai.policy, backend.is_some()),
+            ai_backend: backend.unwrap_or(AiBackend::Local),
+            last_failure: None,
+            undiagnosed: None,
+            jobs: JobTable::new(interactive),
+            history: if interactive {
+                History::load(config.history.size)
+            } else {
+                History::in_memory(config.history.size)
+            },
+            completions: CompletionSpecs::new(),
+            variables: Variables::from_env(),
+            dir_stack: Vec::new(),
+            aliases: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            call_depth: 0,
+            loop_depth: 0,
+            flow: None,
+            conditions: 0,
+            program_depth: 0,
+            config,
+            exiting: None,
+            engine: None,
+        };
+        state.engine = state.open_engine().unwrap_or_else(|err| {
+            eprintln!("dsh: {}", err);
+            None
+        });
+        // `$PWD` from the environment may be stale, or missing.
+        let pwd = cd::logical_cwd(&state);
+        let _ = state.variables.export("PWD", Some(&pwd.to_string_lossy()));
+        state
+    }
+
+    /// Switches to the settings in `config`, as `reload` does. The AI engine
+    /// is kept, with its model, unless it's a different backend now. An
+    /// error means it couldn't be set up, and everything else has switched.
+    pub fn configure(&mut self, config: Config) -> Result<()> {
+        let chosen = AiBackend::from_env(&config.ai);
+        let backend = chosen.unwrap_or(AiBackend::Local);
+        let same_backend = backend == self.ai_backend;
+        self.ai_policy = AiPolicy::from_env(self.interactive, config.ai.policy, chosen.is_some());
+        self.ai_backend = backend;
+        self.history.set_limit(config.history.size);
+        self.config = config;
+        match &mut self.engine {
+            Some(engine) if same_backend && self.ai_policy != AiPolicy::Never => {
+                engine.reconfigure(&self.config.ai.engine_args())
+            }
+            _ => {
+                self.engine = None;
+                self.engine = self.open_engine()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The configured AI engine, unless the policy rules out asking it
+    /// anything.
+    pub fn open_engine(&self) -> Result<Option<Box<dyn Backend>>> {
+        if self.ai_policy == AiPolicy::Never {
+            return Ok(None);
+        }
+        self.ai_backend.open(&self.config.ai).map(Some)
+    }
+}
+
+/// One line the user ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// Seconds since the Unix epoch when the command started.
+    pub timestamp: u64,
+    pub duration: Duration,
+    /// The value `$?` had afterwards.
+    pub status: i32,
+    pub cwd: String,
+}
+
+impl HistoryEntry {
+    /// Describes an entry that just finished running.
+    pub fn new(command: &str, cwd: &Path, started: SystemTime, status: ExitStatus) -> Self {
+        HistoryEntry {
+            command: command.to_string(),
+            timestamp: started
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            duration: started.elapsed().unwrap_or_default(),
+            status: status.code(),
+            cwd: cwd.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// The on-disk form: tab-separated fields, one entry per line.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.timestamp,
+            self.duration.as_millis(),
+            self.status,
+            escape(&self.cwd),
+            escape(&self.command)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        Some(HistoryEntry {
+            timestamp: fields.next()?.parse().ok()?,
+            duration: Duration::from_millis(fields.next()?.parse().ok()?),
+            status: fields.next()?.parse().ok()?,
+            cwd: unescape(fields.next()?),
+            command: unescape(fields.next()?),
+        })
+    }
+}
+
+/// What the AI engine gets to see of an earlier command.
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "$ {}  (in {}, exit {}, {:.1}s)",
+            self.command,
+            self.cwd,
+            self.status,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+/// The command history, appended to a file as commands finish so that it
+/// survives crashes and is shared by shells running side by side.
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    /// The number of `entries[0]`, as shown by `history` and used by `!n`.
+    first_number: usize,
+    path: Option<PathBuf>,
+    /// How many entries are kept.
+    limit: usize,
+}
+
+impl History {
+    /// Loads `$XDG_DATA_HOME/dsh/history`, or `~/.local/share/dsh/history`.
+    /// Problems with the file are reported and leave the history in memory
+    /// only. At most `limit` entries are kept.
+    pub fn load(limit: usize) -> Self {
+        let path = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+            .map(|dir| dir.join("dsh").join("history"));
+
+        match path {
+            Some(path) => History::open(&path, limit).unwrap_or_else(|err| {
+                eprintln!("dsh: history: {}: {}", path.display(), err);
+                History::in_memory(limit)
+            }),
+            None => History::in_memory(limit),
+        }
+    }
+
+    pub fn in_memory(limit: usize) -> Self {
+        History {
+            entries: Vec::new(),
+            first_number: 1,
+            path: None,
+            limit,
+        }
+    }
+
+    pub fn open(path: &Path, limit: usize) -> Result<Self, Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut history = History {
+            path: Some(path.to_path_buf()),
+            ..History::in_memory(limit)
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(history),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = 0;
+        for line in BufReader::new(file).lines() {
+            lines += 1;
+            // Skip lines that are damaged, e.g. by a crash mid-write.
+            if let Some(entry) = HistoryEntry::from_line(&line?) {
+                history.push(entry);
+            }
+        }
+
+        // Compact the file once it has grown past the limit.
+        if lines > limit {
+            history.trim();
+            history.first_number = 1;
+            history.rewrite()?;
+        }
+        Ok(history)
+    }
+
+    /// Keeps at most `limit` entries from now on, forgetting the oldest
+    /// ones if there are more already. The file is compacted the next time
+    /// it is loaded.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+        self.first_number += excess;
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The number `history` shows for `entries()[index]`.
+    pub fn number(&self, index: usize) -> usize {
+        self.first_number + index
+    }
+
+    /// The last `count` entries, oldest first.
+    pub fn recent(&self, count: usize) -> &[HistoryEntry] {
+        &self.entries[self.entries.len().saturating_sub(count)..]
+    }
+
+    /// Adds an entry and appends it to the history file. Lines starting
+    /// with a space are not recorded.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if entry.command.trim().is_empty() || entry.command.starts_with(' ') {
+            return;
+        }
+        if let Err(err) = self.append(&entry) {
+            eprintln!("dsh: history: {}", err);
+        }
+        self.push(entry);
+        self.trim();
+    }
+
+    /// Running the same command twice in a row only keeps the latest run.
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.entries.last().map(|last| &last.command) == Some(&entry.command) {
+            self.entries.pop();
+        }
+        self.entries.push(entry);
+    }
+
+    /// Forgets everything, on disk as well.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.first_number += self.entries.len();
+        self.entries.clear();
+        self.rewrite()
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(entry.to_line().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn rewrite(&self) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let contents: String = self.entries.iter().map(HistoryEntry::to_line).collect();
+            let partial = path.with_extension("tmp");
+            fs::write(&partial, contents)?;
+            fs::rename(&partial, path)?;
+        }
+        Ok(())
+    }
+
+    /// Expands history references in a line typed by the user: `!!` for the
+    /// last command, `!n` and `!-n` by number, `!prefix` for the last
+    /// command starting with `prefix` and `!?text` for the last one
+    /// containing `text`. Single quotes and backslashes keep a `!` literal,
+    /// as does a `!` followed by a space, `=` or `(`.
+    ///
+    /// Returns `None` if there was nothing to expand.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, Error> {
+        let mut expanded = String::with_capacity(line.len());
+        let mut changed = false;
+        let mut in_single = false;
+        let mut in_double = false;
+        let mut rest = line;
+
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            match c {
+                '\\' if !in_single => {
+                    expanded.push(c);
+                    if let Some(next) = rest.chars().next() {
+                        expanded.push(next);
+                        rest = &rest[next.len_utf8()..];
+                    }
+                }
+                '\'' if !in_double => {
+                    in_single = !in_single;
+                    expanded.push(c);
+                }
+                '"' if !in_single => {
+                    in_double = !in_double;
+                    expanded.push(c);
+                }
+                '!' if !in_single => match self.event(rest, in_double)? {
+                    Some((command, used)) => {
+                        expanded.push_str(command);
+                        rest = &rest[used..];
+                        changed = true;
+                    }
+                    None => expanded.push(c),
+                },
+                _ => expanded.push(c),
+            }
+        }
+
+        Ok(changed.then_some(expanded))
+    }
+
+    /// Resolves the event after a `!`, returning the command it refers to
+    /// and how many bytes of `spec` it used.
+    fn event(&self, spec: &str, in_double: bool) -> Result<Option<(&str, usize)>, Error> {
+        let end = spec
+            .find(|c: char| c.is_whitespace() || ";&|<>()'`".contains(c) || (in_double && c == '"'))
+            .unwrap_or(spec.len());
+        let word = &spec[..end];
+
+        let (found, used) = if spec.starts_with('!') {
+            (self.entries.last(), 1)
+        } else if word.is_empty() || word.starts_with('=') {
+            return Ok(None);
+        } else if let Some(text) = word.strip_prefix('?') {
+            let text = text.strip_suffix('?').unwrap_or(text);
+            let found = self.entries.iter().rev().find(|e| e.command.contains(text));
+            (found, end)
+        } else if let Some(digits) = numeric_prefix(word) {
+            let number: i64 = word[..digits]
+                .parse()
+                .map_err(|_| anyhow!("!{}: event not found", word))?;
+            let index = if number < 0 {
+                self.entries
+                    .len()
+                    .checked_sub(number.unsigned_abs() as usize)
+            } else {
+                (number as usize).checked_sub(self.first_number)
+            };
+            (index.and_then(|index| self.entries.get(index)), digits)
+        } else {
+            let found = self
+                .entries
+                .iter()
+                .rev()
+                .find(|e| e.command.starts_with(word));
+            (found, end)
+        };
+
+        match found {
+            Some(entry) => Ok(Some((&entry.command, used))),
+            None => Err(anyhow!("!{}: event not found", &spec[..used.max(1)])),
+        }
+    }
+}
+
+/// The length of a leading `n` or `-n`, if `word` starts with one.
+fn numeric_prefix(word: &str) -> Option<usize> {
+    let sign = usize::from(word.starts_with('-'));
+    let digits = word[sign..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .count();
+    (digits > 0).then_some(sign + digits)
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HISTORY_SIZE;
+
+    fn entry(command: &str) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            timestamp: 1_700_000_000,
+            duration: Duration::from_millis(1500),
+            status: 0,
+            cwd: "/tmp".to_string(),
+        }
+    }
+
+    fn history(commands: &[&str]) -> History {
+        let mut history = History::in_memory(HISTORY_SIZE);
+        for command in commands {
+            history.record(entry(command));
+        }
+        history
+    }
+
+    #[test]
+    fn expands_events() {
+        let history = history(&["cargo build", "git status", "cargo test -p dsh"]);
+        let expand = |line: &str| history.expand(line).unwrap();
+
+        assert_eq!(expand("sudo !!"), Some("sudo cargo test -p dsh".into()));
+        assert_eq!(expand("!1 --release"), Some("cargo build --release".into()));
+        assert_eq!(expand("!-2"), Some("git status".into()));
+        assert_eq!(
+            expand("!git; !?build?"),
+            Some("git status; cargo build".into())
+        );
+        assert_eq!(
+            expand("echo \"!!\""),
+            Some("echo \"cargo test -p dsh\"".into())
+        );
+        assert_eq!(expand("echo '!!' \\!! ! != !"), None);
+        assert!(history.expand("!nope").is_err());
+        assert!(history.expand("!9").is_err());
+    }
+
+    #[test]
+    fn collapses_repeated_commands() {
+        let history = history(&["ls", "ls", " secret", "pwd", "ls"]);
+        let commands: Vec<&str> = history
+            .entries()
+            .iter()
+            .map(|e| e.command.as_str())
+            .collect();
+        assert_eq!(commands, vec!["ls", "pwd", "ls"]);
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let dir = env::temp_dir().join(format!("dsh-history-test-{}", std::process::id()));
+        let path = dir.join("history");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut history = History::open(&path, HISTORY_SIZE).unwrap();
+        history.record(entry("echo 'a\tb'\nprintf '\\n'"));
+        history.record(entry("ls"));
+        history.record(entry("ls"));
+
+        let reloaded = History::open(&path, HISTORY_SIZE).unwrap();
+        assert_eq!(reloaded.entries(), history.entries());
+        assert_eq!(reloaded.entries().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
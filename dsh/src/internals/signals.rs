@@ -0,0 +1,64 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+use super::jobs::Pid;
+
+/// Process group of the job running in the foreground, or 0 while the shell
+/// itself is.
+static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+
+static INTERRUPT: Notify = Notify::const_new();
+
+/// Set by SIGINT while no job is in the foreground, until a loop notices.
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Records which process group SIGINT and SIGQUIT should go to.
+pub fn set_foreground(pgid: Option<Pid>) {
+    FOREGROUND.store(pgid.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// Catches SIGINT and SIGQUIT so they never kill the shell, and passes them
+/// on to the foreground job. Ctrl+C normally reaches the job straight from
+/// the terminal; this covers signals sent to the shell itself.
+///
+/// While the shell is busy on its own, e.g. streaming an AI answer, SIGINT
+/// wakes [`interrupted`] instead.
+pub fn listen() -> io::Result<()> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut quit = signal(SignalKind::quit())?;
+
+    tokio::spawn(async move {
+        loop {
+            let signal = tokio::select! {
+                Some(()) = interrupt.recv() => libc::SIGINT,
+                Some(()) = quit.recv() => libc::SIGQUIT,
+                else => break,
+            };
+
+            let pgid = FOREGROUND.load(Ordering::SeqCst);
+            if pgid > 0 {
+                unsafe { libc::kill(-pgid, signal) };
+            } else if signal == libc::SIGINT {
+                PENDING.store(true, Ordering::SeqCst);
+                INTERRUPT.notify_waiters();
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Resolves on the next SIGINT that reaches the shell while no job is in
+/// the foreground.
+pub async fn interrupted() {
+    INTERRUPT.notified().await
+}
+
+/// Whether SIGINT reached the shell, with no job in the foreground, since
+/// the last call. Loops check it so that one running only builtins can
+/// still be stopped.
+pub fn take_interrupt() -> bool {
+    PENDING.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::os::unix::process::ExitStatusExt;
+
+/// How a command finished, in the terms the shell cares about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The process exited on its own with this code.
+    Exited(i32),
+    /// The process was killed by this signal.
+    Signaled(i32),
+    /// The process was stopped by this signal and can still be resumed.
+    Stopped(i32),
+}
+
+impl ExitStatus {
+    pub const SUCCESS: ExitStatus = ExitStatus::Exited(0);
+    pub const FAILURE: ExitStatus = ExitStatus::Exited(1);
+    /// Reported when a program can't be found on `$PATH`.
+    pub const NOT_FOUND: ExitStatus = ExitStatus::Exited(127);
+    /// Reported when a program was found but couldn't be executed.
+    pub const NOT_EXECUTABLE: ExitStatus = ExitStatus::Exited(126);
+    /// A command interrupted with Ctrl+C, reported as 130.
+    pub const INTERRUPTED: ExitStatus = ExitStatus::Signaled(libc::SIGINT);
+
+    pub fn success(&self) -> bool {
+        *self == Self::SUCCESS
+    }
+
+    /// The value reported through `$?`: the exit code, or 128 plus the
+    /// signal number for a killed or stopped process.
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitStatus::Exited(code) => *code,
+            ExitStatus::Signaled(signal) | ExitStatus::Stopped(signal) => 128 + signal,
+        }
+    }
+
+    /// Decodes a status as filled in by `waitpid(2)`.
+    pub fn from_wait(raw: libc::c_int) -> Self {
+        if libc::WIFEXITED(raw) {
+            ExitStatus::Exited(libc::WEXITSTATUS(raw))
+        } else if libc::WIFSIGNALED(raw) {
+            ExitStatus::Signaled(libc::WTERMSIG(raw))
+        } else if libc::WIFSTOPPED(raw) {
+            ExitStatus::Stopped(libc::WSTOPSIG(raw))
+        } else {
+            ExitStatus::FAILURE
+        }
+    }
+}
+
+impl From<std::process::ExitStatus> for ExitStatus {
+    fn from(status: std::process::ExitStatus) -> Self {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => ExitStatus::Exited(code),
+            (None, Some(signal)) => ExitStatus::Signaled(signal),
+            (None, None) => ExitStatus::FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exit code {}", code),
+            ExitStatus::Signaled(signal) => write!(f, "signal {}", signal),
+            ExitStatus::Stopped(signal) => write!(f, "stopped by signal {}", signal),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Copies of the shell made with `fork`, for builtins, functions and
+//! compound commands that run in a pipeline or in the background, and for
+//! the commands of a `$(...)`. Nothing they change reaches the shell.
+
+use std::future::Future;
+use std::io::{self, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+use super::jobs::{Group, Pid};
+use super::redirect::{FdTable, TeeEnd};
+use super::shell::ShellState;
+use super::status::ExitStatus;
+
+/// Which side of a [`fork`] the code is running on.
+pub enum Fork {
+    /// The shell, with the child's pid and the tees to copy its output.
+    Parent { pid: Pid, tees: Vec<TeeEnd> },
+    /// The copy, which has to end with [`exit`].
+    Child,
+}
+
+/// Forks the shell, with the child's descriptors pointed at `fds` and the
+/// child in `group`.
+pub fn fork(state: &mut ShellState, fds: &FdTable, group: Group) -> io::Result<Fork> {
+    let (files, tees) = fds.resolve()?;
+    // Whatever is still buffered would be written twice.
+    io::stdout().flush()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            state.jobs.enter(group);
+            // The shell ignores these and handles SIGINT itself, on threads
+            // the copy doesn't have; in the copy they end it, as they would
+            // any other command.
+            for signal in [libc::SIGINT, libc::SIGQUIT, libc::SIGPIPE] {
+                unsafe { libc::signal(signal, libc::SIG_DFL) };
+            }
+            if let Err(err) = files.install() {
+                report!("{}", err);
+                process::exit(ExitStatus::FAILURE.code());
+            }
+            // Their pipes were closed by `install`.
+            mem::forget(files);
+            mem::forget(tees);
+            // A client of the engine may be tied to the shell's runtime.
+            mem::forget(state.engine.take());
+            state.engine = state.open_engine().unwrap_or(None);
+            Ok(Fork::Child)
+        }
+        pid => Ok(Fork::Parent { pid, tees }),
+    }
+}
+
+/// Runs `future` in a forked child. The runtime the child was forked from
+/// lost its threads in the fork, so this one gets a runtime of its own.
+///
+/// It runs on the thread that forked, the only one whose stack survived:
+/// anything borrowed from the stack of another thread is memory a thread
+/// started later may be given.
+pub fn run(future: impl Future<Output = ExitStatus>) -> ExitStatus {
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            report!("{}", err);
+            return ExitStatus::FAILURE;
+        }
+    };
+    // A panic must not unwind into the frames the child copied from the
+    // shell, which would carry on as if it were the shell.
+    let status = panic::catch_unwind(AssertUnwindSafe(|| {
+        tokio::task::block_in_place(|| runtime.block_on(future))
+    }))
+    .unwrap_or(ExitStatus::FAILURE);
+    // Shutting it down would wait for threads that may still be reading
+    // from pipes of jobs the child left running.
+    mem::forget(runtime);
+    status
+}
+
+/// Waits for a forked child that isn't part of a job.
+pub fn wait(pid: Pid) -> io::Result<ExitStatus> {
+    let mut raw = 0;
+    while unsafe { libc::waitpid(pid, &mut raw, 0) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    Ok(ExitStatus::from_wait(raw))
+}
+
+/// Ends a forked child with `status`, or with the one `exit` asked for.
+pub fn exit(state: &ShellState, status: ExitStatus) -> ! {
+    let _ = io::stdout().flush();
+    process::exit(state.exiting.unwrap_or(status.code()))
+}
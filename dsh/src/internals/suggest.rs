@@ -0,0 +1,59 @@
+//! `# request` lines: the AI engine turns a request in plain words into a
+//! command, which the user gets to check before it runs.
+
+use std::env;
+
+use anyhow::{anyhow, Result};
+
+use super::diagnosis::AiPolicy;
+use super::shell::ShellState;
+use super::signals;
+
+/// The request in a line like `# find all rust files larger than 1MB`.
+pub fn request(line: &str) -> Option<&str> {
+    let request = line.trim_start().strip_prefix('#')?.trim();
+    (!request.is_empty()).then_some(request)
+}
+
+/// Asks the AI engine for a command that does what `request` says.
+/// `None` means the user gave up on it with Ctrl+C.
+pub async fn suggest(state: &mut ShellState, request: &str) -> Result<Option<String>> {
+    let engine = match &mut state.engine {
+        Some(engine) if state.ai_policy != AiPolicy::Never => engine,
+        _ => return Err(anyhow!("AI assistance is turned off")),
+    };
+
+    let prompt = prompt(request);
+    tokio::select! {
+        result = ai_engine::suggest_command(engine.as_mut(), &prompt) => result.map(Some),
+        _ = signals::interrupted() => Ok(None),
+    }
+}
+
+/// The request, with what the model needs to know about where the command
+/// will run.
+fn prompt(request: &str) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    format!(
+        "{}\n\nThe command runs on {} in the directory {}.\n",
+        request,
+        env::consts::OS,
+        cwd.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_requests() {
+        assert_eq!(
+            request("# find all rust files larger than 1MB"),
+            Some("find all rust files larger than 1MB")
+        );
+        assert_eq!(request("  #list jobs  "), Some("list jobs"));
+        assert_eq!(request("#"), None);
+        assert_eq!(request("echo # not a request"), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use anyhow::{anyhow, Result};
+
+/// The shell's variables. Only exported ones reach the environment of the
+/// commands it runs; the rest stay in the shell.
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    vars: BTreeMap<String, Variable>,
+    /// `$1`, `$2`, ...
+    positional: Vec<String>,
+    /// `$0`.
+    arg0: String,
+    /// One frame per function call: the variables made `local` in it, with
+    /// what they were before, to put back when it returns.
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Variable {
+    value: String,
+    exported: bool,
+}
+
+impl Variables {
+    /// Starts out with the environment dsh was given, all of it exported.
+    pub fn from_env() -> Self {
+        let vars = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| is_name(name))
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value,
+                    exported: true,
+                };
+                (name, variable)
+            })
+            .collect();
+        Variables {
+            vars,
+            positional: Vec::new(),
+            arg0: env::args().next().unwrap_or_else(|| "dsh".to_string()),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|var| var.value.as_str())
+    }
+
+    /// Sets a variable, keeping it exported if it already was.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        if !is_name(name) {
+            return Err(anyhow!("`{}': not a valid identifier", name));
+        }
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                let var = Variable {
+                    value: value.to_string(),
+                    exported: false,
+                };
+                self.vars.insert(name.to_string(), var);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks a variable for export, setting it first when given a value.
+    /// Exporting a variable that isn't set sets it to the empty string.
+    pub fn export(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+        let value = match value {
+            Some(value) => value.to_string(),
+            None => self.get(name).unwrap_or_default().to_string(),
+        };
+        self.set(name, &value)?;
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = true;
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    /// Starts the scope of a function call.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Ends the scope of a function call, putting back every variable it
+    /// made local.
+    pub fn pop_scope(&mut self) {
+        let Some(saved) = self.scopes.pop() else {
+            return;
+        };
+        for (name, var) in saved.into_iter().rev() {
+            match var {
+                Some(var) => self.vars.insert(name, var),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+
+    /// Makes `name` local to the current function call, unset until it is
+    /// given a value. Outside a function there is no scope to make it
+    /// local to.
+    pub fn make_local(&mut self, name: &str) -> Result<()> {
+        if !is_name(name) {
+            return Err(anyhow!("`{}': not a valid identifier", name));
+        }
+        let Some(scope) = self.scopes.last_mut() else {
+            return Err(anyhow!("can only be used in a function"));
+        };
+        if !scope.iter().any(|(local, _)| local == name) {
+            scope.push((name.to_string(), self.vars.remove(name)));
+        }
+        Ok(())
+    }
+
+    /// Every variable's name, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.vars.keys().map(String::as_str)
+    }
+
+    /// What child processes get as their environment.
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    /// Replaces `$1`, `$2`, ..., returning the ones there were before.
+    pub fn set_positional(&mut self, args: Vec<String>) -> Vec<String> {
+        std::mem::replace(&mut self.positional, args)
+    }
+
+    pub fn arg0(&self) -> &str {
+        &self.arg0
+    }
+
+    pub fn set_arg0(&mut self, arg0: String) {
+        self.arg0 = arg0;
+    }
+}
+
+/// Whether `name` can name a variable: a letter or underscore, then
+/// letters, digits and underscores.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quotes `value` so that the shell reads it back as it is.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quotes `value` only if the shell would read it back differently
+/// without quotes.
+pub fn quote_if_needed(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:,+@%^=".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_exported_variables_reach_children() {
+        let mut vars = Variables::default();
+        vars.set("LOCAL", "1").unwrap();
+        vars.export("SHARED", Some("2")).unwrap();
+        vars.set("SHARED", "3").unwrap();
+        vars.export("LOCAL", None).unwrap();
+        vars.set("HIDDEN", "4").unwrap();
+
+        let exported: Vec<_> = vars.exported().collect();
+        assert_eq!(exported, vec![("LOCAL", "1"), ("SHARED", "3")]);
+        assert!(vars.set("1abc", "x").is_err());
+        vars.unset("LOCAL");
+        assert_eq!(vars.get("LOCAL"), None);
+    }
+
+    #[test]
+    fn locals_end_with_their_scope() {
+        let mut vars = Variables::default();
+        vars.export("X", Some("global")).unwrap();
+        assert!(vars.make_local("X").is_err());
+
+        vars.push_scope();
+        vars.make_local("X").unwrap();
+        assert_eq!(vars.get("X"), None);
+        vars.set("X", "local").unwrap();
+        vars.make_local("X").unwrap();
+        assert_eq!(vars.get("X"), Some("local"));
+        vars.make_local("NEW").unwrap();
+        vars.set("NEW", "1").unwrap();
+        vars.pop_scope();
+
+        assert_eq!(vars.get("X"), Some("global"));
+        assert_eq!(vars.exported().count(), 1);
+        assert_eq!(vars.get("NEW"), None);
+    }
+}
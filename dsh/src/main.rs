@@ -1,69 +1,224 @@
+/// Prints an error as `dsh: ...`, after the script and line it came from
+/// while a script is running.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        $crate::internals::exec::report(format_args!($($arg)*))
+    };
+}
+
+mod args;
 mod builtins;
+mod config;
+mod editor;
 mod internals;
-mod utils;
-
-use ai_engine::AIEngine;
-use internals::commands;
 
-use crossterm::{
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    ExecutableCommand,
-};
-use std::{
-    io::{self, Write},
-    process,
-};
+use args::{Args, Source};
+use config::Config;
+use editor::{EditMode, Editor, Input};
+use internals::completion::ShellCompleter;
+use internals::parser::ParseError;
+use internals::shell::{HistoryEntry, ShellState};
+use internals::status::ExitStatus;
+use internals::{exec, parser, prompt, redirect, signals, suggest};
 
-use utils::setup_workdir;
+use crossterm::style::Stylize;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::process;
+use std::time::SystemTime;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Setup a Ctrl+C handler to ignore the signal
-    ctrlc::set_handler(|| {}).expect("Error setting Ctrl+C handler");
+    let args = match Args::parse(env::args().collect()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("dsh: {}", err);
+            eprintln!("usage: {}", args::USAGE);
+            process::exit(2);
+        }
+    };
+    let interactive = args.is_interactive();
+    // A broken config is reported, and the defaults used instead.
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("dsh: {}", err);
+        Config::default()
+    });
+    let mut state = ShellState::new(interactive, config);
+    state.login = args.login;
+    state.options = args.options;
+    state.variables.set_arg0(args.arg0);
+    state.variables.set_positional(args.positional);
 
-    //  let mut error_output_map: HashMap<u32, String> = HashMap::new();
-    let mut ai_engine = AIEngine::default()?;
+    // Ctrl+C and Ctrl+\ are for the foreground job, never the shell itself
+    signals::listen()?;
+
+    if !args.norc {
+        run_startup_files(&mut state).await;
+        if let Some(code) = state.exiting {
+            process::exit(code);
+        }
+    }
+
+    match args.source {
+        Source::Command(command) => {
+            exec::run_script(&command, "-c", &mut state).await;
+        }
+        Source::Script(path) => match fs::read_to_string(&path) {
+            // A `#!` line is a comment to the shell.
+            Ok(script) => {
+                exec::run_script(&script, &path, &mut state).await;
+            }
+            Err(err) => {
+                let status = match err.kind() {
+                    io::ErrorKind::NotFound => ExitStatus::NOT_FOUND,
+                    _ => ExitStatus::NOT_EXECUTABLE,
+                };
+                eprintln!("dsh: {}", redirect::file_error(&path, err));
+                state.last_status = status;
+            }
+        },
+        Source::Stdin if !interactive => {
+            let lines = io::stdin().lock().lines().map_while(Result::ok);
+            exec::run_lines(lines, "stdin", &mut state).await;
+        }
+        Source::Stdin => interact(&mut state).await?,
+    }
+
+    // `exit`, `set -e` and the end of the input all end up here, with the
+    // history written and the line that exited recorded in it.
+    process::exit(state.exiting.unwrap_or(state.last_status.code()))
+}
+
+/// Sources `~/.dsh_profile` in a login shell, then `~/.dshrc` in an
+/// interactive one. Their mistakes are reported and startup carries on.
+async fn run_startup_files(state: &mut ShellState) {
+    let Some(home) = state.variables.get("HOME").filter(|home| !home.is_empty()) else {
+        return;
+    };
+    let home = PathBuf::from(home);
+    let files = [(state.login, ".dsh_profile"), (state.interactive, ".dshrc")];
+    for (wanted, name) in files {
+        if !wanted {
+            continue;
+        }
+        let path = home.join(name).to_string_lossy().into_owned();
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                builtins::source::run_sourced(&source, &path, state).await;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("dsh: {}", redirect::file_error(&path, err)),
+        }
+        if state.exiting.is_some() {
+            return;
+        }
+    }
+}
+
+/// Reads commands at the prompt until `exit` or Ctrl+D.
+async fn interact(state: &mut ShellState) -> anyhow::Result<()> {
+    let mut editor = Editor::new(EditMode::default());
+    let mut keybindings = None;
 
-    let mut workdir = setup_workdir();
     loop {
-        workdir = setup_workdir();
+        state.jobs.notify();
 
-        std::io::stdout()
-            .execute(SetForegroundColor(Color::Blue))?
-            .execute(Print(workdir?.as_str()))?
-            .execute(ResetColor)?;
-        io::stdout().flush().expect("Failed to flush stdout");
+        // `reload` may have changed them since the last prompt.
+        if keybindings.as_ref() != Some(&state.config.keybindings) {
+            let config = &state.config.keybindings;
+            editor.set_keymap(EditMode::from_env(config.mode), &config.bindings);
+            keybindings = Some(config.clone());
+        }
 
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
+        let config = &state.config.prompt;
+        let left = state.variables.get("PS1");
+        let prompt = prompt::render(
+            left.or(config.format.as_deref()).unwrap_or(prompt::PS1),
+            state,
+        );
+        let right = state.variables.get("RPS1");
+        let right = right.or(config.right_format.as_deref()).unwrap_or_default();
+        editor.set_right_prompt(&prompt::render(right, state));
+        let continuation = state.variables.get("PS2").unwrap_or("> ");
+        editor.set_continuation_prompt(&prompt::render(continuation, state));
+        let completer = ShellCompleter::new(&state.history, &state.completions, &state.variables);
+        let input = editor.read_line(&prompt, &state.history, &completer, |text| {
+            !matches!(parser::parse(text), Err(ParseError::Incomplete(_)))
+        })?;
+        let input = match input {
+            Input::Line(input) => input,
+            Input::Interrupted => {
+                state.last_status = ExitStatus::INTERRUPTED;
+                continue;
+            }
+            Input::Eof => break,
+        };
 
-        let input = input.trim();
-        if input.is_empty() {
+        let line = input.trim_end();
+        if line.trim().is_empty() {
             continue;
         }
 
-        let args: Vec<&str> = input.split_whitespace().collect();
-        match args[0] {
-            "cd" => {
-                builtins::cd::run(input);
+        let line = match state.history.expand(line) {
+            Ok(Some(expanded)) => {
+                println!("{}", expanded);
+                expanded
             }
-            "help" => {
-                todo!();
+            Ok(None) => line.to_string(),
+            Err(err) => {
+                eprintln!("dsh: {}", err);
+                continue;
             }
-            "exit" => process::exit(0),
-            _ => {
-                let commands: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
-
-                if commands.len() > 1 {
-                    // Handle pipes
-                    todo!()
-                } else {
-                    // Handle single commands
-                    commands::run_single_command(input, &mut ai_engine).await;
+        };
+
+        let cwd = env::current_dir().unwrap_or_default();
+        let started = SystemTime::now();
+
+        // `# request` asks for a command, which shows up at the next prompt
+        if let Some(request) = suggest::request(&line) {
+            let status = match suggest::suggest(state, request).await {
+                Ok(Some(command)) => {
+                    println!(
+                        "{}",
+                        "Enter runs the suggested command; edit it first or Ctrl+C to drop it."
+                            .dark_grey()
+                    );
+                    editor.preload(&command);
+                    ExitStatus::SUCCESS
+                }
+                Ok(None) => {
+                    println!("\n[Interrupted.]");
+                    ExitStatus::INTERRUPTED
                 }
+                Err(err) => {
+                    eprintln!("dsh: {}", err);
+                    ExitStatus::FAILURE
+                }
+            };
+            state.last_duration = started.elapsed().unwrap_or_default();
+            state
+                .history
+                .record(HistoryEntry::new(&line, &cwd, started, status));
+            continue;
+        }
+
+        let status = match parser::parse_with_aliases(&line, &state.aliases) {
+            Ok(program) => exec::run_program(&program, state).await,
+            Err(err) => {
+                eprintln!("dsh: {}", err);
+                state.last_status = ExitStatus::Exited(2);
+                state.last_status
             }
+        };
+        state.last_duration = started.elapsed().unwrap_or_default();
+        state
+            .history
+            .record(HistoryEntry::new(&line, &cwd, started, status));
+        if state.exiting.is_some() {
+            break;
         }
     }
+    Ok(())
 }